@@ -0,0 +1,67 @@
+//! Benchmarks parsing of multi-megabyte tables, sequentially and in parallel.
+//!
+//! Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+/// Generates a table of persons with at least `bytes` bytes of text.
+fn table(bytes: usize) -> String {
+    let mut text = String::from("[\n");
+    let mut i = 0;
+    while text.len() < bytes {
+        text.push_str(&format!("  [\"Person {}\", \"Duck\", {}, {}, Option::Some(\"n{}\")],\n",
+            i, i % 150, i % 2 == 0, i % 7));
+        i += 1;
+    }
+    text.push_str("]\n");
+    text
+}
+
+/// Runs a function a number of times, returning the fastest time.
+fn time<T>(runs: usize, mut f: impl FnMut() -> T) -> Duration {
+    (0..runs).map(|_| {
+        let start = Instant::now();
+        std::hint::black_box(f());
+        start.elapsed()
+    }).min().unwrap()
+}
+
+fn report(name: &str, text: &str, d: Duration) {
+    let mb = text.len() as f64 / 1_000_000.0;
+    println!("{:<24} {:>6.1} MB {:>10.1} ms {:>8.1} MB/s",
+        name, mb, d.as_secs_f64() * 1000.0, mb / d.as_secs_f64());
+}
+
+fn main() {
+    for bytes in [1_000_000, 4_000_000, 16_000_000] {
+        let text = table(bytes);
+        report("arena::parse", &text, time(5, || iknow::arena::parse(&text).unwrap()));
+        report("arena::parse + to_root", &text,
+            time(5, || iknow::arena::parse(&text).unwrap().root().to_root()));
+        // The grammar based parser is much slower, so it only runs once on the smallest table.
+        if bytes == 1_000_000 {
+            let mut root = None;
+            report("parsing::parse_str", &text,
+                time(1, || root = Some(iknow::parsing::parse_str(&text, &[]).unwrap())));
+            assert_eq!(root.unwrap(), iknow::arena::parse(&text).unwrap().root().to_root());
+
+            let mut chunked = None;
+            report("batch::parse_chunked", &text,
+                time(1, || chunked = Some(iknow::batch::parse_chunked(&text, 64 * 1024, 0).unwrap())));
+            assert_eq!(chunked.unwrap(), iknow::arena::parse(&text).unwrap().root().to_root());
+        }
+    }
+
+    // Separate documents parsed on every core, which share strings through the global interner.
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let docs: Vec<String> = (0..threads).map(|_| table(250_000)).collect();
+    let all = docs.concat();
+    report(&format!("parsing::parse_str x {}", threads), &all, time(1, || {
+        std::thread::scope(|s| {
+            let handles: Vec<_> = docs.iter()
+                .map(|doc| s.spawn(move || iknow::parsing::parse_str(doc, &[]).unwrap()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect::<Vec<_>>()
+        })
+    }));
+}
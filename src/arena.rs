@@ -0,0 +1,454 @@
+//! Arena representation of data, borrowing strings from the source text.
+//!
+//! Nodes are stored in one vector and refer to each other by `NodeId`,
+//! such that parsing a document allocates a few growing vectors
+//! instead of a `Box` or `Vec` per node.
+//! Strings without escapes are slices of the source text.
+//!
+//! The hand-written parser reads data, i.e. tables and instances:
+//!
+//! ```text
+//! [
+//!   ["Donald", "Duck"],
+//!   (ins 0 [first_name: "Dolly", last_name: "Duck"]),
+//!   (ins 1 [(ref 3), (ref 4), EdgeDir::Left]),
+//!   Str("Scrooge"),
+//! ]
+//! ```
+//!
+//! Declarations and types, e.g. `struct Person {..}` or `Arc<String>`,
+//! are parsed with `parsing` instead.
+//! Use `RootRef::to_root` to convert data into `Root`.
+
+use crate::*;
+
+use std::borrow::Cow;
+
+/// The index of a node in an arena.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeId(u32);
+
+/// A node in an arena, with the same meaning as the `Root` variant of the same name.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node<'a> {
+    /// A type, e.g. `Option` in `Option::None`.
+    Ty(&'a str),
+    /// A string, borrowed from the source unless it has escapes.
+    Str(Cow<'a, str>),
+    /// A number.
+    F64(f64),
+    /// A boolean.
+    Bool(bool),
+    /// An avatar, e.g. `Str("Donald")`.
+    Avatar(NodeId, NodeId),
+    /// A named field, e.g. `first_name: "Donald"`.
+    Field(NodeId, NodeId),
+    /// A tuple, with items stored in the arena from `start`.
+    Tup {
+        /// Index of the first item among the items of arena.
+        start: u32,
+        /// Number of items.
+        len: u32,
+    },
+    /// An instance, e.g. `(ins 0 ["Donald", "Duck"])`.
+    Instance {
+        /// Index of declaration.
+        class: usize,
+        /// Data of instance, if any.
+        data: Option<NodeId>,
+    },
+    /// An instance with explicit type, e.g. `Option::None`.
+    InstanceTy {
+        /// Type of instance.
+        ty: NodeId,
+        /// Data of instance, if any.
+        data: Option<NodeId>,
+    },
+    /// A reference to an instance, e.g. `(ref 3)`.
+    Ref(usize),
+}
+
+/// Nodes of a document, borrowing from the source text.
+#[derive(Clone, Debug, Default)]
+pub struct Arena<'a> {
+    nodes: Vec<Node<'a>>,
+    /// Items of tuples.
+    items: Vec<NodeId>,
+}
+
+impl<'a> Arena<'a> {
+    fn push(&mut self, node: Node<'a>) -> NodeId {
+        self.nodes.push(node);
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+
+    /// Returns a node.
+    pub fn node(&self, id: NodeId) -> &Node<'a> {&self.nodes[id.0 as usize]}
+
+    /// Returns the number of nodes.
+    pub fn len(&self) -> usize {self.nodes.len()}
+
+    /// Returns `true` if there are no nodes.
+    pub fn is_empty(&self) -> bool {self.nodes.is_empty()}
+
+    /// Returns a reference to a node.
+    pub fn get(&self, id: NodeId) -> RootRef<'_> {RootRef {arena: self, id}}
+}
+
+/// A parsed document, with the arena and the id of its root node.
+#[derive(Clone, Debug)]
+pub struct Document<'a> {
+    /// Nodes of document.
+    pub arena: Arena<'a>,
+    /// The root node.
+    pub root: NodeId,
+}
+
+impl Document<'_> {
+    /// Returns a reference to the root node.
+    pub fn root(&self) -> RootRef<'_> {self.arena.get(self.root)}
+}
+
+/// A reference to a node in an arena.
+#[derive(Clone, Copy, Debug)]
+pub struct RootRef<'a> {
+    arena: &'a Arena<'a>,
+    id: NodeId,
+}
+
+impl<'a> RootRef<'a> {
+    /// Returns the node.
+    pub fn node(&self) -> &'a Node<'a> {self.arena.node(self.id)}
+
+    /// Returns the id of the node.
+    pub fn id(&self) -> NodeId {self.id}
+
+    /// Returns the string of `Str` or `Ty`.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self.node() {
+            Node::Str(s) => Some(s),
+            Node::Ty(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the items of a tuple.
+    pub fn items(&self) -> impl Iterator<Item = RootRef<'a>> + 'a {
+        let arena = self.arena;
+        let ids: &'a [NodeId] = match self.node() {
+            Node::Tup {start, len} => &arena.items[*start as usize..(*start + *len) as usize],
+            _ => &[],
+        };
+        ids.iter().map(move |&id| RootRef {arena, id})
+    }
+
+    /// Returns the child at an index, using the same order as `Root::child`.
+    pub fn child(&self, ind: usize) -> Option<RootRef<'a>> {
+        let id = match *self.node() {
+            Node::Avatar(a, b) | Node::Field(a, b) => match ind {0 => a, 1 => b, _ => return None},
+            Node::Tup {start, len} => if ind < len as usize {
+                self.arena.items[start as usize + ind]
+            } else {return None},
+            Node::Instance {data, ..} => if ind == 0 {data?} else {return None},
+            Node::InstanceTy {ty, data} => match ind {0 => ty, 1 => data?, _ => return None},
+            Node::Ty(_) | Node::Str(_) | Node::F64(_) | Node::Bool(_) | Node::Ref(_) => return None,
+        };
+        Some(RootRef {arena: self.arena, id})
+    }
+
+    /// Converts into `Root`, interning strings.
+    ///
+    /// Strings are shared with the global interner once for the whole node, see `symbol::share_root`.
+    pub fn to_root(&self) -> Root {
+        let mut root = self.convert();
+        symbol::share_root(&mut root);
+        root
+    }
+
+    fn convert(&self) -> Root {
+        let get = |id: NodeId| Box::new(self.arena.get(id).convert());
+        match *self.node() {
+            Node::Ty(s) => Root::Ty(Arc::new(s.into())),
+            Node::Str(ref s) => Root::Str(Arc::new(s.to_string())),
+            Node::F64(x) => Root::F64(x),
+            Node::Bool(x) => Root::Bool(x),
+            Node::Avatar(a, b) => Root::Avatar(Box::new((*get(a), *get(b)))),
+            Node::Field(a, b) => Root::Field(Box::new((*get(a), *get(b)))),
+            Node::Tup {..} => Root::Tup(self.items().map(|it| it.convert()).collect()),
+            Node::Instance {class, data} => Root::Instance {class, data: data.map(get)},
+            Node::InstanceTy {ty, data} => Root::InstanceTy {ty: get(ty), data: data.map(get)},
+            Node::Ref(ind) => Root::Ref(ind),
+        }
+    }
+}
+
+/// Parses data into an arena.
+pub fn parse(src: &str) -> Result<Document<'_>, String> {
+    let mut parser = Parser {src, pos: 0, arena: Arena::default(), stack: vec![]};
+    parser.ws();
+    let root = parser.expr()?;
+    parser.ws();
+    if parser.pos < src.len() {return Err(parser.error("Expected end of document"))}
+    Ok(Document {arena: parser.arena, root})
+}
+
+/// Characters that end a name, as in the grammar.
+const SEPS: &str = "(){}<>[],:.=\"";
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    arena: Arena<'a>,
+    /// Items of tuples being parsed.
+    stack: Vec<NodeId>,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {&self.src[self.pos..]}
+
+    fn error(&self, msg: &str) -> String {
+        let line = self.src[..self.pos].matches('\n').count() + 1;
+        let col = self.pos - self.src[..self.pos].rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+        let found: String = self.rest().chars().take(20).collect();
+        format!("{}:{}: {}, found `{}`", line, col, msg, found)
+    }
+
+    fn ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {false}
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {Ok(())} else {Err(self.error(&format!("Expected `{}`", token)))}
+    }
+
+    /// Reads a keyword followed by whitespace, e.g. `(ins `.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let rest = self.rest();
+        if rest.starts_with(keyword) &&
+            rest[keyword.len()..].starts_with(|c: char| c.is_whitespace()) {
+            self.pos += keyword.len();
+            self.ws();
+            true
+        } else {false}
+    }
+
+    /// Reads a name, ending at whitespace or a separator.
+    fn name(&mut self) -> &'a str {
+        let rest = self.rest();
+        let end = rest.find(|c: char| c.is_whitespace() || SEPS.contains(c)).unwrap_or(rest.len());
+        self.pos += end;
+        &rest[..end]
+    }
+
+    fn usize(&mut self) -> Result<usize, String> {
+        let rest = self.rest();
+        let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let x = rest[..end].parse().map_err(|_| self.error("Expected integer"))?;
+        self.pos += end;
+        Ok(x)
+    }
+
+    fn expr(&mut self) -> Result<NodeId, String> {
+        let left = self.expr_left()?;
+        let start = self.pos;
+        self.ws();
+        if self.rest().starts_with(':') && !self.rest().starts_with("::") {
+            self.pos += 1;
+            self.ws();
+            let value = self.expr()?;
+            return Ok(self.arena.push(Node::Field(left, value)));
+        }
+        self.pos = start;
+        Ok(left)
+    }
+
+    fn expr_left(&mut self) -> Result<NodeId, String> {
+        let rest = self.rest();
+        if self.eat("[") {
+            self.items(",", "]")
+        } else if self.keyword("(tup") {
+            self.items("", ")")
+        } else if self.keyword("(ins") {
+            let class = self.usize()?;
+            self.ws();
+            let data = if self.rest().starts_with(')') {None} else {Some(self.expr()?)};
+            self.ws();
+            self.expect(")")?;
+            Ok(self.arena.push(Node::Instance {class, data}))
+        } else if self.keyword("(ref") {
+            let ind = self.usize()?;
+            self.ws();
+            self.expect(")")?;
+            Ok(self.arena.push(Node::Ref(ind)))
+        } else if self.keyword("(field") || self.keyword("(ava") {
+            let field = rest.starts_with("(field");
+            let a = self.expr()?;
+            self.ws();
+            let b = self.expr()?;
+            self.ws();
+            self.expect(")")?;
+            Ok(self.arena.push(if field {Node::Field(a, b)} else {Node::Avatar(a, b)}))
+        } else if self.eat("(") {
+            self.items(",", ")")
+        } else if rest.starts_with('"') {
+            let s = self.string()?;
+            Ok(self.arena.push(Node::Str(s)))
+        } else if rest.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.') {
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || "+-._".contains(c)))
+                .unwrap_or(rest.len());
+            let num = &rest[..end];
+            let x = if num.contains('_') {num.replace('_', "").parse()} else {num.parse()};
+            let x = x.map_err(|_| self.error("Invalid number"))?;
+            self.pos += end;
+            Ok(self.arena.push(Node::F64(x)))
+        } else {
+            let name = self.name();
+            match name {
+                "" => Err(self.error("Expected expression")),
+                "true" => Ok(self.arena.push(Node::Bool(true))),
+                "false" => Ok(self.arena.push(Node::Bool(false))),
+                _ if self.eat("::") => {
+                    let ty = self.arena.push(Node::Ty(name));
+                    let data = self.name_or_expr()?;
+                    Ok(self.arena.push(Node::InstanceTy {ty, data: Some(data)}))
+                }
+                _ if self.eat("(") => {
+                    let a = self.arena.push(Node::Str(Cow::Borrowed(name)));
+                    self.ws();
+                    let b = self.expr()?;
+                    self.ws();
+                    self.expect(")")?;
+                    Ok(self.arena.push(Node::Avatar(a, b)))
+                }
+                _ => {
+                    // Names are only used as field keys, e.g. `first_name: "Donald"`.
+                    self.ws();
+                    if self.rest().starts_with(':') && !self.rest().starts_with("::") {
+                        Ok(self.arena.push(Node::Str(Cow::Borrowed(name))))
+                    } else {
+                        Err(format!("Unsupported name `{}` in data, use `parsing` for declarations", name))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads data of a variant, e.g. `None` or `Some(3)` in `Option::Some(3)`.
+    fn name_or_expr(&mut self) -> Result<NodeId, String> {
+        let start = self.pos;
+        let name = self.name();
+        if !name.is_empty() && !self.rest().starts_with('(') && !self.rest().starts_with("::") &&
+           name != "true" && name != "false" && !name.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(self.arena.push(Node::Str(Cow::Borrowed(name))));
+        }
+        self.pos = start;
+        self.expr()
+    }
+
+    /// Reads items of a tuple up to `end`, separated by `sep` or whitespace if empty.
+    fn items(&mut self, sep: &str, end: &str) -> Result<NodeId, String> {
+        let base = self.stack.len();
+        self.ws();
+        while !self.eat(end) {
+            let item = self.expr()?;
+            self.stack.push(item);
+            self.ws();
+            if !sep.is_empty() && !self.eat(sep) {
+                self.expect(end)?;
+                break;
+            }
+            self.ws();
+        }
+        let start = self.arena.items.len() as u32;
+        self.arena.items.extend(self.stack.drain(base..));
+        let len = self.arena.items.len() as u32 - start;
+        Ok(self.arena.push(Node::Tup {start, len}))
+    }
+
+    /// Reads a quoted string, borrowing from the source if it has no escapes.
+    fn string(&mut self) -> Result<Cow<'a, str>, String> {
+        let rest = &self.rest()[1..];
+        let end = rest.find(['"', '\\']).ok_or_else(|| self.error("Unterminated string"))?;
+        if rest[end..].starts_with('"') {
+            self.pos += end + 2;
+            return Ok(Cow::Borrowed(&rest[..end]));
+        }
+        let mut res = String::from(&rest[..end]);
+        let mut chars = rest[end..].char_indices();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    self.pos += end + i + 2;
+                    return Ok(Cow::Owned(res));
+                }
+                '\\' => match chars.next().map(|(_, ch)| ch) {
+                    Some('n') => res.push('\n'),
+                    Some('r') => res.push('\r'),
+                    Some('t') => res.push('\t'),
+                    Some('b') => res.push('\u{8}'),
+                    Some('f') => res.push('\u{c}'),
+                    Some('0') => res.push('\0'),
+                    Some('u') => {
+                        let hex: String = if rest[end..].get(i + 2..).map(|s| s.starts_with('{')) == Some(true) {
+                            chars.by_ref().skip(1).map(|(_, c)| c).take_while(|&c| c != '}').collect()
+                        } else {
+                            chars.by_ref().take(4).map(|(_, c)| c).collect()
+                        };
+                        res.push(u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+                            .ok_or_else(|| self.error("Invalid unicode escape"))?);
+                    }
+                    Some(ch) => res.push(ch),
+                    None => break,
+                },
+                ch => res.push(ch),
+            }
+        }
+        Err(self.error("Unterminated string"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(src: &str) {
+        let doc = parse(src).unwrap();
+        assert_eq!(doc.root().to_root(), parsing::parse_str(src, &[]).unwrap(), "{}", src);
+    }
+
+    #[test]
+    fn test_parse() {
+        check(include_str!("../source/test/person/table-data.txt"));
+        check(include_str!("../source/test/graph/edge-data.txt"));
+        check(r#"[
+            (ins 0 [first_name: "Dolly", last_name: "Duck"]),
+            (ins 1 [(ref 3), (ref 4), EdgeDir::Left]),
+            (tup 1 -2.5 1_000 true),
+            Str("Scrooge"),
+            Option::Some(Foo((1, 2))),
+            (ins 2),
+            "a\"b\\c",
+            "",
+            (),
+        ]"#);
+
+        let doc = parse(r#"[["Donald", "Duck"], ["Dolly", "Du\"ck"]]"#).unwrap();
+        let rows: Vec<RootRef> = doc.root().items().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(rows[0].child(1).unwrap().node(), Node::Str(Cow::Borrowed("Duck"))));
+        assert!(matches!(rows[1].child(1).unwrap().node(), Node::Str(Cow::Owned(s)) if s == "Du\"ck"));
+        assert_eq!(doc.arena.len(), 7);
+
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("struct Person {name: Arc<String>}").is_err());
+        assert_eq!(parse("[1, x]").unwrap_err(), "Unsupported name `x` in data, use `parsing` for declarations");
+    }
+}
@@ -0,0 +1,251 @@
+//! Parallel parsing and validation of many files, or of one large document.
+//!
+//! Work is spread over a number of threads, which share one compiled grammar.
+//! Files are listed recursively from a directory, e.g. `source/test`,
+//! and every file is parsed and validated independently.
+//! Files with data only, e.g. tables, are parsed but not validated.
+//! The report lists the result of every file, in the order of paths,
+//! with errors aggregated per file.
+//!
+//! A large document with a top-level tuple, e.g. a table with millions of rows,
+//! is split into chunks of whole items, which are parsed in parallel and joined.
+
+use crate::*;
+use crate::parsing::Parser;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// The result of parsing and validating a file.
+#[derive(Clone, Debug)]
+pub struct FileReport {
+    /// Path of file.
+    pub path: PathBuf,
+    /// The parsed document, if parsing succeeded.
+    pub doc: Option<Root>,
+    /// Errors from parsing or validation.
+    pub errors: Vec<String>,
+}
+
+/// The results of parsing and validating many files.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Results of files, ordered by path.
+    pub files: Vec<FileReport>,
+}
+
+impl Report {
+    /// Returns `true` if there are no errors.
+    pub fn is_ok(&self) -> bool {
+        self.files.iter().all(|f| f.errors.is_empty())
+    }
+
+    /// Returns all errors with the path of their file.
+    pub fn errors(&self) -> impl Iterator<Item = (&Path, &str)> {
+        self.files.iter().flat_map(|f| f.errors.iter().map(move |e| (&*f.path, e.as_str())))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, err) in self.errors() {
+            for line in err.lines() {
+                writeln!(w, "{}: {}", path.display(), line)?;
+            }
+        }
+        let failed = self.files.iter().filter(|f| !f.errors.is_empty()).count();
+        write!(w, "{} file(s), {} with errors", self.files.len(), failed)
+    }
+}
+
+/// Returns the number of threads to use when `threads` is zero.
+fn threads_or_default(threads: usize) -> usize {
+    if threads > 0 {return threads}
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Runs a function on every item using a number of threads, keeping the order of items.
+fn run_parallel<T: Sync, U: Send>(items: &[T], threads: usize, f: impl Fn(&T) -> U + Sync) -> Vec<U> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, U)>> = Mutex::new(Vec::with_capacity(items.len()));
+    std::thread::scope(|s| {
+        for _ in 0..threads_or_default(threads).min(items.len()) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= items.len() {break}
+                let res = f(&items[i]);
+                results.lock().unwrap().push((i, res));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, res)| res).collect()
+}
+
+/// Lists files with an extension in a directory and its subdirectories, sorted by path.
+pub fn files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>, String> {
+    fn walk(dir: &Path, extension: &str, res: &mut Vec<PathBuf>) -> Result<(), String> {
+        let entries = std::fs::read_dir(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        for entry in entries {
+            let path = entry.map_err(|err| format!("{}: {}", dir.display(), err))?.path();
+            if path.is_dir() {
+                walk(&path, extension, res)?;
+            } else if path.extension().map(|e| e == extension).unwrap_or(false) {
+                res.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    let mut res = vec![];
+    walk(dir, extension, &mut res)?;
+    res.sort();
+    Ok(res)
+}
+
+/// Returns `true` if a document is a declaration or contains declarations.
+///
+/// Data without declarations, e.g. a table, is validated against a format in another file.
+fn has_declarations(doc: &Root) -> bool {
+    match doc {
+        Root::Struct {..} | Root::Enum {..} => true,
+        Root::Tup(items) => items.iter().any(|it| matches!(it, Root::Struct {..} | Root::Enum {..})),
+        _ => false,
+    }
+}
+
+/// Parses and validates files in parallel.
+///
+/// Files without declarations are only parsed.
+/// Uses all available cores when `threads` is zero.
+pub fn check_files(paths: &[PathBuf], threads: usize) -> Report {
+    let parser = Parser::shared();
+    let files = run_parallel(paths, threads, |path| {
+        let mut report = FileReport {path: path.clone(), doc: None, errors: vec![]};
+        match parser.parse(&path.to_string_lossy(), &[]) {
+            Ok(doc) => {
+                if has_declarations(&doc) {
+                    if let Err(err) = validate::validate_document(&doc) {report.errors.push(err)}
+                }
+                report.doc = Some(doc);
+            }
+            Err(err) => report.errors.push(err),
+        }
+        report
+    });
+    Report {files}
+}
+
+/// Parses and validates all `.txt` files in a directory and its subdirectories.
+pub fn check_dir(dir: &Path, threads: usize) -> Result<Report, String> {
+    Ok(check_files(&files(dir, "txt")?, threads))
+}
+
+/// Splits a document with a top-level tuple `[..]` into chunks of whole items.
+///
+/// Every chunk is a tuple with at least `chunk_size` bytes of items, except the last,
+/// returned with the line in the document where it starts.
+pub fn split(text: &str, chunk_size: usize) -> Result<Vec<(usize, String)>, String> {
+    let trimmed = text.trim_start();
+    let open = text.len() - trimmed.len();
+    if !trimmed.starts_with('[') {return Err("Expected top-level tuple starting with `[`".into())}
+    let mut chunks = vec![];
+    let mut depth = 0;
+    let mut in_str = false;
+    let mut escaped = false;
+    let mut start = open + 1;
+    let mut end = None;
+    for (i, ch) in text[open..].char_indices().map(|(i, ch)| (i + open, ch)) {
+        if in_str {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_str = false,
+                _ => {}
+            }
+            continue;
+        }
+        match ch {
+            '"' => in_str = true,
+            '[' | '(' | '{' | '<' => depth += 1,
+            ']' | ')' | '}' | '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(i);
+                    break;
+                }
+            }
+            ',' if depth == 1 && i + 1 - start >= chunk_size => {
+                chunks.push(start..i);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let end = end.ok_or("Expected `]` at end of top-level tuple")?;
+    if !text[end + 1..].trim().is_empty() {return Err("Unexpected text after top-level tuple".into())}
+    if !text[start..end].trim().is_empty() {chunks.push(start..end)}
+    Ok(chunks.into_iter().map(|r| {
+        let line = text[..r.start].matches('\n').count() + 1;
+        (line, format!("[{}]", &text[r]))
+    }).collect())
+}
+
+/// Parses a large document with a top-level tuple in parallel chunks.
+///
+/// Errors are reported for all chunks, one per line, with the line where the chunk starts.
+/// Uses all available cores when `threads` is zero.
+pub fn parse_chunked(text: &str, chunk_size: usize, threads: usize) -> Result<Root, String> {
+    let parser = Parser::shared();
+    let chunks = split(text, chunk_size)?;
+    let results = run_parallel(&chunks, threads, |(_, chunk)| parser.parse_str(chunk, &[]));
+    let mut items = vec![];
+    let mut errors = vec![];
+    for ((line, _), res) in chunks.iter().zip(results) {
+        match res {
+            Ok(Root::Tup(chunk)) => items.extend(chunk),
+            Ok(_) => errors.push(format!("Chunk at line {}: Expected tuple", line)),
+            Err(err) => errors.push(format!("Chunk at line {}: {}", line, err.replace('\n', " "))),
+        }
+    }
+    if errors.is_empty() {Ok(Root::Tup(items))} else {Err(errors.join("\n"))}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_dir() {
+        let report = check_dir(Path::new("source/test/person"), 4).unwrap();
+        assert_eq!(report.files.len(), 9);
+        assert!(report.files.windows(2).all(|w| w[0].path < w[1].path));
+        assert!(report.files.iter().all(|f| f.doc.is_some()));
+        let failed: Vec<&Path> = report.files.iter()
+            .filter(|f| !f.errors.is_empty()).map(|f| &*f.path).collect();
+        assert_eq!(failed, vec![
+            Path::new("source/test/person/meta-with-instances.txt"),
+            Path::new("source/test/person/person-constraints.txt"),
+        ]);
+        assert!(!report.is_ok());
+        assert!(format!("{}", report).ends_with("9 file(s), 2 with errors"));
+    }
+
+    #[test]
+    fn test_chunks() {
+        let text = include_str!("../source/test/graph/graph-with-refs.txt");
+        let chunks = split(text, 1).unwrap();
+        assert_eq!(chunks.len(), 8);
+        assert_eq!(chunks[3], (16, "[\n  (ins 0 Str(\"Donald\"))]".to_string()));
+        assert_eq!(parse_chunked(text, 40, 3).unwrap(), parsing::parse_str(text, &[]).unwrap());
+        assert_eq!(split("[]", 1).unwrap(), vec![]);
+
+        let err = parse_chunked("[\n  [1, 2],\n  [1, ?],\n  \"a, [b\",\n]", 1, 2).unwrap_err();
+        assert!(err.starts_with("Chunk at line 2: "), "{}", err);
+        assert!(split("[1, 2", 1).is_err());
+        assert!(split("(tup 1 2)", 1).is_err());
+    }
+}
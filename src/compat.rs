@@ -0,0 +1,608 @@
+//! Compatibility between versions of a format, and migration of data.
+//!
+//! Changes to declarations are classified by which data stays valid:
+//!
+//! - Adding a variant is backward compatible, since old data is valid in the new version
+//! - Removing a variant is forward compatible, since new data is valid in the old version
+//! - Adding a field at the end is backward compatible, if it has a default or an `Option` type,
+//!   since it may be omitted in data
+//! - Removing such a field at the end is forward compatible
+//! - Adding an attribute, e.g. `#[non_empty]`, is forward compatible, since it constrains data,
+//!   while removing one is backward compatible
+//! - Other changes to struct fields are breaking, since records are positional,
+//!   e.g. `["Donald", "Duck"]`
+//!
+//! Old instances can be rewritten to a new version with `migrate`,
+//! filling in added fields from rules, declared defaults or the default of their type.
+//! References `(ref N)` are renumbered to the new indices of instances.
+
+use crate::*;
+use crate::path::Path;
+use crate::schema::*;
+use crate::value::{Record, Value, Variant};
+
+use std::cell::Cell;
+
+/// Compatibility of a change between two versions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compat {
+    /// Data of either version is valid in the other version.
+    Full,
+    /// Old data is valid in the new version.
+    Backward,
+    /// New data is valid in the old version.
+    Forward,
+    /// Old data must be migrated.
+    Breaking,
+}
+
+impl Compat {
+    /// Combines two compatibilities, keeping what both allow.
+    pub fn and(self, other: Compat) -> Compat {
+        use Compat::*;
+
+        match (self, other) {
+            (Full, x) | (x, Full) => x,
+            (Backward, Backward) => Backward,
+            (Forward, Forward) => Forward,
+            _ => Breaking,
+        }
+    }
+}
+
+impl fmt::Display for Compat {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Compat::Full => write!(w, "full"),
+            Compat::Backward => write!(w, "backward"),
+            Compat::Forward => write!(w, "forward"),
+            Compat::Breaking => write!(w, "breaking"),
+        }
+    }
+}
+
+/// A change to a declaration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SchemaChange {
+    /// Location of change, e.g. `.Person.age`.
+    pub path: Path,
+    /// Description of change.
+    pub description: String,
+    /// Compatibility of change.
+    pub compat: Compat,
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(w, "{} {}: {}", self.compat, self.path, self.description)
+    }
+}
+
+/// Compares the declarations of two formats, matched by name.
+pub fn check(old: &Root, new: &Root) -> Result<Vec<SchemaChange>, String> {
+    let old = Schema::from_root(old)?;
+    let new = Schema::from_root(new)?;
+    let mut res = vec![];
+    for a in &old.defs {
+        match new.get(a.name()) {
+            Some(b) => res.extend(check_def(a, b)),
+            None => res.push(change(Path::root().name(a.name()),
+                "Removed declaration".into(), Compat::Breaking)),
+        }
+    }
+    for b in &new.defs {
+        if old.get(b.name()).is_none() {
+            res.push(change(Path::root().name(b.name()), "Added declaration".into(), Compat::Full));
+        }
+    }
+    Ok(res)
+}
+
+/// Compares two versions of a declaration.
+pub fn check_def(old: &Def, new: &Def) -> Vec<SchemaChange> {
+    let path = Path::root().name(new.name());
+    let mut res = vec![];
+    if old.params() != new.params() {
+        res.push(change(path.clone(), format!("Changed generic parameters from {} to {}",
+            params(old.params()), params(new.params())), Compat::Breaking));
+    }
+    match (old, new) {
+        (Def::Struct(a), Def::Struct(b)) => check_fields(&a.fields, &b.fields, &path, &mut res),
+        (Def::Enum(a), Def::Enum(b)) => {
+            for va in &a.variants {
+                let path = path.name(&va.name);
+                match b.variants.iter().find(|vb| vb.name == va.name) {
+                    Some(vb) => match (&va.data, &vb.data) {
+                        (VariantData::Unit, VariantData::Unit) => {}
+                        (VariantData::Type(ta), VariantData::Type(tb)) => check_ty(ta, tb, &path, &mut res),
+                        (VariantData::Fields(fa), VariantData::Fields(fb)) =>
+                            check_fields(fa, fb, &path, &mut res),
+                        _ => res.push(change(path, "Changed kind of variant".into(), Compat::Breaking)),
+                    },
+                    None => res.push(change(path, "Removed variant".into(), Compat::Forward)),
+                }
+            }
+            for vb in &b.variants {
+                if a.variants.iter().all(|va| va.name != vb.name) {
+                    res.push(change(path.name(&vb.name), "Added variant".into(), Compat::Backward));
+                }
+            }
+        }
+        _ => res.push(change(path, "Changed kind of declaration".into(), Compat::Breaking)),
+    }
+    res
+}
+
+/// Combines the compatibility of changes.
+pub fn level(changes: &[SchemaChange]) -> Compat {
+    changes.iter().fold(Compat::Full, |acc, ch| acc.and(ch.compat))
+}
+
+/// Returns the compatibility of a value of type `old` read as type `new`.
+///
+/// Named types are compared by name, since changes to their declarations are checked separately.
+pub fn ty_compat(old: &TypeRef, new: &TypeRef) -> Compat {
+    let (old, new) = (strip(old), strip(new));
+    if old == new {return Compat::Full}
+    match (old, new) {
+        (TypeRef::Primitive(a), TypeRef::Primitive(b)) => match (a.as_str(), b.as_str()) {
+            ("usize", "f64") => Compat::Backward,
+            ("f64", "usize") => Compat::Forward,
+            _ => Compat::Breaking,
+        },
+        (TypeRef::Applied(ba, aa), TypeRef::Applied(bb, ab)) if ba == bb && aa.len() == ab.len() =>
+            aa.iter().zip(ab).fold(Compat::Full, |acc, (a, b)| acc.and(ty_compat(a, b))),
+        (TypeRef::Tuple(aa), TypeRef::Tuple(ab)) if aa.len() == ab.len() =>
+            aa.iter().zip(ab).fold(Compat::Full, |acc, (a, b)| acc.and(ty_compat(a, b))),
+        // A value is read as `Option::Some`, but `Option::None` is not a value.
+        (_, TypeRef::Applied(b, args)) if is_option(b) => match ty_compat(old, &args[0]) {
+            Compat::Full | Compat::Backward => Compat::Backward,
+            _ => Compat::Breaking,
+        },
+        (TypeRef::Applied(a, args), _) if is_option(a) => match ty_compat(&args[0], new) {
+            Compat::Full | Compat::Forward => Compat::Forward,
+            _ => Compat::Breaking,
+        },
+        _ => Compat::Breaking,
+    }
+}
+
+fn check_fields(old: &[FieldDef], new: &[FieldDef], path: &Path, res: &mut Vec<SchemaChange>) {
+    for (i, fa) in old.iter().enumerate() {
+        match new.iter().find(|fb| fb.name == fa.name) {
+            Some(fb) => {
+                check_ty(&fa.ty, &fb.ty, &path.name(&fa.name), res);
+                if fa.default != fb.default {
+                    let show = |x: &Option<Root>| x.as_ref()
+                        .map(|x| format!("`{}`", x)).unwrap_or_else(|| "none".into());
+                    res.push(change(path.name(&fa.name), format!("Changed default from {} to {}",
+                        show(&fa.default), show(&fb.default)), Compat::Full));
+                }
+                for attr in fa.attrs.iter().filter(|x| !fb.attrs.contains(x)) {
+                    res.push(change(path.name(&fa.name), format!("Removed attribute `{}`", attr),
+                        Compat::Backward));
+                }
+                for attr in fb.attrs.iter().filter(|x| !fa.attrs.contains(x)) {
+                    res.push(change(path.name(&fa.name), format!("Added attribute `{}`", attr),
+                        Compat::Forward));
+                }
+            }
+            None => res.push(change(path.name(&fa.name), "Removed field".into(),
+                if i >= new.len() && fa.is_optional() {Compat::Forward} else {Compat::Breaking})),
+        }
+    }
+    for (i, fb) in new.iter().enumerate() {
+        if old.iter().all(|fa| fa.name != fb.name) {
+            res.push(change(path.name(&fb.name), format!("Added field of type `{}`", fb.ty),
+                if i >= old.len() && fb.is_optional() {Compat::Backward} else {Compat::Breaking}));
+        }
+    }
+    let common = |a: &[FieldDef], b: &[FieldDef]| -> Vec<Arc<String>> {
+        a.iter().filter(|f| b.iter().any(|g| g.name == f.name)).map(|f| f.name.clone()).collect()
+    };
+    if common(old, new) != common(new, old) {
+        res.push(change(path.clone(), "Reordered fields".into(), Compat::Breaking));
+    }
+}
+
+fn check_ty(old: &TypeRef, new: &TypeRef, path: &Path, res: &mut Vec<SchemaChange>) {
+    if old != new {
+        res.push(change(path.clone(), format!("Changed type from `{}` to `{}`", old, new),
+            ty_compat(old, new)));
+    }
+}
+
+fn change(path: Path, description: String, compat: Compat) -> SchemaChange {
+    SchemaChange {path, description, compat}
+}
+
+fn params(params: &[ParamDef]) -> String {
+    let names: Vec<String> = params.iter().map(|p| format!(".{}", p.name)).collect();
+    format!("<{}>", names.join(", "))
+}
+
+/// Removes `Arc` and `box`, which do not change how data is written.
+fn strip(ty: &TypeRef) -> &TypeRef {
+    match ty {
+        TypeRef::Applied(base, args) if args.len() == 1 && matches!(&**base,
+            TypeRef::Primitive(name) if name.as_str() == "Arc" || name.as_str() == "box") => strip(&args[0]),
+        _ => ty,
+    }
+}
+
+fn is_option(ty: &TypeRef) -> bool {
+    matches!(ty, TypeRef::Primitive(name) if name.as_str() == "Option")
+}
+
+/// A user-supplied migration rule.
+pub enum Rule {
+    /// Data of a field that is added to a struct or a struct-like variant.
+    Default {
+        /// Name of struct or variant.
+        ty: String,
+        /// Name of field.
+        field: String,
+        /// Data of field, e.g. `0` or `Option::None`.
+        value: Root,
+    },
+    /// A field that is renamed.
+    RenameField {
+        /// Name of struct or variant.
+        ty: String,
+        /// Old name of field.
+        from: String,
+        /// New name of field.
+        to: String,
+    },
+    /// A variant that is renamed.
+    RenameVariant {
+        /// Name of enum.
+        ty: String,
+        /// Old name of variant.
+        from: String,
+        /// New name of variant.
+        to: String,
+    },
+    /// A function that rewrites old values of a type before they are migrated.
+    Map {
+        /// Name of type.
+        ty: String,
+        /// Rewrites a value.
+        map: Box<dyn Fn(Value) -> Result<Value, String>>,
+    },
+}
+
+/// Returns the default data of a type, if any.
+///
+/// Numbers default to `0`, `bool` to `false`, `String` to `""`,
+/// `Option` to `Option::None` and `Vec` to `[]`.
+pub fn default_value(ty: &TypeRef) -> Option<Value> {
+    match strip(ty) {
+        TypeRef::Primitive(name) => match name.as_str() {
+            "f64" => Some(Value::F64(0.0)),
+            "usize" => Some(Value::Usize(0)),
+            "bool" => Some(Value::Bool(false)),
+            "String" => Some(Value::Str(Arc::new(String::new()))),
+            _ => None,
+        },
+        TypeRef::Applied(base, _) => match &**base {
+            TypeRef::Primitive(name) if name.as_str() == "Option" => Some(Value::Option(None)),
+            TypeRef::Primitive(name) if name.as_str() == "Vec" => Some(Value::List(vec![])),
+            _ => None,
+        },
+        TypeRef::Tuple(items) => Some(Value::Tuple(items.iter()
+            .map(default_value)
+            .collect::<Option<_>>()?)),
+        _ => None,
+    }
+}
+
+/// Migrates the instances of a document to a new format.
+///
+/// The result contains the declarations of the new format,
+/// followed by the instances of the old document.
+/// Instances `(ins N ..)` are renumbered to the new declaration with the same name.
+/// References `(ref N)` are renumbered to the new indices of instances,
+/// and an error is returned if a reference does not refer to an instance.
+pub fn migrate(old: &Root, new: &Root, rules: &[Rule]) -> Result<Root, String> {
+    let instances = value::read_instances(old)?;
+    let schema = Schema::from_root(new)?;
+    let mut res: Vec<Root> = match new {
+        Root::Tup(items) => items.iter()
+            .filter(|it| !matches!(it, Root::Instance {..} | Root::InstanceTy {..}))
+            .cloned()
+            .collect(),
+        _ => vec![new.clone()],
+    };
+    let migrator = Migrator {schema: &schema, rules, self_def: None};
+    let items = if let Root::Tup(items) = old {&items[..]} else {&[]};
+    let start = res.len();
+    // New index of every migrated instance, used to renumber references.
+    let mut moved: Vec<(usize, usize)> = vec![];
+    for (i, item) in items.iter().enumerate() {
+        let path = Path::root().index(i).name(&Arc::new("data".into()));
+        let value = instances.iter().find(|ins| ins.index == i);
+        match item {
+            Root::Instance {data, ..} => {
+                // Instances without data are migrated without data.
+                let class = match value {
+                    Some(ins) => ins.class.clone(),
+                    None => reference::class_of(old, i)
+                        .ok_or_else(|| format!("{}: Expected instance of declared type", Path::root().index(i)))?,
+                };
+                let class_ind = schema.defs.iter().position(|d| *d.name() == class)
+                    .ok_or_else(|| format!("{}: `{}` is not declared in new format", path, class))?;
+                let data = match (data, value) {
+                    (Some(_), Some(ins)) => Some(Box::new(migrator.convert(
+                        ins.value.clone(), &TypeRef::Named(class), &path)?.to_data())),
+                    _ => None,
+                };
+                moved.push((i, res.len()));
+                res.push(Root::Instance {class: class_ind, data});
+            }
+            Root::InstanceTy {ty, ..} => {
+                let data = match value {
+                    Some(ins) => Some(Box::new(migrator.convert(
+                        ins.value.clone(), &TypeRef::from_root(ty, &[])?, &path)?.to_data())),
+                    None => None,
+                };
+                moved.push((i, res.len()));
+                res.push(Root::InstanceTy {ty: ty.clone(), data});
+            }
+            _ => {}
+        }
+    }
+    for ((old, _), item) in moved.iter().zip(&mut res[start..]) {
+        let missing = Cell::new(None);
+        reference::renumber(item, &|i| match moved.iter().find(|(old, _)| *old == i) {
+            Some((_, new)) => *new,
+            None => {missing.set(Some(i)); i}
+        });
+        if let Some(i) = missing.get() {
+            return Err(format!("{}: Reference `{}` does not refer to a migrated instance",
+                Path::root().index(*old), Root::Ref(i)));
+        }
+    }
+    let res = Root::Tup(res);
+    value::read_instances(&res)?;
+    Ok(res)
+}
+
+struct Migrator<'a> {
+    schema: &'a Schema,
+    rules: &'a [Rule],
+    self_def: Option<Def>,
+}
+
+impl<'a> Migrator<'a> {
+    /// Converts an old value into a value of a type in the new format.
+    fn convert(&self, value: Value, ty: &TypeRef, path: &Path) -> Result<Value, String> {
+        let value = match value.type_name().and_then(|name| self.rules.iter().find_map(|r| match r {
+            Rule::Map {ty, map} if ty == name => Some(map),
+            _ => None,
+        })) {
+            Some(map) => map(value).map_err(|err| format!("{}: {}", path, err))?,
+            None => value,
+        };
+        let mismatch = |value: &Value| format!("{}: Can not migrate `{}` to `{}`",
+            path, value.to_data(), ty);
+        match strip(ty) {
+            TypeRef::Primitive(name) => match (name.as_str(), value) {
+                ("f64", Value::Usize(x)) => Ok(Value::F64(x as f64)),
+                ("usize", Value::F64(x)) if x >= 0.0 && x.fract() == 0.0 => Ok(Value::Usize(x as usize)),
+                (_, value) if value.conforms(ty, None) => Ok(value),
+                (_, value) => Err(mismatch(&value)),
+            },
+            TypeRef::Applied(base, args) if is_option(base) => match value {
+                Value::Option(None) => Ok(Value::Option(None)),
+                Value::Option(Some(x)) => Ok(Value::Option(Some(Box::new(self.convert(*x, &args[0], path)?)))),
+                x => Ok(Value::Option(Some(Box::new(self.convert(x, &args[0], path)?)))),
+            },
+            TypeRef::Applied(base, args) if matches!(&**base,
+                TypeRef::Primitive(name) if name.as_str() == "Vec") => match value {
+                Value::List(items) => Ok(Value::List(items.into_iter().enumerate()
+                    .map(|(i, it)| self.convert(it, &args[0], &path.index(i)))
+                    .collect::<Result<_, _>>()?)),
+                x => Err(mismatch(&x)),
+            },
+            TypeRef::Tuple(tys) => match value {
+                Value::Tuple(items) if items.len() == tys.len() => Ok(Value::Tuple(items.into_iter()
+                    .zip(tys).enumerate()
+                    .map(|(i, (it, ty))| self.convert(it, ty, &path.index(i)))
+                    .collect::<Result<_, _>>()?)),
+                x => Err(mismatch(&x)),
+            },
+            TypeRef::Applied(base, _) if matches!(&**base,
+                TypeRef::Primitive(name) if name.as_str() == "Ref") =>
+                if value.conforms(ty, self.self_def.as_ref().map(|d| d.name())) {Ok(value)}
+                else {Err(mismatch(&value))},
+            TypeRef::Generic(_) => Ok(value),
+            _ => self.convert_def(value, ty, path),
+        }
+    }
+
+    fn convert_def(&self, value: Value, ty: &TypeRef, path: &Path) -> Result<Value, String> {
+        let def = if value::is_self(ty) {
+            self.self_def.clone()
+                .ok_or_else(|| format!("{}: `Self` used outside of a definition", path))?
+        } else {
+            self.schema.instantiate(ty).map_err(|err| format!("{}: {}", path, err))?
+        };
+        let sub = Migrator {schema: self.schema, rules: self.rules, self_def: Some(def.clone())};
+        match (&def, value) {
+            (Def::Struct(s), Value::Struct(rec)) =>
+                Ok(Value::Struct(sub.convert_fields(rec, &s.name, &s.fields, path)?)),
+            (Def::Enum(e), Value::Variant(var)) => {
+                let name = self.rules.iter().find_map(|r| match r {
+                    Rule::RenameVariant {ty, from, to} if *ty == *e.name && *from == *var.name =>
+                        Some(Arc::new(to.clone())),
+                    _ => None,
+                }).unwrap_or(var.name);
+                let v = e.variants.iter().find(|v| v.name == name)
+                    .ok_or_else(|| format!("{}: `{}` has no variant `{}`", path, e.name, name))?;
+                let data = match (&v.data, var.data.map(|x| *x)) {
+                    (VariantData::Unit, None) => None,
+                    (VariantData::Type(ty), Some(x)) => Some(sub.convert(x, ty, &path.name(&name))?),
+                    (VariantData::Fields(fields), Some(Value::Struct(rec))) =>
+                        Some(Value::Struct(sub.convert_fields(rec, &name, fields, path)?)),
+                    _ => return Err(format!("{}: Can not migrate data of variant `{}::{}`",
+                        path, e.name, name)),
+                };
+                Ok(Value::Variant(Variant {ty: e.name.clone(), name, data: data.map(Box::new)}))
+            }
+            (_, x) => Err(format!("{}: Can not migrate `{}` to `{}`", path, x.to_data(), ty)),
+        }
+    }
+
+    fn convert_fields(
+        &self,
+        rec: Record,
+        ty: &Arc<String>,
+        fields: &[FieldDef],
+        path: &Path,
+    ) -> Result<Record, String> {
+        let mut old = rec.fields;
+        let mut res = vec![];
+        for f in fields {
+            let old_name = self.rules.iter().find_map(|r| match r {
+                Rule::RenameField {ty: t, from, to} if *t == **ty && *to == *f.name => Some(from.as_str()),
+                _ => None,
+            }).unwrap_or(f.name.as_str());
+            let path = path.name(&f.name);
+            let value = match old.iter().position(|(g, _)| g.name.as_str() == old_name) {
+                Some(i) => self.convert(old.remove(i).1, &f.ty, &path)?,
+                None => match self.rules.iter().find_map(|r| match r {
+                    Rule::Default {ty: t, field, value} if *t == **ty && *field == *f.name => Some(value),
+                    _ => None,
+                }).or(f.default.as_ref()) {
+                    Some(data) => value::read_type_at(self.schema, &f.ty, data, &path)?,
+                    None => default_value(&f.ty)
+                        .ok_or_else(|| format!("{}: No default for added field of type `{}`", path, f.ty))?,
+                },
+            };
+            res.push((f.clone(), value));
+        }
+        Ok(Record {ty: ty.clone(), fields: res})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let edge_dir = data(include_str!("../source/test/graph/edge_dir.txt"));
+        let changes = check(&edge_dir, &data("enum EdgeDir {Left, Right, Both, None, Up}")).unwrap();
+        assert_eq!(level(&changes), Compat::Backward);
+        assert_eq!(format!("{}", changes[0]), "backward .EdgeDir.Up: Added variant");
+        let changes = check(&edge_dir, &data("enum EdgeDir {Left, Right, Both}")).unwrap();
+        assert_eq!(level(&changes), Compat::Forward);
+
+        let person = data(include_str!("../source/test/person/person.txt"));
+        let changes = check(&person, &data(
+            "struct Person {first_name: Arc<String>, last_name: Arc<String>, age: f64}")).unwrap();
+        assert_eq!(level(&changes), Compat::Breaking);
+        assert_eq!(changes[0].path, Path::parse("Person.age").unwrap());
+        let changes = check(&person, &data(
+            "struct Person {first_name: Arc<String>, last_name: Arc<String>, age: f64 = 0}")).unwrap();
+        assert_eq!(level(&changes), Compat::Backward);
+        let changes = check(&data(
+            "struct Person {first_name: Arc<String>, nick: Option<String>, last_name: Arc<String>}"),
+            &person).unwrap();
+        assert_eq!(level(&changes), Compat::Breaking);
+        let changes = check(&person, &data(
+            "struct Person {last_name: Arc<String>, first_name: Arc<String>}")).unwrap();
+        assert_eq!(changes[0].description, "Reordered fields");
+        let constrained = data(
+            "struct Person {#[non_empty] first_name: Arc<String>, last_name: Arc<String>}");
+        let changes = check(&person, &constrained).unwrap();
+        assert_eq!(format!("{}", changes[0]), "forward .Person.first_name: Added attribute `\"non_empty\"`");
+        assert_eq!(level(&check(&constrained, &person).unwrap()), Compat::Backward);
+
+        let f64_ty = TypeRef::parse("f64").unwrap();
+        let usize_ty = TypeRef::parse("usize").unwrap();
+        let option_ty = TypeRef::parse("Option<f64>").unwrap();
+        assert_eq!(ty_compat(&usize_ty, &f64_ty), Compat::Backward);
+        assert_eq!(ty_compat(&f64_ty, &option_ty), Compat::Backward);
+        assert_eq!(ty_compat(&option_ty, &usize_ty), Compat::Forward);
+        assert_eq!(ty_compat(&TypeRef::parse("Arc<String>").unwrap(),
+            &TypeRef::parse("String").unwrap()), Compat::Full);
+        assert_eq!(ty_compat(&TypeRef::parse("Vec<f64>").unwrap(), &f64_ty), Compat::Breaking);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let old = data(include_str!("../source/test/person/person-with-instance.txt"));
+        let new = data(r#"[
+            enum Title {Mr, Ms},
+            struct Person {
+                name: Arc<String>,
+                last_name: Arc<String>,
+                age: f64,
+                nick: Option<Arc<String>>,
+                title: (ty Title),
+            },
+        ]"#);
+        let rules = [
+            Rule::RenameField {ty: "Person".into(), from: "first_name".into(), to: "name".into()},
+            Rule::Default {ty: "Person".into(), field: "title".into(), value: data("Title::Mr")},
+        ];
+        assert_eq!(migrate(&old, &new, &rules).unwrap(), data(r#"[
+            enum Title {Mr, Ms},
+            struct Person {
+                name: Arc<String>,
+                last_name: Arc<String>,
+                age: f64,
+                nick: Option<Arc<String>>,
+                title: (ty Title),
+            },
+            (ins 1 ["Donald", "Duck", 0, Option::None, "Mr"]),
+            (ins 1 ["Dolly", "Duck", 0, Option::None, "Mr"]),
+        ]"#));
+        assert!(migrate(&old, &new, &rules[..1]).is_err());
+
+        // References follow instances to their new indices.
+        let graph = data(include_str!("../source/test/graph/graph-with-refs.txt"));
+        let mut decls = vec![data("struct Meta {name: Arc<String>}")];
+        decls.extend((0..3).filter_map(|i| graph.child(i)).cloned());
+        let res = migrate(&graph, &Root::Tup(decls.clone()), &[]).unwrap();
+        validate::validate_document(&res).unwrap();
+        assert_eq!(res.select(&Path::parse("[7].data.from").unwrap()), Some(&Root::Ref(4)));
+
+        // Instances without data are kept, and references must refer to migrated instances.
+        let mut items = if let Root::Tup(items) = graph {items} else {panic!()};
+        items.push(data("(ins 0)"));
+        let res = migrate(&Root::Tup(items.clone()), &Root::Tup(decls.clone()), &[]).unwrap();
+        assert_eq!(res.child(9), Some(&Root::Instance {class: 1, data: None}));
+        items[6] = data("(ins 2 [(ref 3), (ref 0), EdgeDir::Both])");
+        assert_eq!(migrate(&Root::Tup(items), &Root::Tup(decls), &[]).unwrap_err(),
+            "[6]: Reference `(ref 0)` does not refer to a migrated instance");
+
+        let rules = [
+            Rule::RenameField {ty: "Person".into(), from: "first_name".into(), to: "name".into()},
+            Rule::Map {ty: "Person".into(), map: Box::new(|v: Value| {
+                let title = match v.get("first_name").and_then(|x| x.as_str()) {
+                    Some("Dolly") => "Ms",
+                    _ => "Mr",
+                };
+                let mut rec = if let Value::Struct(rec) = v {rec} else {
+                    return Err("Expected struct".into())
+                };
+                rec.fields.push((FieldDef {
+                    name: Arc::new("title".into()),
+                    ty: TypeRef::parse("(ty Title)").unwrap(),
+                    default: None,
+                    attrs: vec![],
+                }, Value::Variant(Variant {
+                    ty: Arc::new("Title".into()),
+                    name: Arc::new(title.into()),
+                    data: None,
+                })));
+                Ok(Value::Struct(rec))
+            })},
+        ];
+        let res = migrate(&old, &new, &rules).unwrap();
+        assert_eq!(res.select(&Path::parse("[3].data.title").unwrap()), Some(&data(r#""Ms""#)));
+    }
+}
@@ -0,0 +1,344 @@
+//! Constraints on fields, declared with attributes.
+//!
+//! Attributes are written before a field in a struct declaration:
+//!
+//! ```text
+//! struct Person {
+//!     #[unique] id: usize,
+//!     #[non_empty] #[pattern("^[A-Z]")] name: Arc<String>,
+//!     #[range(0, 150)] age: f64 = 0,
+//!     #[ref(Person)] parent: Option<usize>,
+//! }
+//! ```
+//!
+//! - `range(a, b)`, `min(a)` and `max(b)` bound numbers, inclusive
+//! - `pattern("..")` requires strings to match a pattern, see `pattern::Pattern`
+//! - `non_empty` requires strings and lists to have items
+//! - `unique` requires values of the field to differ between all records of the type
+//! - `ref(T)` requires an index to refer to an instance of `T`,
+//!   counting all items of the document
+//!
+//! Constraints apply to the items of `Vec` and the data of `Option::Some`,
+//! except `non_empty` and `unique` which apply to the whole list.
+//! A missing `Option` value satisfies every constraint.
+
+use crate::*;
+use crate::path::Path;
+use crate::pattern::Pattern;
+use crate::schema::*;
+use crate::value::{Record, Value};
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// A constraint on the values of a field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constraint {
+    /// Inclusive bounds of a number, e.g. `range(0, 150)` or `min(0)`.
+    Range {
+        /// Lower bound.
+        min: Option<f64>,
+        /// Upper bound.
+        max: Option<f64>,
+    },
+    /// A pattern that strings must match, e.g. `pattern("^[A-Z]")`.
+    Pattern(Pattern),
+    /// Strings and lists must have items.
+    NonEmpty,
+    /// Values must differ between records of the same type.
+    Unique,
+    /// An index of an instance of a type, e.g. `ref(Node)`.
+    Ref(Arc<String>),
+}
+
+/// A value that violates a constraint.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// Path of value in document, e.g. `[3].data.age`.
+    pub path: Path,
+    /// Describes the violation.
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(w, "{}: {}", self.path, self.message)
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Range {min: Some(a), max: Some(b)} => write!(w, "range({}, {})", a, b),
+            Constraint::Range {min: Some(a), max: None} => write!(w, "min({})", a),
+            Constraint::Range {min: None, max: Some(b)} => write!(w, "max({})", b),
+            Constraint::Range {min: None, max: None} => write!(w, "range"),
+            Constraint::Pattern(p) => write!(w, "pattern({:?})", p.as_str()),
+            Constraint::NonEmpty => write!(w, "non_empty"),
+            Constraint::Unique => write!(w, "unique"),
+            Constraint::Ref(ty) => write!(w, "ref({})", ty),
+        }
+    }
+}
+
+impl Constraint {
+    /// Converts an attribute into a constraint, e.g. `range(0, 150)`.
+    pub fn from_root(attr: &Root) -> Result<Constraint, String> {
+        let (name, args) = match attr {
+            Root::Str(name) => (name, &[][..]),
+            Root::Avatar(ab) => match ab.as_ref() {
+                (Root::Str(name), Root::Tup(args)) => (name, &args[..]),
+                _ => return Err(format!("Expected attribute, found `{}`", attr)),
+            },
+            _ => return Err(format!("Expected attribute, found `{}`", attr)),
+        };
+        let number = |x: &Root| if let Root::F64(x) = x {Ok(*x)} else {
+            Err(format!("Expected number in `{}`, found `{}`", attr, x))
+        };
+        match (name.as_str(), args) {
+            ("range", [a, b]) => Ok(Constraint::Range {min: Some(number(a)?), max: Some(number(b)?)}),
+            ("min", [a]) => Ok(Constraint::Range {min: Some(number(a)?), max: None}),
+            ("max", [b]) => Ok(Constraint::Range {min: None, max: Some(number(b)?)}),
+            ("pattern", [Root::Str(p)]) => Ok(Constraint::Pattern(Pattern::parse(p)?)),
+            ("non_empty", []) => Ok(Constraint::NonEmpty),
+            ("unique", []) => Ok(Constraint::Unique),
+            ("ref", [Root::Str(ty)]) | ("ref", [Root::Ty(ty)]) => Ok(Constraint::Ref(ty.clone())),
+            ("range" | "min" | "max" | "pattern" | "non_empty" | "unique" | "ref", _) =>
+                Err(format!("Wrong arguments for attribute `{}`", attr)),
+            _ => Err(format!("Unknown attribute `{}`", name)),
+        }
+    }
+
+    /// Returns the constraints of a field.
+    pub fn of_field(f: &FieldDef) -> Result<Vec<Constraint>, String> {
+        f.attrs.iter().map(Constraint::from_root).collect()
+    }
+
+    /// Checks that the constraint applies to values of a type.
+    ///
+    /// Generic parameters are accepted, since their type is not known.
+    pub fn check_ty(&self, ty: &TypeRef) -> Result<(), String> {
+        let prim = |ty: &TypeRef, names: &[&str]| match ty {
+            TypeRef::Primitive(name) => names.contains(&name.as_str()),
+            TypeRef::Applied(base, _) => matches!(&**base,
+                TypeRef::Primitive(name) if names.contains(&name.as_str())),
+            TypeRef::Generic(_) => true,
+            _ => false,
+        };
+        let ok = match self {
+            Constraint::Range {..} => prim(item_ty(ty, true), &["f64", "usize"]),
+            Constraint::Pattern(_) => prim(item_ty(ty, true), &["String"]),
+            Constraint::Ref(_) => prim(item_ty(ty, true), &["usize"]),
+            Constraint::NonEmpty => prim(item_ty(ty, false), &["String", "Vec"]),
+            Constraint::Unique => true,
+        };
+        if ok {Ok(())} else {Err(format!("Attribute `{}` does not apply to `{}`", self, ty))}
+    }
+}
+
+/// Removes `Option`, `Arc` and `box`, and optionally `Vec`, to get the type of constrained items.
+fn item_ty(ty: &TypeRef, vec: bool) -> &TypeRef {
+    match ty {
+        TypeRef::Applied(base, args) if args.len() == 1 => match &**base {
+            TypeRef::Primitive(name) if matches!(name.as_str(), "Option" | "Arc" | "box") ||
+                vec && name.as_str() == "Vec" => item_ty(&args[0], vec),
+            _ => ty,
+        },
+        _ => ty,
+    }
+}
+
+/// Checks constraints of fields in a document and returns all violations.
+///
+/// Returns an error if the document is invalid,
+/// or if an attribute is unknown or does not apply to the type of its field.
+pub fn check(doc: &Root) -> Result<Vec<Violation>, String> {
+    let schema = Schema::from_root(doc)?;
+    for def in &schema.defs {
+        for (path, f) in fields(def) {
+            for c in Constraint::of_field(f).map_err(|err| format!("{}: {}", path, err))? {
+                c.check_ty(&f.ty).map_err(|err| format!("{}: {}", path, err))?;
+                if let Constraint::Ref(ty) = &c {
+                    if schema.get(ty).is_none() {
+                        return Err(format!("{}: Unknown type `{}` in `{}`", path, ty, c));
+                    }
+                }
+            }
+        }
+    }
+
+    let instances = value::read_instances(doc)?;
+    let mut checker = Checker {
+        classes: HashMap::new(),
+        unique: HashMap::new(),
+        violations: vec![],
+    };
+    for ins in &instances {
+        checker.classes.insert(ins.index, ins.class.clone());
+    }
+    for ins in &instances {
+        let path = Path::root().index(ins.index).name(&Arc::new("data".into()));
+        checker.value(&ins.value, &path)?;
+    }
+    Ok(checker.violations)
+}
+
+/// Returns fields of a definition with their paths, e.g. `.Person.age`.
+fn fields(def: &Def) -> Vec<(Path, &FieldDef)> {
+    let path = Path::root().name(def.name());
+    match def {
+        Def::Struct(s) => s.fields.iter().map(|f| (path.name(&f.name), f)).collect(),
+        Def::Enum(e) => e.variants.iter().flat_map(|v| match &v.data {
+            VariantData::Fields(fields) => fields.iter()
+                .map(|f| (path.name(&v.name).name(&f.name), f)).collect(),
+            _ => vec![],
+        }).collect(),
+    }
+}
+
+/// A value with the path where it was first seen.
+type Seen = (Root, Path);
+
+struct Checker {
+    /// Types of instances by index in the document.
+    classes: HashMap<usize, Arc<String>>,
+    /// Values of unique fields seen so far with their paths, per record type and field.
+    unique: HashMap<(Arc<String>, Arc<String>), Vec<Seen>>,
+    violations: Vec<Violation>,
+}
+
+impl Checker {
+    fn value(&mut self, v: &Value, path: &Path) -> Result<(), String> {
+        match v {
+            Value::Struct(rec) => self.record(rec, path)?,
+            Value::Variant(var) => match var.data.as_deref() {
+                Some(Value::Struct(rec)) if rec.ty == var.name => self.record(rec, path)?,
+                Some(data) => self.value(data, &path.name(&var.name))?,
+                None => {}
+            },
+            Value::Option(Some(x)) => self.value(x, path)?,
+            Value::List(items) | Value::Tuple(items) => for (i, it) in items.iter().enumerate() {
+                self.value(it, &path.index(i))?;
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, rec: &Record, path: &Path) -> Result<(), String> {
+        for (f, v) in &rec.fields {
+            let path = path.name(&f.name);
+            for c in Constraint::of_field(f)? {
+                self.constraint(&c, &rec.ty, f, v, &path);
+            }
+            self.value(v, &path)?;
+        }
+        Ok(())
+    }
+
+    fn constraint(&mut self, c: &Constraint, ty: &Arc<String>, f: &FieldDef, v: &Value, path: &Path) {
+        match c {
+            Constraint::NonEmpty => match v.flatten() {
+                Some(Value::Str(s)) if s.is_empty() => self.violation(path, "Expected non-empty string".into()),
+                Some(Value::List(items)) if items.is_empty() =>
+                    self.violation(path, "Expected non-empty list".into()),
+                _ => {}
+            },
+            Constraint::Unique => {
+                if matches!(v, Value::Option(None)) {return}
+                let data = v.to_data();
+                let seen = self.unique.entry((ty.clone(), f.name.clone())).or_default();
+                match seen.iter().find(|(x, _)| *x == data) {
+                    Some((_, first)) => {
+                        let message = format!("Duplicate value `{}` of unique field, first at {}", data, first);
+                        self.violation(path, message);
+                    }
+                    None => seen.push((data, path.clone())),
+                }
+            }
+            _ => self.items(c, v, path),
+        }
+    }
+
+    /// Checks a constraint on items of lists and the data of `Option::Some`.
+    fn items(&mut self, c: &Constraint, v: &Value, path: &Path) {
+        match v {
+            Value::Option(x) => if let Some(x) = x {self.items(c, x, path)},
+            Value::List(items) => for (i, it) in items.iter().enumerate() {
+                self.items(c, it, &path.index(i));
+            },
+            _ => match c {
+                Constraint::Range {min, max} => if let Some(x) = v.as_f64()
+                    .or_else(|| v.as_usize().map(|x| x as f64)) {
+                    if min.is_some_and(|min| x < min) || max.is_some_and(|max| x > max) {
+                        self.violation(path, format!("Value `{}` is out of `{}`", x, c));
+                    }
+                },
+                Constraint::Pattern(p) => if let Some(s) = v.as_str() {
+                    if !p.is_match(s) {
+                        self.violation(path, format!("Value {:?} does not match `{}`", s, c));
+                    }
+                },
+                Constraint::Ref(ty) => if let Some(ind) = v.as_usize() {
+                    match self.classes.get(&ind) {
+                        Some(class) if class == ty => {}
+                        Some(class) => self.violation(path, format!(
+                            "Reference `{}` to `{}` refers to an instance of `{}`", ind, ty, class)),
+                        None => self.violation(path, format!("Dangling reference `{}` to `{}`", ind, ty)),
+                    }
+                },
+                Constraint::NonEmpty | Constraint::Unique => {}
+            },
+        }
+    }
+
+    fn violation(&mut self, path: &Path, message: String) {
+        self.violations.push(Violation {path: path.clone(), message});
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let doc = parsing::parse_str(include_str!("../source/test/person/person-constraints.txt"), &[])
+            .unwrap();
+        assert_eq!(parsing::parse_str(&format!("{}", doc), &[]).unwrap(), doc);
+        if let Def::Struct(s) = &Schema::from_root(&doc).unwrap().defs[0] {
+            let constraints = Constraint::of_field(&s.fields[1]).unwrap();
+            assert_eq!(constraints[0], Constraint::NonEmpty);
+            assert_eq!(format!("{}", constraints[1]), "pattern(\"^[A-Z]\")");
+        }
+        let violations: Vec<String> = check(&doc).unwrap().iter().map(|v| format!("{}", v)).collect();
+        assert_eq!(violations, vec![
+            "[2].data.name: Expected non-empty string",
+            "[2].data.name: Value \"\" does not match `pattern(\"^[A-Z]\")`",
+            "[2].data.age: Value `200` is out of `range(0, 150)`",
+            "[3].data.id: Duplicate value `1` of unique field, first at [2].data.id",
+            "[3].data.name: Value \"dolly\" does not match `pattern(\"^[A-Z]\")`",
+            "[3].data.parents[1]: Dangling reference `4` to `Person`",
+        ]);
+        assert!(validate::validate_document(&doc).unwrap_err().contains("[3].data.id: Duplicate"));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let check_src = |src: &str| check(&parsing::parse_str(src, &[]).unwrap());
+        assert!(check_src("[struct Foo {#[unique] #[min(0)] x: usize}, (ins 0 [1]), (ins 0 [2])]")
+            .unwrap().is_empty());
+        assert_eq!(check_src("[struct Foo {#[sorted] x: usize}]").unwrap_err(),
+            ".Foo.x: Unknown attribute `sorted`");
+        assert_eq!(check_src("[struct Foo {#[pattern(\"a\")] x: usize}]").unwrap_err(),
+            ".Foo.x: Attribute `pattern(\"a\")` does not apply to `usize`");
+        assert_eq!(check_src("[struct Foo {#[ref(Bar)] x: usize}]").unwrap_err(),
+            ".Foo.x: Unknown type `Bar` in `ref(Bar)`");
+        assert!(check_src("[struct Foo {#[range(0)] x: usize}]").is_err());
+        let violations = check_src("[struct Foo {#[ref(Foo)] x: usize}, struct Bar {y: f64},
+            (ins 1 [0]), (ins 0 [2]), (ins 0 [3])]").unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(format!("{}", violations[0]),
+            "[3].data.x: Reference `2` to `Foo` refers to an instance of `Bar`");
+    }
+}
@@ -0,0 +1,309 @@
+//! Structural diff and patch between documents.
+//!
+//! Changes are keyed by paths, see `path::Path`:
+//!
+//! - Fields of struct declarations, variants of enum declarations
+//!   and named fields of tuples are matched by name, e.g. `[0].age`
+//! - Other items, e.g. declarations and instances of a document, are matched by position
+//! - Positional records of instances use the field names of their declaration,
+//!   when the declaration is the same in both documents, e.g. `[6].data.dir`
+
+use crate::*;
+use crate::path::{find_decl, field_ty, Path, Segment};
+
+/// A change from one document to another.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A node was added, with path in the new document.
+    Added(Path, Root),
+    /// A node was removed, with path in the old document.
+    Removed(Path, Root),
+    /// A node was replaced, from old to new value.
+    Changed(Path, Root, Root),
+}
+
+impl Change {
+    /// Returns the path of change.
+    pub fn path(&self) -> &Path {
+        match self {
+            Change::Added(path, _) | Change::Removed(path, _) | Change::Changed(path, ..) => path,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Change::Added(path, new) => write!(w, "+ {}: {}", path, print::source(new)),
+            Change::Removed(path, old) => write!(w, "- {}: {}", path, print::source(old)),
+            Change::Changed(path, old, new) =>
+                write!(w, "~ {}: {} -> {}", path, print::source(old), print::source(new)),
+        }
+    }
+}
+
+/// Computes the changes from `old` to `new`.
+///
+/// Applying the changes with `patch` to `old` gives `new`,
+/// except that added named items are appended at the end.
+pub fn diff(old: &Root, new: &Root) -> Vec<Change> {
+    let docs = match (old, new) {
+        (Root::Tup(a), Root::Tup(b)) => (&a[..], &b[..]),
+        _ => (&[][..], &[][..]),
+    };
+    let mut differ = Differ {docs, changes: vec![]};
+    differ.node(old, new, &Path::root(), None);
+    differ.changes
+}
+
+/// Applies changes to a document.
+///
+/// Returns an error if a change does not match the document,
+/// e.g. when the old value is different.
+pub fn patch(root: &mut Root, changes: &[Change]) -> Result<(), String> {
+    for change in changes {
+        match change {
+            Change::Changed(path, old, new) => {
+                let node = root.select_mut(path)
+                    .ok_or_else(|| format!("{}: Could not find node", path))?;
+                if node != old {
+                    return Err(format!("{}: Expected `{}`, found `{}`", path,
+                        print::source(old), print::source(node)));
+                }
+                *node = new.clone();
+            }
+            Change::Removed(path, old) => {
+                let (items, seg) = parent_items(root, path)?;
+                let ind = match seg {
+                    Segment::Index(i) => *i,
+                    Segment::Name(name) => items.iter().position(|it| key(it) == Some(name))
+                        .ok_or_else(|| format!("{}: Could not find node", path))?,
+                };
+                match items.get(ind) {
+                    Some(it) if it == old => {items.remove(ind);}
+                    Some(it) => return Err(format!("{}: Expected `{}`, found `{}`", path,
+                        print::source(old), print::source(it))),
+                    None => return Err(format!("{}: Could not find node", path)),
+                }
+            }
+            Change::Added(path, new) => {
+                let (items, seg) = parent_items(root, path)?;
+                match seg {
+                    Segment::Index(i) if *i <= items.len() => items.insert(*i, new.clone()),
+                    Segment::Name(name) if items.iter().all(|it| key(it) != Some(name)) =>
+                        items.push(new.clone()),
+                    _ => return Err(format!("{}: Node already exists", path)),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Differ<'a> {
+    /// Items of old and new document, used to look up declarations.
+    docs: (&'a [Root], &'a [Root]),
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    /// Compares two nodes.
+    ///
+    /// `decl` is the declaration describing a positional record, if any.
+    fn node(&mut self, a: &'a Root, b: &'a Root, path: &Path, decl: Option<&'a Root>) {
+        use Root::*;
+
+        if a == b {return}
+        match (a, b) {
+            (Tup(xs), Tup(ys)) if is_record(xs) && is_record(ys) => self.named(xs, ys, path, decl),
+            (Tup(xs), Tup(ys)) => match decl {
+                Some(Struct {fields, ..}) if xs.len() == fields.len() && ys.len() == fields.len() => {
+                    for ((x, y), f) in xs.iter().zip(ys).zip(fields) {
+                        if let Some((name, _)) = f.as_field() {
+                            let decl = field_ty(decl, name).and_then(|ty| self.decl(ty));
+                            self.node(x, y, &path.name(name), decl);
+                        }
+                    }
+                }
+                _ => self.positional(xs, ys, path),
+            },
+            (Params(xs), Params(ys)) => self.positional(xs, ys, path),
+            (Struct {name: na, fields: xs}, Struct {name: nb, fields: ys})
+            if na == nb && is_record(xs) && is_record(ys) => self.named(xs, ys, path, None),
+            (Enum {name: na, variants: xs}, Enum {name: nb, variants: ys})
+            if na == nb && is_named(xs) && is_named(ys) => self.named(xs, ys, path, None),
+            (Avatar(x), Avatar(y)) if x.0 == y.0 => {
+                let path = if let Str(name) = &x.0 {path.name(name)} else {path.index(1)};
+                self.node(&x.1, &y.1, &path, None)
+            }
+            (Field(x), Field(y)) if x.0 == y.0 => self.node(&x.1, &y.1, &path.index(1), None),
+            (Instance {class: ca, data: Some(x)}, Instance {class: cb, data: Some(y)}) if ca == cb => {
+                let decl = match (self.docs.0.get(*ca), self.docs.1.get(*cb)) {
+                    // Classes refer to declarations only at the top level of a document.
+                    (Some(da), Some(db)) if is_top_level(path) && da == db => Some(db),
+                    _ => None,
+                };
+                self.node(x, y, &path.name(&Arc::new("data".into())), decl)
+            }
+            (InstanceTy {ty: ta, data: Some(x)}, InstanceTy {ty: tb, data: Some(y)}) if ta == tb => {
+                let decl = self.decl(tb);
+                self.node(x, y, &path.name(&Arc::new("data".into())), decl)
+            }
+            _ => self.changes.push(Change::Changed(path.clone(), a.clone(), b.clone())),
+        }
+    }
+
+    /// Finds a declaration by type name, if it is the same in both documents.
+    fn decl(&self, ty: &Root) -> Option<&'a Root> {
+        match (find_decl(self.docs.0, ty), find_decl(self.docs.1, ty)) {
+            (Some(da), Some(db)) if da == db => Some(db),
+            _ => None,
+        }
+    }
+
+    /// Compares items by position.
+    fn positional(&mut self, xs: &'a [Root], ys: &'a [Root], path: &Path) {
+        for (i, (x, y)) in xs.iter().zip(ys).enumerate() {
+            self.node(x, y, &path.index(i), None);
+        }
+        // Remove from the end, such that indices stay valid when patching.
+        for i in (ys.len()..xs.len()).rev() {
+            self.changes.push(Change::Removed(path.index(i), xs[i].clone()));
+        }
+        for (i, y) in ys.iter().enumerate().skip(xs.len()) {
+            self.changes.push(Change::Added(path.index(i), y.clone()));
+        }
+    }
+
+    /// Compares items by name.
+    fn named(&mut self, xs: &'a [Root], ys: &'a [Root], path: &Path, decl: Option<&'a Root>) {
+        for x in xs {
+            let name = key(x).unwrap();
+            match ys.iter().find(|y| key(y) == Some(name)) {
+                Some(y) => match (x, y) {
+                    (Root::Field(kx), Root::Field(ky)) => {
+                        let decl = field_ty(decl, name).and_then(|ty| self.decl(ty));
+                        self.node(&kx.1, &ky.1, &path.name(name), decl)
+                    }
+                    _ if x == y => {}
+                    _ => self.variant(x, y, &path.name(name)),
+                },
+                None => self.changes.push(Change::Removed(path.name(name), x.clone())),
+            }
+        }
+        for y in ys {
+            let name = key(y).unwrap();
+            if xs.iter().all(|x| key(x) != Some(name)) {
+                self.changes.push(Change::Added(path.name(name), y.clone()));
+            }
+        }
+    }
+
+    /// Compares two enum variants with the same name.
+    fn variant(&mut self, a: &'a Root, b: &'a Root, path: &Path) {
+        use Root::*;
+
+        match (a, b) {
+            (Avatar(x), Avatar(y)) => self.node(&x.1, &y.1, &path.index(1), None),
+            (Struct {fields: xs, ..}, Struct {fields: ys, ..}) if is_record(xs) && is_record(ys) =>
+                self.named(xs, ys, path, None),
+            _ => self.changes.push(Change::Changed(path.clone(), a.clone(), b.clone())),
+        }
+    }
+}
+
+/// Returns the name of a field or enum variant.
+pub(crate) fn key(item: &Root) -> Option<&Arc<String>> {
+    use Root::*;
+
+    match item {
+        Str(name) => Some(name),
+        Field(kv) | Avatar(kv) => if let Str(name) = &kv.0 {Some(name)} else {None},
+        Struct {name, ..} => if let Str(name) = &**name {Some(name)} else {None},
+        _ => None,
+    }
+}
+
+/// Returns `true` if all items have distinct names.
+fn is_named(items: &[Root]) -> bool {
+    !items.is_empty() && items.iter().enumerate().all(|(i, it)| match key(it) {
+        Some(name) => items[..i].iter().all(|x| key(x) != Some(name)),
+        None => false,
+    })
+}
+
+/// Returns `true` if all items are fields with distinct names.
+pub(crate) fn is_record(items: &[Root]) -> bool {
+    is_named(items) && items.iter().all(|it| matches!(it, Root::Field(_)))
+}
+
+fn is_top_level(path: &Path) -> bool {
+    matches!(&path.segments[..], [Segment::Index(_)])
+}
+
+/// Finds the items of the parent node and the last segment of a path.
+fn parent_items<'a, 'b>(root: &'a mut Root, path: &'b Path) -> Result<(&'a mut Vec<Root>, &'b Segment), String> {
+    let seg = path.segments.last().ok_or_else(|| "Can not add or remove the root".to_string())?;
+    let parent = path.parent().unwrap();
+    match root.select_mut(&parent) {
+        Some(Root::Tup(items)) | Some(Root::Params(items)) |
+        Some(Root::Struct {fields: items, ..}) | Some(Root::Enum {variants: items, ..}) => Ok((items, seg)),
+        Some(x) => Err(format!("{}: Expected items, found `{}`", parent, x)),
+        None => Err(format!("{}: Could not find node", parent)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_patch(old: &Root, new: &Root) -> Vec<String> {
+        let changes = diff(old, new);
+        let mut res = old.clone();
+        patch(&mut res, &changes).unwrap();
+        assert_eq!(&res, new);
+        changes.iter().map(|ch| format!("{}", ch)).collect()
+    }
+
+    #[test]
+    fn test_person() {
+        let old = data(include_str!("../source/test/person/person-with-instance.txt"));
+        assert!(diff(&old, &old).is_empty());
+        let new = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>, age: f64},
+            (ins 0 ["Donald", "Duck", 90]),
+        ]"#);
+        assert_eq!(check_patch(&old, &new), vec![
+            "+ [0].age: age: f64",
+            "+ [1].data[2]: 90",
+            "- [2]: (ins 0 [\"Dolly\", \"Duck\"])",
+        ]);
+        let new = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Donald", "Duck"]),
+            (ins 0 ["Della", "Duck"]),
+        ]"#);
+        assert_eq!(check_patch(&old, &new), vec![
+            "~ [2].data.first_name: \"Dolly\" -> \"Della\"",
+        ]);
+
+        let mut other = new.clone();
+        assert!(patch(&mut other, &diff(&old, &new)).is_err());
+    }
+
+    #[test]
+    fn test_graph() {
+        let old = data(include_str!("../source/test/graph/graph-with-instances.txt"));
+        let mut new = old.clone();
+        *new.select_mut(&Path::parse("[6].data.dir").unwrap()).unwrap() = data("EdgeDir::Left");
+        *new.select_mut(&Path::parse("[3].data.Str").unwrap()).unwrap() = data(r#""Donald Duck""#);
+        if let Some(Root::Enum {variants, ..}) = new.child_mut(0) {
+            variants.push(data("Bool(bool)"));
+        }
+        assert_eq!(check_patch(&old, &new), vec![
+            "+ [0].Bool: Bool(bool)",
+            "~ [3].data.Str: \"Donald\" -> \"Donald Duck\"",
+            "~ [6].data.dir.data: \"Both\" -> \"Left\"",
+        ]);
+    }
+}
@@ -0,0 +1,406 @@
+//! Graphs of nodes and edges described by the graph formats.
+//!
+//! Nodes are instances of `Node`, and edges are instances of:
+//!
+//! ```text
+//! struct Edge {
+//!     from: usize,
+//!     to: usize,
+//!     dir: (ty EdgeDir),
+//! }
+//! ```
+//!
+//! Where `from` and `to` are indices among the nodes, or `Ref<(ty Node)>` references.
+//! The direction of an edge decides how it is traversed:
+//!
+//! - `Right` goes from `from` to `to`
+//! - `Left` goes from `to` to `from`
+//! - `Both` goes both ways
+//! - `None` is not traversed, but kept when exporting edges
+
+use crate::*;
+use crate::schema::*;
+use crate::value::Value;
+
+use std::collections::VecDeque;
+
+/// Format of edge tables, e.g. `[[0, 1, EdgeDir::Left]]`.
+pub const EDGE_FORMAT: &str = r#"[
+  struct Edge {from: usize, to: usize, dir: (ty EdgeDir)},
+  enum EdgeDir {Left, Right, Both, None},
+]"#;
+
+/// Direction of an edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeDir {
+    /// From `to` to `from`.
+    Left,
+    /// From `from` to `to`.
+    Right,
+    /// Both ways.
+    Both,
+    /// Not traversed.
+    None,
+}
+
+/// An edge between two nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    /// Index of first node.
+    pub from: usize,
+    /// Index of second node.
+    pub to: usize,
+    /// Direction of edge.
+    pub dir: EdgeDir,
+}
+
+/// A graph with adjacency lists following the direction of edges.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Graph {
+    nodes: Vec<Root>,
+    edges: Vec<Edge>,
+    /// Reachable neighbours of each node, with the index of the edge used.
+    adjacency: Vec<Vec<(usize, usize)>>,
+}
+
+impl EdgeDir {
+    /// Parses the name of a variant, e.g. `Left`.
+    pub fn from_name(name: &str) -> Result<EdgeDir, String> {
+        match name {
+            "Left" => Ok(EdgeDir::Left),
+            "Right" => Ok(EdgeDir::Right),
+            "Both" => Ok(EdgeDir::Both),
+            "None" => Ok(EdgeDir::None),
+            _ => Err(format!("`EdgeDir` has no variant `{}`", name)),
+        }
+    }
+
+    /// Returns the name of variant.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeDir::Left => "Left",
+            EdgeDir::Right => "Right",
+            EdgeDir::Both => "Both",
+            EdgeDir::None => "None",
+        }
+    }
+}
+
+impl Edge {
+    fn from_value(v: &Value, node_of: &dyn Fn(&Value) -> Option<usize>) -> Result<Edge, String> {
+        let node = |field: &str| v.get(field).and_then(node_of)
+            .ok_or_else(|| format!("Expected node in field `{}` of edge", field));
+        let dir = v.get("dir").and_then(|x| x.variant())
+            .ok_or_else(|| "Expected `dir` in edge".to_string())?;
+        Ok(Edge {from: node("from")?, to: node("to")?, dir: EdgeDir::from_name(dir)?})
+    }
+
+    /// Converts edge into a row of an edge table, e.g. `[0, 1, EdgeDir::Left]`.
+    pub fn to_root(&self) -> Root {
+        Root::Tup(vec![
+            Root::F64(self.from as f64),
+            Root::F64(self.to as f64),
+            Root::InstanceTy {
+                ty: Box::new(Root::Ty(Arc::new("EdgeDir".into()))),
+                data: Some(Box::new(Root::Str(Arc::new(self.dir.name().into())))),
+            },
+        ])
+    }
+}
+
+impl Graph {
+    /// Creates a graph from node data and edges.
+    pub fn new(nodes: Vec<Root>, edges: Vec<Edge>) -> Result<Graph, String> {
+        let mut adjacency = vec![vec![]; nodes.len()];
+        for (i, e) in edges.iter().enumerate() {
+            for n in [e.from, e.to] {
+                if n >= nodes.len() {
+                    return Err(format!("Edge {} refers to node {}, found {} node(s)",
+                        i, n, nodes.len()));
+                }
+            }
+            if matches!(e.dir, EdgeDir::Right | EdgeDir::Both) {adjacency[e.from].push((e.to, i))}
+            if matches!(e.dir, EdgeDir::Left | EdgeDir::Both) {adjacency[e.to].push((e.from, i))}
+        }
+        Ok(Graph {nodes, edges, adjacency})
+    }
+
+    /// Loads a graph from the instances of `Node` and `Edge` in a document.
+    ///
+    /// Edges refer to nodes by index in the document, either as `usize` or as references `(ref N)`.
+    pub fn from_document(doc: &Root) -> Result<Graph, String> {
+        let instances = value::read_instances(doc)?;
+        let nodes: Vec<&value::Instance> = instances.iter()
+            .filter(|ins| ins.class.as_str() == "Node").collect();
+        let node_of = |v: &Value| {
+            let ind = match v {
+                Value::Usize(i) => *i,
+                Value::Ref(r) => r.index,
+                _ => return None,
+            };
+            nodes.iter().position(|ins| ins.index == ind)
+        };
+        let edges = instances.iter().filter(|ins| ins.class.as_str() == "Edge")
+            .map(|ins| Edge::from_value(&ins.value, &node_of)
+                .map_err(|err| format!("[{}]: {}", ins.index, err)))
+            .collect::<Result<_, _>>()?;
+        Graph::new(nodes.iter().map(|ins| ins.value.to_data()).collect(), edges)
+    }
+
+    /// Loads a graph from a node table and an edge table, see `EDGE_FORMAT`.
+    pub fn from_tables(nodes: &Root, edges: &Root) -> Result<Graph, String> {
+        let (nodes, rows) = match (nodes, edges) {
+            (Root::Tup(nodes), Root::Tup(rows)) => (nodes.clone(), rows),
+            _ => return Err("Expected tables of nodes and edges".into()),
+        };
+        let schema = Schema::from_root(&parsing::parse_str(EDGE_FORMAT, &[])?)?;
+        let ty = TypeRef::Named(Arc::new("Edge".into()));
+        let edges = rows.iter().enumerate().map(|(i, row)| {
+            let path = path::Path::root().index(i);
+            let v = value::read_type_at(&schema, &ty, row, &path)?;
+            Edge::from_value(&v, &|x| x.as_usize()).map_err(|err| format!("{}: {}", path, err))
+        }).collect::<Result<_, _>>()?;
+        Graph::new(nodes, edges)
+    }
+
+    /// Returns the data of nodes.
+    pub fn nodes(&self) -> &[Root] {
+        &self.nodes
+    }
+
+    /// Returns the edges.
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Returns the nodes reachable from a node by a single edge.
+    ///
+    /// Returns no nodes for an unknown node.
+    pub fn neighbors(&self, node: usize) -> impl Iterator<Item = usize> + '_ {
+        self.adjacency.get(node).into_iter().flatten().map(|&(n, _)| n)
+    }
+
+    /// Returns nodes reachable from a node in breadth-first order, starting with the node.
+    ///
+    /// Returns an empty list for an unknown node.
+    pub fn bfs(&self, start: usize) -> Vec<usize> {
+        if start >= self.nodes.len() {return vec![]}
+        let mut visited = vec![false; self.nodes.len()];
+        let mut res = vec![];
+        let mut queue = VecDeque::from([start]);
+        visited[start] = true;
+        while let Some(n) = queue.pop_front() {
+            res.push(n);
+            for m in self.neighbors(n) {
+                if !visited[m] {
+                    visited[m] = true;
+                    queue.push_back(m);
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns nodes reachable from a node in depth-first order, starting with the node.
+    ///
+    /// Returns an empty list for an unknown node.
+    pub fn dfs(&self, start: usize) -> Vec<usize> {
+        if start >= self.nodes.len() {return vec![]}
+        let mut visited = vec![false; self.nodes.len()];
+        let mut res = vec![];
+        let mut stack = vec![start];
+        while let Some(n) = stack.pop() {
+            if visited[n] {continue}
+            visited[n] = true;
+            res.push(n);
+            // Reversed, such that neighbours are visited in edge order.
+            stack.extend(self.adjacency[n].iter().rev().map(|&(m, _)| m).filter(|&m| !visited[m]));
+        }
+        res
+    }
+
+    /// Returns a path with the fewest edges between two nodes, including both nodes.
+    ///
+    /// Returns `None` if there is no path, or if a node is unknown.
+    pub fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        if from >= self.nodes.len() || to >= self.nodes.len() {return None}
+        let mut parent: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::from([from]);
+        let mut visited = vec![false; self.nodes.len()];
+        visited[from] = true;
+        while let Some(n) = queue.pop_front() {
+            if n == to {
+                let mut path = vec![to];
+                while let Some(p) = parent[*path.last().unwrap()] {path.push(p)}
+                path.reverse();
+                return Some(path);
+            }
+            for m in self.neighbors(n) {
+                if !visited[m] {
+                    visited[m] = true;
+                    parent[m] = Some(n);
+                    queue.push_back(m);
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns groups of nodes connected by edges, ignoring their direction.
+    ///
+    /// Edges with direction `None` do not connect nodes.
+    /// Each component is sorted, and components are ordered by their first node.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut undirected = vec![vec![]; self.nodes.len()];
+        for e in self.edges.iter().filter(|e| e.dir != EdgeDir::None) {
+            undirected[e.from].push(e.to);
+            undirected[e.to].push(e.from);
+        }
+        let mut component: Vec<Option<usize>> = vec![None; self.nodes.len()];
+        let mut res: Vec<Vec<usize>> = vec![];
+        for start in 0..self.nodes.len() {
+            if component[start].is_some() {continue}
+            let mut stack = vec![start];
+            let mut nodes = vec![];
+            component[start] = Some(res.len());
+            while let Some(n) = stack.pop() {
+                nodes.push(n);
+                for &m in &undirected[n] {
+                    if component[m].is_none() {
+                        component[m] = Some(res.len());
+                        stack.push(m);
+                    }
+                }
+            }
+            nodes.sort();
+            res.push(nodes);
+        }
+        res
+    }
+
+    /// Returns a cycle following the direction of edges, if any.
+    ///
+    /// The cycle is returned as a list of nodes, where the last node has an edge to the first.
+    /// An edge with direction `Both` is not traversed back and forth, so it is not a cycle alone.
+    pub fn find_cycle(&self) -> Option<Vec<usize>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {New, Active, Done}
+
+        let mut state = vec![State::New; self.nodes.len()];
+        for start in 0..self.nodes.len() {
+            if state[start] != State::New {continue}
+            // Nodes on the current path with the edge used to reach them and the next neighbour.
+            let mut stack: Vec<(usize, Option<usize>, usize)> = vec![(start, None, 0)];
+            state[start] = State::Active;
+            while let Some(&mut (n, via, ref mut next)) = stack.last_mut() {
+                match self.adjacency[n].get(*next) {
+                    Some(&(m, edge)) => {
+                        *next += 1;
+                        if Some(edge) == via {continue}
+                        match state[m] {
+                            State::New => {
+                                state[m] = State::Active;
+                                stack.push((m, Some(edge), 0));
+                            }
+                            State::Active => {
+                                let i = stack.iter().position(|&(k, _, _)| k == m).unwrap();
+                                return Some(stack[i..].iter().map(|&(k, _, _)| k).collect());
+                            }
+                            State::Done => {}
+                        }
+                    }
+                    None => {
+                        state[n] = State::Done;
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the node table, e.g. `[Str("Donald"), Str("Dolly")]`.
+    pub fn node_table(&self) -> Root {
+        Root::Tup(self.nodes.clone())
+    }
+
+    /// Returns the edge table, e.g. `[[0, 1, EdgeDir::Left]]`, see `EDGE_FORMAT`.
+    pub fn edge_table(&self) -> Root {
+        Root::Tup(self.edges.iter().map(|e| e.to_root()).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document() {
+        let graph = Graph::from_document(&data(
+            include_str!("../source/test/graph/graph-with-instances.txt"))).unwrap();
+        assert_eq!(graph.nodes().len(), 3);
+        assert_eq!(graph.edges()[1], Edge {from: 2, to: 0, dir: EdgeDir::Right});
+        assert_eq!(graph.bfs(2), vec![2, 0, 1]);
+        assert_eq!(graph.bfs(1), vec![1, 0]);
+        assert_eq!(graph.shortest_path(2, 1), Some(vec![2, 0, 1]));
+        assert_eq!(graph.shortest_path(1, 2), None);
+        assert_eq!(graph.components(), vec![vec![0, 1, 2]]);
+        assert_eq!(graph.find_cycle(), None);
+
+        let refs = Graph::from_document(&data(
+            include_str!("../source/test/graph/graph-with-refs.txt"))).unwrap();
+        assert_eq!(refs.edges(), graph.edges());
+        let other = include_str!("../source/test/graph/graph-with-instances.txt")
+            .replace("[3, 4,", "[0, 4,");
+        assert_eq!(Graph::from_document(&data(&other)).unwrap_err(),
+            "[6]: Expected node in field `from` of edge");
+
+        let tables = Graph::from_tables(&graph.node_table(), &graph.edge_table()).unwrap();
+        assert_eq!(tables, graph);
+        let edge_data = data(include_str!("../source/test/graph/edge-data3.txt"));
+        assert_eq!(Graph::from_tables(&data(include_str!("../source/test/graph/node-data.txt")),
+            &edge_data).unwrap().edge_table(), edge_data);
+    }
+
+    #[test]
+    fn test_traversal() {
+        let edge = |from, to, dir| Edge {from, to, dir};
+        let nodes = (0..6).map(|i| Root::F64(i as f64)).collect::<Vec<_>>();
+        let graph = Graph::new(nodes.clone(), vec![
+            edge(0, 1, EdgeDir::Right),
+            edge(0, 2, EdgeDir::Right),
+            edge(1, 3, EdgeDir::Right),
+            edge(3, 2, EdgeDir::Left),
+            edge(4, 5, EdgeDir::None),
+        ]).unwrap();
+        assert_eq!(graph.bfs(0), vec![0, 1, 2, 3]);
+        assert_eq!(graph.dfs(0), vec![0, 1, 3, 2]);
+        assert_eq!(graph.shortest_path(0, 3), Some(vec![0, 1, 3]));
+        assert_eq!(graph.shortest_path(4, 5), None);
+        assert_eq!(graph.components(), vec![vec![0, 1, 2, 3], vec![4], vec![5]]);
+        assert_eq!(graph.find_cycle(), None);
+
+        // Unknown nodes.
+        assert_eq!(graph.neighbors(6).count(), 0);
+        assert_eq!(graph.bfs(6), vec![]);
+        assert_eq!(graph.dfs(usize::MAX), vec![]);
+        assert_eq!(graph.shortest_path(0, 6), None);
+        assert_eq!(graph.shortest_path(6, 0), None);
+
+        let graph = Graph::new(nodes.clone(), vec![
+            edge(0, 1, EdgeDir::Both),
+            edge(1, 2, EdgeDir::Right),
+            edge(3, 2, EdgeDir::Right),
+            edge(3, 1, EdgeDir::Left),
+        ]).unwrap();
+        assert_eq!(graph.find_cycle(), None);
+        let graph = Graph::new(nodes.clone(), vec![
+            edge(0, 1, EdgeDir::Right),
+            edge(1, 2, EdgeDir::Both),
+            edge(0, 2, EdgeDir::Left),
+        ]).unwrap();
+        assert_eq!(graph.find_cycle(), Some(vec![0, 1, 2]));
+
+        assert!(Graph::new(nodes, vec![edge(0, 6, EdgeDir::Both)]).is_err());
+    }
+}
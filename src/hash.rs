@@ -0,0 +1,279 @@
+//! Equality, ordering and content-addressed hashing of `Root` values.
+//!
+//! Floats are compared after normalizing them:
+//!
+//! - `-0.0` equals `0.0`
+//! - All NaN values are equal, and greater than every other number
+//!
+//! Other numbers are ordered as usual, such that `Root` implements `Eq`, `Ord` and `Hash`.
+//! Values of different variants are ordered by the declaration order of variants,
+//! e.g. `Ty` before `Str`.
+//!
+//! The stable hash depends only on content, not on how strings are shared,
+//! nor on the platform or version of Rust.
+//! A deduplicating builder shares strings and identical subtrees between values.
+
+use crate::*;
+use crate::symbol::Interner;
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+/// Normalizes a float, such that equal numbers have the same bits.
+pub fn normalize(x: f64) -> f64 {
+    if x.is_nan() {f64::NAN} else if x == 0.0 {0.0} else {x}
+}
+
+impl Root {
+    /// Returns the position of the variant in the declaration of `Root`.
+    fn variant_index(&self) -> u8 {
+        use Root::*;
+
+        match self {
+            Ty(_) => 0,
+            Str(_) => 1,
+            F64(_) => 2,
+            Bool(_) => 3,
+            Avatar(_) => 4,
+            Tup(_) => 5,
+            Field(_) => 6,
+            Params(_) => 7,
+            Bound(_) => 8,
+            Default(_) => 9,
+            Attr(_) => 10,
+            Struct {..} => 11,
+            Enum {..} => 12,
+            Instance {..} => 13,
+            InstanceTy {..} => 14,
+            Ref(_) => 15,
+        }
+    }
+}
+
+impl PartialEq for Root {
+    fn eq(&self, other: &Root) -> bool {self.cmp(other) == Ordering::Equal}
+}
+
+impl Eq for Root {}
+
+impl PartialOrd for Root {
+    fn partial_cmp(&self, other: &Root) -> Option<Ordering> {Some(self.cmp(other))}
+}
+
+impl Ord for Root {
+    fn cmp(&self, other: &Root) -> Ordering {
+        use Root::*;
+
+        match (self, other) {
+            (Ty(a), Ty(b)) | (Str(a), Str(b)) if Arc::ptr_eq(a, b) => Ordering::Equal,
+            (Ty(a), Ty(b)) | (Str(a), Str(b)) => a.cmp(b),
+            (F64(a), F64(b)) => normalize(*a).total_cmp(&normalize(*b)),
+            (Bool(a), Bool(b)) => a.cmp(b),
+            (Avatar(a), Avatar(b)) | (Field(a), Field(b)) | (Bound(a), Bound(b)) |
+            (Default(a), Default(b)) | (Attr(a), Attr(b)) => a.cmp(b),
+            (Tup(a), Tup(b)) | (Params(a), Params(b)) => a.cmp(b),
+            (Struct {name: a, fields: x}, Struct {name: b, fields: y}) |
+            (Enum {name: a, variants: x}, Enum {name: b, variants: y}) => (a, x).cmp(&(b, y)),
+            (Instance {class: a, data: x}, Instance {class: b, data: y}) => (a, x).cmp(&(b, y)),
+            (InstanceTy {ty: a, data: x}, InstanceTy {ty: b, data: y}) => (a, x).cmp(&(b, y)),
+            (Ref(a), Ref(b)) => a.cmp(b),
+            _ => self.variant_index().cmp(&other.variant_index()),
+        }
+    }
+}
+
+impl Hash for Root {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use Root::*;
+
+        // Lengths are written as `u64`, which does not depend on the platform.
+        fn str<H: Hasher>(s: &str, state: &mut H) {
+            state.write_u64(s.len() as u64);
+            state.write(s.as_bytes());
+        }
+        fn list<H: Hasher>(items: &[Root], state: &mut H) {
+            state.write_u64(items.len() as u64);
+            for it in items {it.hash(state)}
+        }
+        fn option<H: Hasher>(data: &Option<Box<Root>>, state: &mut H) {
+            match data {
+                None => state.write_u8(0),
+                Some(data) => {state.write_u8(1); data.hash(state)}
+            }
+        }
+
+        state.write_u8(self.variant_index());
+        match self {
+            Ty(s) | Str(s) => str(s, state),
+            F64(x) => state.write_u64(normalize(*x).to_bits()),
+            Bool(x) => state.write_u8(*x as u8),
+            Avatar(ab) | Field(ab) | Bound(ab) | Default(ab) | Attr(ab) => {ab.0.hash(state); ab.1.hash(state)}
+            Tup(items) | Params(items) => list(items, state),
+            Struct {name, fields: items} | Enum {name, variants: items} => {
+                name.hash(state);
+                list(items, state);
+            }
+            Instance {class, data} => {state.write_u64(*class as u64); option(data, state)}
+            InstanceTy {ty, data} => {ty.hash(state); option(data, state)}
+            Ref(ind) => state.write_u64(*ind as u64),
+        }
+    }
+}
+
+/// A 64 bit FNV-1a hasher, which gives the same hash on every platform.
+#[derive(Clone, Copy, Debug)]
+pub struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Fnv {Fnv(0xcbf2_9ce4_8422_2325)}
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {self.0}
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, x: u64) {self.write(&x.to_le_bytes())}
+}
+
+/// Returns a stable hash of the content of a value.
+///
+/// Equal values have the same hash, e.g. `-0.0` and `0.0`.
+pub fn stable_hash(root: &Root) -> u64 {
+    let mut state = Fnv::default();
+    root.hash(&mut state);
+    state.finish()
+}
+
+/// Builds values that share strings and identical subtrees.
+///
+/// Use `intern` for values that are stored as `Root`, which shares strings,
+/// or `shared` to get the same `Arc` for identical values.
+#[derive(Clone, Debug, Default)]
+pub struct Dedup {
+    strings: Interner,
+    nodes: HashSet<Arc<Root>>,
+}
+
+impl Dedup {
+    /// Creates an empty builder.
+    pub fn new() -> Dedup {Dedup::default()}
+
+    /// Returns a shared string with the same content.
+    pub fn string(&mut self, s: &Arc<String>) -> Arc<String> {
+        self.strings.share(s.clone())
+    }
+
+    /// Returns a copy of a value where equal strings share memory.
+    pub fn intern(&mut self, root: &Root) -> Root {
+        use Root::*;
+
+        let pair = |d: &mut Dedup, ab: &(Root, Root)| Box::new((d.intern(&ab.0), d.intern(&ab.1)));
+        let list = |d: &mut Dedup, items: &[Root]| items.iter().map(|it| d.intern(it)).collect();
+        match root {
+            Ty(s) => Ty(self.string(s)),
+            Str(s) => Str(self.string(s)),
+            F64(_) | Bool(_) | Ref(_) => root.clone(),
+            Avatar(ab) => Avatar(pair(self, ab)),
+            Field(ab) => Field(pair(self, ab)),
+            Bound(ab) => Bound(pair(self, ab)),
+            Default(ab) => Default(pair(self, ab)),
+            Attr(ab) => Attr(pair(self, ab)),
+            Tup(items) => Tup(list(self, items)),
+            Params(items) => Params(list(self, items)),
+            Struct {name, fields} =>
+                Struct {name: Box::new(self.intern(name)), fields: list(self, fields)},
+            Enum {name, variants} =>
+                Enum {name: Box::new(self.intern(name)), variants: list(self, variants)},
+            Instance {class, data} =>
+                Instance {class: *class, data: data.as_ref().map(|d| Box::new(self.intern(d)))},
+            InstanceTy {ty, data} => InstanceTy {
+                ty: Box::new(self.intern(ty)),
+                data: data.as_ref().map(|d| Box::new(self.intern(d))),
+            },
+        }
+    }
+
+    /// Returns a shared value, which is the same `Arc` for every equal value.
+    ///
+    /// E.g. the rows of a large table, where many rows are identical.
+    pub fn shared(&mut self, root: &Root) -> Arc<Root> {
+        if let Some(node) = self.nodes.get(root) {return node.clone()}
+        let node = Arc::new(self.intern(root));
+        self.nodes.insert(node.clone());
+        node
+    }
+
+    /// Returns the number of distinct strings.
+    pub fn strings(&self) -> usize {self.strings.len()}
+
+    /// Returns the number of distinct shared values.
+    pub fn len(&self) -> usize {self.nodes.len()}
+
+    /// Returns `true` if there are no shared values.
+    pub fn is_empty(&self) -> bool {self.nodes.is_empty()}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(Root::F64(0.0), Root::F64(-0.0));
+        assert_eq!(Root::F64(f64::NAN), Root::F64(-f64::NAN));
+        assert!(Root::F64(f64::INFINITY) < Root::F64(f64::NAN));
+        assert!(Root::F64(-1.0) < Root::F64(-0.0));
+        assert_eq!(stable_hash(&Root::F64(0.0)), stable_hash(&Root::F64(-0.0)));
+        assert_ne!(stable_hash(&Root::F64(0.0)), stable_hash(&Root::F64(1.0)));
+        assert!(Root::Str(Arc::new("b".into())) < Root::F64(0.0));
+        assert!(Root::Ref(0) > Root::Tup(vec![]));
+    }
+
+    #[test]
+    fn test_hash() {
+        let a = root_self();
+        let b = parsing::parse_str(&format!("{}", a), &[]).unwrap();
+        assert_eq!(a, b);
+        assert_eq!(stable_hash(&a), stable_hash(&b));
+        assert_eq!(stable_hash(&Root::Tup(vec![])), 0x04f0_d766_3d89_5b60);
+        let mut set = std::collections::BTreeSet::new();
+        set.insert(a);
+        set.insert(b);
+        set.insert(Root::Ref(1));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let table = parsing::parse_str(include_str!("../source/test/person/table-data.txt"), &[]).unwrap();
+        let rows = if let Root::Tup(rows) = &table {rows} else {panic!()};
+        let mut dedup = Dedup::new();
+        let interned = dedup.intern(&table);
+        assert_eq!(interned, table);
+        assert_eq!(dedup.strings(), 3);
+        let strings = |r: &Root| -> Vec<Arc<String>> {
+            let mut res = vec![];
+            fn walk(r: &Root, res: &mut Vec<Arc<String>>) {
+                if let Root::Str(s) = r {res.push(s.clone())}
+                let mut i = 0;
+                while let Some(child) = r.child(i) {walk(child, res); i += 1}
+            }
+            walk(r, &mut res);
+            res
+        };
+        let all = strings(&interned);
+        for s in &all {
+            assert!(all.iter().filter(|t| t == &s).all(|t| Arc::ptr_eq(t, s)));
+        }
+        let shared: Vec<Arc<Root>> = rows.iter().chain(rows).map(|r| dedup.shared(r)).collect();
+        assert_eq!(dedup.len(), rows.len());
+        assert!(Arc::ptr_eq(&shared[0], &shared[rows.len()]));
+    }
+}
@@ -0,0 +1,268 @@
+//! In-memory knowledge base over instances of many documents.
+//!
+//! Documents are ingested one at a time, registering their declarations by name
+//! and storing their instances per type.
+//! Secondary indices on fields answer point and range lookups
+//! without scanning the instances of a type:
+//!
+//! ```text
+//! let mut kb = KnowledgeBase::new();
+//! kb.ingest(&doc)?;
+//! kb.create_index("Person", "age")?;
+//! kb.get("Person", "age", 80.0)?;
+//! kb.range("Person", "age", Key::from(30.0)..Key::from(90.0))?;
+//! ```
+//!
+//! Indices are kept up to date when more documents are ingested.
+//! References `(ref N)` keep the index of the instance in the document it came from.
+
+use crate::*;
+use crate::schema::*;
+use crate::value::Value;
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Bound, RangeBounds};
+
+/// A key of a secondary index.
+///
+/// Numbers, both `f64` and `usize`, are ordered by `f64::total_cmp`,
+/// where `-0.0` equals `0.0` and all NaNs are equal.
+/// Unit variants are keyed by name, e.g. `Left`.
+#[derive(Clone, Debug)]
+pub enum Key {
+    /// A boolean.
+    Bool(bool),
+    /// A number.
+    Num(f64),
+    /// A string or name of unit variant.
+    Str(Arc<String>),
+}
+
+impl Key {
+    /// Returns the key of a value.
+    ///
+    /// Returns `None` for `Option::None` and values that can not be indexed,
+    /// e.g. lists or structs.
+    pub fn from_value(v: &Value) -> Option<Key> {
+        match v {
+            Value::Bool(x) => Some(Key::Bool(*x)),
+            Value::F64(x) => Some(Key::Num(*x)),
+            Value::Usize(x) => Some(Key::Num(*x as f64)),
+            Value::Str(x) => Some(Key::Str(x.clone())),
+            Value::Option(Some(x)) => Key::from_value(x),
+            Value::Variant(var) if var.data.is_none() => Some(Key::Str(var.name.clone())),
+            _ => None,
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            Key::Bool(_) => 0,
+            Key::Num(_) => 1,
+            Key::Str(_) => 2,
+        }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {self.cmp(other) == Ordering::Equal}
+}
+
+impl Eq for Key {}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {Some(self.cmp(other))}
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            (Key::Bool(a), Key::Bool(b)) => a.cmp(b),
+            (Key::Num(a), Key::Num(b)) => hash::normalize(*a).total_cmp(&hash::normalize(*b)),
+            (Key::Str(a), Key::Str(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl From<bool> for Key {
+    fn from(x: bool) -> Key {Key::Bool(x)}
+}
+
+impl From<f64> for Key {
+    fn from(x: f64) -> Key {Key::Num(x)}
+}
+
+impl From<&str> for Key {
+    fn from(x: &str) -> Key {Key::Str(Arc::new(x.into()))}
+}
+
+/// A secondary index from keys to positions among the instances of a type.
+#[derive(Clone, Debug, Default)]
+struct Index {
+    keys: BTreeMap<Key, Vec<usize>>,
+}
+
+impl Index {
+    fn insert(&mut self, field: &str, v: &Value, pos: usize) {
+        if let Some(key) = v.get(field).and_then(Key::from_value) {
+            self.keys.entry(key).or_default().push(pos);
+        }
+    }
+}
+
+/// Instances of many documents, with secondary indices on fields.
+#[derive(Clone, Debug, Default)]
+pub struct KnowledgeBase {
+    /// Declarations by name.
+    formats: HashMap<Arc<String>, Def>,
+    /// Instances by type name.
+    instances: HashMap<Arc<String>, Vec<Value>>,
+    /// Indices by type name and field.
+    indices: HashMap<(Arc<String>, Arc<String>), Index>,
+}
+
+impl KnowledgeBase {
+    /// Creates an empty knowledge base.
+    pub fn new() -> KnowledgeBase {
+        KnowledgeBase::default()
+    }
+
+    /// Ingests the declarations and instances of a document.
+    ///
+    /// Returns an error if a declaration differs from an earlier one of the same name.
+    /// Nothing is ingested when an error is returned.
+    pub fn ingest(&mut self, doc: &Root) -> Result<(), String> {
+        let schema = Schema::from_root(doc)?;
+        for def in &schema.defs {
+            match self.formats.get(def.name()) {
+                Some(old) if old != def =>
+                    return Err(format!("Declaration of `{}` differs from earlier declaration", def.name())),
+                _ => {}
+            }
+        }
+        let instances = value::read_instances(doc)?;
+        for def in schema.defs {
+            self.formats.insert(def.name().clone(), def);
+        }
+        for ins in instances {
+            let list = self.instances.entry(ins.class.clone()).or_default();
+            let pos = list.len();
+            for ((class, field), index) in &mut self.indices {
+                if *class == ins.class {index.insert(field, &ins.value, pos)}
+            }
+            list.push(ins.value);
+        }
+        Ok(())
+    }
+
+    /// Returns the declaration of a type by name.
+    pub fn format(&self, name: &str) -> Option<&Def> {
+        self.formats.get(&Arc::new(name.to_string()))
+    }
+
+    /// Returns the names of declared types, sorted.
+    pub fn formats(&self) -> Vec<&Arc<String>> {
+        let mut res: Vec<_> = self.formats.keys().collect();
+        res.sort();
+        res
+    }
+
+    /// Returns the instances of a type, in order of ingestion.
+    pub fn instances(&self, class: &str) -> &[Value] {
+        self.instances.get(&Arc::new(class.to_string())).map(|x| &x[..]).unwrap_or(&[])
+    }
+
+    /// Builds a secondary index on a field of a struct type.
+    ///
+    /// Instances where the field is `Option::None`, or not a number, string, bool
+    /// or unit variant, are left out of the index.
+    pub fn create_index(&mut self, class: &str, field: &str) -> Result<(), String> {
+        match self.format(class) {
+            Some(Def::Struct(s)) if s.fields.iter().any(|f| *f.name == field) => {}
+            Some(Def::Struct(_)) => return Err(format!("`{}` has no field `{}`", class, field)),
+            Some(Def::Enum(_)) => return Err(format!("Expected struct, found enum `{}`", class)),
+            None => return Err(format!("Unknown type `{}`", class)),
+        }
+        let mut index = Index::default();
+        for (pos, v) in self.instances(class).iter().enumerate() {
+            index.insert(field, v, pos);
+        }
+        self.indices.insert((Arc::new(class.into()), Arc::new(field.into())), index);
+        Ok(())
+    }
+
+    fn index(&self, class: &str, field: &str) -> Result<&Index, String> {
+        self.indices.get(&(Arc::new(class.into()), Arc::new(field.into())))
+            .ok_or_else(|| format!("No index on `{}.{}`", class, field))
+    }
+
+    /// Returns instances of a type where a field equals a key, using an index.
+    pub fn get(&self, class: &str, field: &str, key: impl Into<Key>) -> Result<Vec<&Value>, String> {
+        let index = self.index(class, field)?;
+        let instances = self.instances(class);
+        Ok(index.keys.get(&key.into()).map(|pos| pos.iter().map(|&i| &instances[i]).collect())
+            .unwrap_or_default())
+    }
+
+    /// Returns instances of a type where a field is within a range of keys, using an index.
+    ///
+    /// Instances are ordered by key, then by order of ingestion.
+    pub fn range<R: RangeBounds<Key>>(&self, class: &str, field: &str, range: R) -> Result<Vec<&Value>, String> {
+        let index = self.index(class, field)?;
+        let instances = self.instances(class);
+        let empty = match (range.start_bound(), range.end_bound()) {
+            (Bound::Included(a), Bound::Included(b)) => a > b,
+            (Bound::Included(a), Bound::Excluded(b)) |
+            (Bound::Excluded(a), Bound::Included(b)) |
+            (Bound::Excluded(a), Bound::Excluded(b)) => a >= b,
+            _ => false,
+        };
+        if empty {return Ok(vec![])}
+        Ok(index.keys.range(range).flat_map(|(_, pos)| pos.iter().map(|&i| &instances[i])).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_person() {
+        let format = include_str!("../source/test/person/person-defaults.txt");
+        let mut kb = KnowledgeBase::new();
+        kb.ingest(&data(&format!("[{}, (ins 0 [\"Donald\", \"Duck\", 80]), (ins 0 [\"Dolly\", \"Duck\", 30])]",
+            format))).unwrap();
+        kb.create_index("Person", "age").unwrap();
+        kb.create_index("Person", "last_name").unwrap();
+        kb.ingest(&data(&format!("[{}, (ins 0 [\"Scrooge\", \"McDuck\", 75])]", format))).unwrap();
+        assert_eq!(kb.formats(), vec![&Arc::new("Person".to_string())]);
+        assert_eq!(kb.instances("Person").len(), 3);
+
+        let first_names = |res: Vec<&Value>| -> Vec<String> {
+            res.iter().map(|v| v.get("first_name").unwrap().as_str().unwrap().into()).collect()
+        };
+        assert_eq!(first_names(kb.get("Person", "last_name", "Duck").unwrap()), vec!["Donald", "Dolly"]);
+        assert_eq!(first_names(kb.get("Person", "age", 75.0).unwrap()), vec!["Scrooge"]);
+        assert!(kb.get("Person", "age", 10.0).unwrap().is_empty());
+        assert_eq!(first_names(kb.range("Person", "age", Key::from(70.0)..).unwrap()), vec!["Scrooge", "Donald"]);
+        assert_eq!(first_names(kb.range("Person", "age", ..=Key::from(75.0)).unwrap()), vec!["Dolly", "Scrooge"]);
+        assert!(kb.range("Person", "age", Key::from(80.0)..Key::from(30.0)).unwrap().is_empty());
+        kb.ingest(&data(&format!("[{}, (ins 0 [\"Della\", \"Duck\", -0])]", format))).unwrap();
+        assert_eq!(first_names(kb.get("Person", "age", 0.0).unwrap()), vec!["Della"]);
+
+        assert!(kb.get("Person", "first_name", "Donald").is_err());
+        assert!(kb.create_index("Person", "weight").is_err());
+        assert!(kb.ingest(&data("[struct Person {name: Arc<String>}]")).is_err());
+    }
+
+    #[test]
+    fn test_graph() {
+        let mut kb = KnowledgeBase::new();
+        kb.ingest(&data(include_str!("../source/test/graph/graph-with-refs.txt"))).unwrap();
+        kb.create_index("Edge", "dir").unwrap();
+        assert_eq!(kb.get("Edge", "dir", "Both").unwrap(), vec![&kb.instances("Edge")[0]]);
+        assert!(kb.create_index("Node", "Str").is_err());
+    }
+}
@@ -22,10 +22,16 @@
 //!     Bool(bool),
 //!     Avatar(Box<(Self, Self)>),
 //!     Tup(Vec<Self>),
+//!     Field(Box<(Self, Self)>),
+//!     Params(Vec<Self>),
+//!     Bound(Box<(Self, Self)>),
+//!     Default(Box<(Self, Self)>),
+//!     Attr(Box<(Self, Self)>),
 //!     Struct {name: Box<Self>, fields: Vec<Self>},
 //!     Enum {name: Box<Self>, variants: Vec<Self>},
 //!     Instance {class: usize, data: Option<Box<Self>>},
 //!     InstanceTy {ty: Box<Self>, data: Option<Box<Self>>},
+//!     Ref(usize),
 //! }
 //! ```
 //!
@@ -42,10 +48,16 @@
 //!     Val(.T),
 //!     Avatar(Box<(Self<.T>, Self<.T>)>),
 //!     Tup(Vec<Self<.T>>),
+//!     Field(Box<(Self<.T>, Self<.T>)>),
+//!     Params(Vec<Self<.T>>),
+//!     Bound(Box<(Self<.T>, Self<.T>)>),
+//!     Default(Box<(Self<.T>, Self<.T>)>),
+//!     Attr(Box<(Self<.T>, Self<.T>)>),
 //!     Struct {name: Box<Self<.T>>, fields: Vec<Self<.T>>},
 //!     Enum {name: Box<Self<.T>>, variants: Vec<Self<.T>>},
 //!     Instance {class: usize, data: Option<Box<Self<.T>>>},
 //!     InstanceTy {ty: Box<Self<.T>>, data: Option<Box<Self<.T>>>},
+//!     Ref(usize),
 //! }
 //! ```
 //!
@@ -61,9 +73,34 @@ use std::sync::Arc;
 use std::fmt;
 
 pub mod parsing;
+pub mod path;
+pub mod schema;
+pub mod resolve;
+pub mod validate;
+pub mod value;
+pub mod query;
+pub mod diff;
+pub mod print;
+pub mod compat;
+pub mod merge;
+pub mod pattern;
+pub mod constraint;
+pub mod reference;
+pub mod graph;
+pub mod render;
+pub mod triples;
+pub mod kb;
+pub mod store;
+pub mod hash;
+pub mod symbol;
+pub mod arena;
+pub mod batch;
+pub mod visit;
+
+pub use diff::{diff, patch, Change};
 
 /// Root knowledge structure.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug)]
 pub enum Root {
     /// A type.
     Ty(Arc<String>),
@@ -78,6 +115,27 @@ pub enum Root {
     Avatar(Box<(Self, Self)>),
     /// Describes a tuple of variable number of items.
     Tup(Vec<Self>),
+    /// Describes a named field, e.g. `name: Arc<String>`,
+    /// where `name` is the key and `Arc<String>` is the value.
+    Field(Box<(Self, Self)>),
+    /// Describes multiple generic parameters or arguments, e.g. `<.K, .V>`.
+    ///
+    /// A single parameter is stored directly as the core of an avatar.
+    Params(Vec<Self>),
+    /// Describes a generic parameter with bounds, e.g. `.T: Ord`,
+    /// where `.T` is the parameter and `Ord` is the bound.
+    ///
+    /// Multiple bounds are a tuple, e.g. `.T: Ord + Clone`.
+    Bound(Box<(Self, Self)>),
+    /// Describes a type with a default value, e.g. `f64 = 0`.
+    ///
+    /// Used as the value of a field in a struct declaration, e.g. `age: f64 = 0`.
+    Default(Box<(Self, Self)>),
+    /// Describes an attribute with the value it applies to, e.g. `#[range(0, 150)] f64`.
+    ///
+    /// Used as the value of a field in a struct declaration, e.g. `#[non_empty] name: String`.
+    /// Attributes without arguments are strings, otherwise avatars with a tuple as core.
+    Attr(Box<(Self, Self)>),
     /// Describes a struct.
     Struct {
         /// Name of struct.
@@ -106,52 +164,69 @@ pub enum Root {
         /// Data of instance, if any.
         data: Option<Box<Self>>
     },
+    /// A reference to an instance by its index in the document, e.g. `(ref 3)`.
+    ///
+    /// Used as data of `Ref<T>`, where the instance must have type `T`.
+    Ref(usize),
 }
 
 impl Root {
     /// The `Self` type.
     pub fn ty_self() -> Root {
-        Root::Ty(Arc::new("Self".into()))
+        Root::Ty(symbol::builtin("Self"))
     }
 
     /// The `Arc` type.
     pub fn ty_arc() -> Root {
-        Root::Ty(Arc::new("Arc".into()))
+        Root::Ty(symbol::builtin("Arc"))
     }
 
     /// The `String` type.
     pub fn ty_string() -> Root {
-        Root::Ty(Arc::new("String".into()))
+        Root::Ty(symbol::builtin("String"))
     }
 
     /// The `f64` type.
     pub fn ty_f64() -> Root {
-        Root::Ty(Arc::new("f64".into()))
+        Root::Ty(symbol::builtin("f64"))
     }
 
     /// The `bool` type.
     pub fn ty_bool() -> Root {
-        Root::Ty(Arc::new("bool".into()))
+        Root::Ty(symbol::builtin("bool"))
     }
 
     /// The `Box` type.
     pub fn ty_box() -> Root {
-        Root::Ty(Arc::new("box".into()))
+        Root::Ty(symbol::builtin("box"))
     }
 
     /// The `usize` type.
     pub fn ty_usize() -> Root {
-        Root::Ty(Arc::new("usize".into()))
+        Root::Ty(symbol::builtin("usize"))
     }
 
     /// The `Option` type.
     pub fn ty_option() -> Root {
-        Root::Ty(Arc::new("Option".into()))
+        Root::Ty(symbol::builtin("Option"))
     }
 
     /// The `Vec` type.
     pub fn ty_vec() -> Root {
-        Root::Ty(Arc::new("Vec".into()))
+        Root::Ty(symbol::builtin("Vec"))
+    }
+
+    /// Creates a named field.
+    pub fn field(name: &str, value: Root) -> Root {
+        Root::Field(Box::new((Root::Str(symbol::intern(name)), value)))
+    }
+
+    /// Returns the name and value of a field with a string key.
+    pub fn as_field(&self) -> Option<(&Arc<String>, &Root)> {
+        if let Root::Field(kv) = self {
+            if let Root::Str(name) = &kv.0 {return Some((name, &kv.1))}
+        }
+        None
     }
 }
 
@@ -160,32 +235,32 @@ pub fn root_self() -> Root {
     use Root::*;
 
     Enum {
-        name: Box::new(Str(Arc::new("Root".into()))),
+        name: Box::new(Str(symbol::intern("Root"))),
         variants: vec![
             Avatar(Box::new((
-                Str(Arc::new("Ty".into())),
+                Str(symbol::intern("Ty")),
                 Avatar(Box::new((
                     Root::ty_arc(),
                     Root::ty_string(),
                 ))),
             ))),
             Avatar(Box::new((
-                Str(Arc::new("Str".into())),
+                Str(symbol::intern("Str")),
                 Avatar(Box::new((
                     Root::ty_arc(),
                     Root::ty_string(),
                 ))),
             ))),
             Avatar(Box::new((
-                Str(Arc::new("F64".into())),
+                Str(symbol::intern("F64")),
                 Root::ty_f64(),
             ))),
             Avatar(Box::new((
-                Str(Arc::new("Bool".into())),
+                Str(symbol::intern("Bool")),
                 Root::ty_bool(),
             ))),
             Avatar(Box::new((
-                Str(Arc::new("Avatar".into())),
+                Str(symbol::intern("Avatar")),
                 Avatar(Box::new((
                     Root::ty_box(),
                     Tup(vec![
@@ -195,59 +270,106 @@ pub fn root_self() -> Root {
                 ))),
             ))),
             Avatar(Box::new((
-                Str(Arc::new("Tup".into())),
+                Str(symbol::intern("Tup")),
                 Avatar(Box::new((
                     Root::ty_vec(),
                     Root::ty_self(),
                 ))),
             ))),
+            Avatar(Box::new((
+                Str(symbol::intern("Field")),
+                Avatar(Box::new((
+                    Root::ty_box(),
+                    Tup(vec![
+                        Root::ty_self(),
+                        Root::ty_self(),
+                    ]),
+                ))),
+            ))),
+            Avatar(Box::new((
+                Str(symbol::intern("Params")),
+                Avatar(Box::new((
+                    Root::ty_vec(),
+                    Root::ty_self(),
+                ))),
+            ))),
+            Avatar(Box::new((
+                Str(symbol::intern("Bound")),
+                Avatar(Box::new((
+                    Root::ty_box(),
+                    Tup(vec![
+                        Root::ty_self(),
+                        Root::ty_self(),
+                    ]),
+                ))),
+            ))),
+            Avatar(Box::new((
+                Str(symbol::intern("Default")),
+                Avatar(Box::new((
+                    Root::ty_box(),
+                    Tup(vec![
+                        Root::ty_self(),
+                        Root::ty_self(),
+                    ]),
+                ))),
+            ))),
+            Avatar(Box::new((
+                Str(symbol::intern("Attr")),
+                Avatar(Box::new((
+                    Root::ty_box(),
+                    Tup(vec![
+                        Root::ty_self(),
+                        Root::ty_self(),
+                    ]),
+                ))),
+            ))),
             Struct {
-                name: Box::new(Str(Arc::new("Struct".into()))),
+                name: Box::new(Str(symbol::intern("Struct"))),
                 fields: vec![
-                    Tup(vec![
-                        Str(Arc::new("name".into())),
+                    Field(Box::new((
+                        Str(symbol::intern("name")),
                         Avatar(Box::new((
                             Root::ty_box(),
                             Root::ty_self(),
                         ))),
-                    ]),
-                    Tup(vec![
-                        Str(Arc::new("fields".into())),
+                    ))),
+                    Field(Box::new((
+                        Str(symbol::intern("fields")),
                         Avatar(Box::new((
                             Root::ty_vec(),
                             Root::ty_self(),
                         ))),
-                    ]),
+                    ))),
                 ],
             },
             Struct {
-                name: Box::new(Str(Arc::new("Enum".into()))),
+                name: Box::new(Str(symbol::intern("Enum"))),
                 fields: vec![
-                    Tup(vec![
-                        Str(Arc::new("name".into())),
+                    Field(Box::new((
+                        Str(symbol::intern("name")),
                         Avatar(Box::new((
                             Root::ty_box(),
                             Root::ty_self(),
                         ))),
-                    ]),
-                    Tup(vec![
-                        Str(Arc::new("variants".into())),
+                    ))),
+                    Field(Box::new((
+                        Str(symbol::intern("variants")),
                         Avatar(Box::new((
                             Root::ty_vec(),
                             Root::ty_self(),
                         ))),
-                    ]),
+                    ))),
                 ],
             },
             Struct {
-                name: Box::new(Str(Arc::new("Instance".into()))),
+                name: Box::new(Str(symbol::intern("Instance"))),
                 fields: vec![
-                    Tup(vec![
-                        Str(Arc::new("class".into())),
+                    Field(Box::new((
+                        Str(symbol::intern("class")),
                         Root::ty_usize(),
-                    ]),
-                    Tup(vec![
-                        Str(Arc::new("data".into())),
+                    ))),
+                    Field(Box::new((
+                        Str(symbol::intern("data")),
                         Avatar(Box::new((
                             Root::ty_option(),
                             Avatar(Box::new((
@@ -255,21 +377,21 @@ pub fn root_self() -> Root {
                                 Root::ty_self(),
                             ))),
                         ))),
-                    ]),
+                    ))),
                 ],
             },
             Struct {
-                name: Box::new(Str(Arc::new("InstanceTy".into()))),
+                name: Box::new(Str(symbol::intern("InstanceTy"))),
                 fields: vec![
-                    Tup(vec![
-                        Str(Arc::new("ty".into())),
+                    Field(Box::new((
+                        Str(symbol::intern("ty")),
                         Avatar(Box::new((
                             Root::ty_box(),
                             Root::ty_self(),
                         ))),
-                    ]),
-                    Tup(vec![
-                        Str(Arc::new("data".into())),
+                    ))),
+                    Field(Box::new((
+                        Str(symbol::intern("data")),
                         Avatar(Box::new((
                             Root::ty_option(),
                             Avatar(Box::new((
@@ -277,9 +399,13 @@ pub fn root_self() -> Root {
                                 Root::ty_self(),
                             ))),
                         ))),
-                    ]),
+                    ))),
                 ]
-            }
+            },
+            Avatar(Box::new((
+                Str(symbol::intern("Ref")),
+                Root::ty_usize(),
+            ))),
         ]
     }
 }
@@ -293,7 +419,19 @@ impl fmt::Display for Root {
             Str(s) => write!(w, "{:?}", s)?,
             F64(v) => write!(w, "{}", v)?,
             Bool(b) => write!(w, "{}", b)?,
+            Ref(ind) => write!(w, "(ref {})", ind)?,
             Avatar(ab) => write!(w, "(ava {} {})", ab.0, ab.1)?,
+            Field(kv) => write!(w, "(field {} {})", kv.0, kv.1)?,
+            Bound(pb) => write!(w, "(bound {} {})", pb.0, pb.1)?,
+            Default(tv) => write!(w, "(default {} {})", tv.0, tv.1)?,
+            Attr(av) => write!(w, "(attr {} {})", av.0, av.1)?,
+            Params(v) => {
+                write!(w, "(params ")?;
+                for i in v {
+                    write!(w, "{} ", i)?;
+                }
+                write!(w, ")")?;
+            }
             Tup(v) => {
                 write!(w, "(tup ")?;
                 for i in v {
@@ -331,6 +469,8 @@ impl fmt::Display for Root {
 }
 
 /// Joins format with data into a single structure.
+///
+/// The data is validated, and omitted fields are filled in, see `validate::complete`.
 pub fn join_format_data(format: &str, data: &str) -> Result<String, String> {
     let format = parsing::parse_str(format, &[])?;
     let data = parsing::parse_str(data, &[])?;
@@ -342,7 +482,16 @@ pub fn join_format_data(format: &str, data: &str) -> Result<String, String> {
             res.push(Root::Instance {class: 0, data: Some(Box::new(rec))});
         }
     }
-    Ok(format!("{}", Root::Tup(res)))
+    Ok(format!("{}", validate::complete(&Root::Tup(res))?))
+}
+
+/// Parses a document in tests, panicking with the error.
+#[cfg(test)]
+pub(crate) fn data(src: &str) -> Root {
+    match parsing::parse_str(src, &[]) {
+        Ok(x) => x,
+        Err(err) => panic!("ERROR:\n{}", err),
+    }
 }
 
 #[cfg(test)]
@@ -370,6 +519,22 @@ mod tests {
             include_str!("../source/test/person/table-data.txt")
         ).unwrap(), format!("{}", format_with_data));
 
+        let with_defaults = join_format_data(
+            include_str!("../source/test/person/person-defaults.txt"),
+            include_str!("../source/test/person/table-data-partial.txt")
+        ).unwrap();
+        assert_eq!(parsing::parse_str(&with_defaults, &[]).unwrap(), parsing::parse_str(r#"[
+            struct Person {
+                first_name: Arc<String>,
+                last_name: Arc<String>,
+                age: f64 = 0,
+                nick: Option<Arc<String>>,
+            },
+            (ins 0 ["Donald", "Duck", 90, Option::None]),
+            (ins 0 ["Dolly", "Duck", 0, Option::None]),
+            (ins 0 ["Scrooge", "McDuck", 0, "Uncle"]),
+        ]"#, &[]).unwrap());
+
         let _meta = match parsing::parse_str(
             include_str!("../source/test/person/meta.txt"), &[]) {
             Ok(x) => x,
@@ -377,6 +542,42 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_field() {
+        use Root::*;
+
+        let person = match parsing::parse_str(
+            include_str!("../source/test/person/person.txt"), &[]) {
+            Ok(x) => x,
+            Err(err) => panic!("ERROR:\n{}", err),
+        };
+        if let Struct {fields, ..} = &person {
+            let names: Vec<&str> = fields.iter()
+                .map(|f| f.as_field().unwrap().0.as_str()).collect();
+            assert_eq!(names, vec!["first_name", "last_name"]);
+        } else {
+            panic!("Expected struct");
+        }
+        let printed = parsing::parse_str(&format!("{}", person), &[]).unwrap();
+        assert_eq!(printed, person);
+
+        let pair = parsing::parse_str(r#"["from", 0]"#, &[]).unwrap();
+        assert_eq!(pair, Tup(vec![Str(Arc::new("from".into())), F64(0.0)]));
+        let named = parsing::parse_str("[from: 0]", &[]).unwrap();
+        assert_eq!(named, Tup(vec![Root::field("from", F64(0.0))]));
+
+        // Names starting with `true` or `false` are not booleans.
+        let flag = parsing::parse_str("struct Flag {false_positive: bool, true_north: bool}", &[]).unwrap();
+        if let Struct {fields, ..} = &flag {
+            let names: Vec<&str> = fields.iter()
+                .map(|f| f.as_field().unwrap().0.as_str()).collect();
+            assert_eq!(names, vec!["false_positive", "true_north"]);
+        }
+        let data = parsing::parse_str("[false_positive: false, true_north: true]", &[]).unwrap();
+        assert_eq!(data, Tup(vec![Root::field("false_positive", Bool(false)),
+            Root::field("true_north", Bool(true))]));
+    }
+
     #[test]
     fn test_graph() {
         use Root::*;
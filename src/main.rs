@@ -0,0 +1,128 @@
+//! Command line interface for Iknow documents.
+
+use std::process;
+
+const USAGE: &str = "Usage:
+  iknow query <file> <query>    Evaluate a query against a document
+  iknow diff <old> <new>        Print changes between two documents
+  iknow merge <base> <ours> <theirs> [<out>] [--key <Type.field>]...
+                                Three-way merge of documents
+  iknow render <types|graph> <dot|mermaid> <file> [<out>]
+                                Render declarations or Node/Edge data as a diagram
+  iknow triples <tsv|nt> <file> Print instances as triples
+  iknow check <dir>             Parse and validate all files in a directory, in parallel
+  iknow import <format> <tsv|nt> <triples>
+                                Read triples into instances of a format
+";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(out) => print!("{}", out),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<String, String> {
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    match args[..] {
+        ["query", file, q] => {
+            let doc = iknow::parsing::parse(file, &[])?;
+            Ok(format!("{}\n", iknow::query::query(&doc, q)?))
+        }
+        ["diff", old, new] => {
+            let old = iknow::parsing::parse(old, &[])?;
+            let new = iknow::parsing::parse(new, &[])?;
+            let mut out = String::new();
+            for change in iknow::diff(&old, &new) {
+                out.push_str(&format!("{}\n", change));
+            }
+            Ok(out)
+        }
+        ["merge", base, ours, theirs, ref rest @ ..] => {
+            let mut out = None;
+            let mut keys = vec![];
+            let mut rest = rest.iter();
+            while let Some(&arg) = rest.next() {
+                if arg == "--key" {
+                    let key = rest.next().ok_or_else(|| USAGE.to_string())?;
+                    keys.push(iknow::merge::Key::parse(key)?);
+                } else if out.is_none() {
+                    out = Some(arg);
+                } else {
+                    return Err(USAGE.into());
+                }
+            }
+            let base = iknow::parsing::parse(base, &[])?;
+            let ours = iknow::parsing::parse(ours, &[])?;
+            let theirs = iknow::parsing::parse(theirs, &[])?;
+            let merged = iknow::merge::merge(&base, &ours, &theirs, &keys);
+            let text = iknow::print::document(&merged.root);
+            let text = match out {
+                Some(file) => {
+                    std::fs::write(file, text).map_err(|err| format!("{}: {}", file, err))?;
+                    String::new()
+                }
+                None => text,
+            };
+            if merged.conflicts.is_empty() {
+                Ok(text)
+            } else {
+                let mut err = format!("{} conflict(s):", merged.conflicts.len());
+                for c in &merged.conflicts {
+                    err.push_str(&format!("\n  {}", c));
+                }
+                Err(err)
+            }
+        }
+        ["render", kind, syntax, file, ref out @ ..] if out.len() <= 1 => {
+            use iknow::{graph::Graph, render, schema::Schema};
+
+            let doc = iknow::parsing::parse(file, &[])?;
+            let text = match (kind, syntax) {
+                ("types", "dot") => render::types_dot(&Schema::from_root(&doc)?),
+                ("types", "mermaid") => render::types_mermaid(&Schema::from_root(&doc)?),
+                ("graph", "dot") => render::graph_dot(&Graph::from_document(&doc)?),
+                ("graph", "mermaid") => render::graph_mermaid(&Graph::from_document(&doc)?),
+                _ => return Err(USAGE.into()),
+            };
+            match out.first() {
+                Some(out) => {
+                    std::fs::write(out, text).map_err(|err| format!("{}: {}", out, err))?;
+                    Ok(String::new())
+                }
+                None => Ok(text),
+            }
+        }
+        ["check", dir] => {
+            let report = iknow::batch::check_dir(std::path::Path::new(dir), 0)?;
+            if report.is_ok() {Ok(format!("{}\n", report))} else {Err(format!("{}", report))}
+        }
+        ["triples", syntax, file] => {
+            use iknow::triples;
+
+            let triples = triples::to_triples(&iknow::parsing::parse(file, &[])?)?;
+            match syntax {
+                "tsv" => Ok(triples::to_tsv(&triples)),
+                "nt" => Ok(triples::to_ntriples(&triples, triples::BASE)),
+                _ => Err(USAGE.into()),
+            }
+        }
+        ["import", format, syntax, file] => {
+            use iknow::triples;
+
+            let format = iknow::parsing::parse(format, &[])?;
+            let text = std::fs::read_to_string(file).map_err(|err| format!("{}: {}", file, err))?;
+            let triples = match syntax {
+                "tsv" => triples::from_tsv(&text)?,
+                "nt" => triples::from_ntriples(&text, triples::BASE)?,
+                _ => return Err(USAGE.into()),
+            };
+            Ok(format!("{}\n", triples::from_triples(&format, &triples)?))
+        }
+        _ => Err(USAGE.into()),
+    }
+}
@@ -0,0 +1,716 @@
+//! Three-way merge of documents.
+//!
+//! Changes made in our and their version relative to a common base are combined:
+//!
+//! - Declarations are matched by name, and so are their fields and variants
+//! - Instances are matched per type, by the value of a key field if one is given,
+//!   otherwise by position among the instances of the same type
+//! - Instances added in both versions are kept from both
+//! - Other items are matched by position
+//!
+//! A change made in only one version is taken from that version.
+//! When both versions change the same node differently, a conflict is reported
+//! and our version of the node is kept.
+//!
+//! References `(ref N)` are renumbered to the indices of instances in the merged document.
+//! A reference to an instance that is not in the merged document is reported as a conflict.
+//!
+//! The merged document is checked against the merged declarations,
+//! and every instance that does not conform to its type is reported as a conflict.
+//!
+//! Items of a document keep our order, and an item added by them is placed after the item
+//! preceding it in their version. Use `print::document` to write the result in source syntax.
+
+use crate::*;
+use crate::diff::{is_record, key};
+use crate::path::{find_decl, Path};
+use crate::schema::{Schema, TypeRef};
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+/// A key field used to match instances of a type, e.g. `Person.first_name`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    /// Name of type.
+    pub ty: String,
+    /// Name of field.
+    pub field: String,
+}
+
+impl Key {
+    /// Parses a key, e.g. `Person.first_name`.
+    pub fn parse(text: &str) -> Result<Key, String> {
+        match text.split_once('.') {
+            Some((ty, field)) if !ty.is_empty() && !field.is_empty() =>
+                Ok(Key {ty: ty.into(), field: field.into()}),
+            _ => Err(format!("Expected key of the form `Type.field`, found `{}`", text)),
+        }
+    }
+}
+
+/// A node changed differently in our and their version.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    /// Location in our version, or in their version if we removed the node.
+    ///
+    /// For an invalid instance, this is the location in the merged document.
+    pub path: Path,
+    /// Node in base version, if any.
+    pub base: Option<Root>,
+    /// Node in our version, or `None` if removed.
+    pub ours: Option<Root>,
+    /// Node in their version, or `None` if removed.
+    pub theirs: Option<Root>,
+    /// Error of an instance that is invalid in the merged document.
+    pub error: Option<String>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if let Some(err) = &self.error {return write!(w, "{}", err)}
+        let side = |x: &Option<Root>| match x {
+            Some(x) => format!("`{}`", print::source(x)),
+            None => "removed".into(),
+        };
+        write!(w, "{}: ours {} conflicts with theirs {}", self.path, side(&self.ours), side(&self.theirs))
+    }
+}
+
+/// The result of a merge.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Merged {
+    /// Merged document, using our version where there are conflicts.
+    pub root: Root,
+    /// Conflicts between our and their version.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Merges our and their version of a document, relative to a common base version.
+pub fn merge(base: &Root, ours: &Root, theirs: &Root, keys: &[Key]) -> Merged {
+    let mut merger = Merger {keys, conflicts: vec![]};
+    if ours == theirs || base == theirs {return Merged {root: ours.clone(), conflicts: vec![]}}
+    if base == ours {return Merged {root: theirs.clone(), conflicts: vec![]}}
+    let root = match (base, ours, theirs) {
+        (Root::Tup(b), Root::Tup(o), Root::Tup(t)) if [b, o, t].iter().any(|items| is_document(items)) => {
+            let items = merger.document(b, o, t);
+            merger.check(&items);
+            Root::Tup(items)
+        }
+        (Root::Tup(b), Root::Tup(o), Root::Tup(t)) =>
+            Root::Tup(merger.positional(b, o, t, &Path::root(), true)),
+        _ => merger.node(base, ours, theirs, &Path::root()),
+    };
+    Merged {root, conflicts: merger.conflicts}
+}
+
+struct Merger<'a> {
+    keys: &'a [Key],
+    conflicts: Vec<Conflict>,
+}
+
+/// How an instance is matched with instances in other versions.
+#[derive(Debug, PartialEq)]
+enum Ident {
+    /// Value of key field.
+    Key(Root),
+    /// Position among instances of the same type.
+    Rank(usize),
+}
+
+/// An item of a merged document, which is placed by its order in our and their version.
+enum Slot {
+    /// Index among merged declarations.
+    Decl(usize),
+    /// Index among matched instances.
+    Ins(usize),
+}
+
+/// An instance in a document.
+struct Ins<'a> {
+    index: usize,
+    class: Arc<String>,
+    ident: Ident,
+    item: &'a Root,
+}
+
+impl<'a> Merger<'a> {
+    fn node(&mut self, base: &Root, ours: &Root, theirs: &Root, path: &Path) -> Root {
+        use Root::*;
+
+        if ours == theirs || base == theirs {return ours.clone()}
+        if base == ours {return theirs.clone()}
+        match (base, ours, theirs) {
+            (Tup(b), Tup(o), Tup(t)) if is_record(b) && is_record(o) && is_record(t) =>
+                Tup(self.named(b, o, t, path)),
+            (Tup(b), Tup(o), Tup(t)) => Tup(self.positional(b, o, t, path, false)),
+            (Params(b), Params(o), Params(t)) => Params(self.positional(b, o, t, path, false)),
+            (Struct {name: nb, fields: b}, Struct {name: no, fields: o}, Struct {name: nt, fields: t})
+            if nb == no && no == nt && is_record(b) && is_record(o) && is_record(t) =>
+                Struct {name: no.clone(), fields: self.named(b, o, t, path)},
+            (Enum {name: nb, variants: b}, Enum {name: no, variants: o}, Enum {name: nt, variants: t})
+            if nb == no && no == nt && is_named(b) && is_named(o) && is_named(t) =>
+                Enum {name: no.clone(), variants: self.named(b, o, t, path)},
+            (Avatar(b), Avatar(o), Avatar(t)) if b.0 == o.0 && o.0 == t.0 => {
+                let path = if let Str(name) = &o.0 {path.name(name)} else {path.index(1)};
+                Avatar(Box::new((o.0.clone(), self.node(&b.1, &o.1, &t.1, &path))))
+            }
+            (Field(b), Field(o), Field(t)) if b.0 == o.0 && o.0 == t.0 =>
+                Field(Box::new((o.0.clone(), self.node(&b.1, &o.1, &t.1, &path.index(1))))),
+            (InstanceTy {ty: tb, data: Some(b)}, InstanceTy {ty: to, data: Some(o)},
+                InstanceTy {ty: tt, data: Some(t)}) if tb == to && to == tt => InstanceTy {
+                ty: to.clone(),
+                data: Some(Box::new(self.node(b, o, t, &path.name(&Arc::new("data".into()))))),
+            },
+            _ => self.conflict(path, Some(base), Some(ours), Some(theirs)),
+        }
+    }
+
+    /// Merges items by name, keeping our order and appending items added by them.
+    fn named(&mut self, base: &[Root], ours: &[Root], theirs: &[Root], path: &Path) -> Vec<Root> {
+        let mut res = vec![];
+        for o in ours {
+            let name = decl_key(o).unwrap();
+            let path = path.name(name);
+            match (find_named(base, name), find_named(theirs, name)) {
+                (Some(b), Some(t)) => res.push(self.item(b, o, t, &path)),
+                // Removed by them.
+                (Some(b), None) => if o != b {res.push(self.conflict(&path, Some(b), Some(o), None))},
+                // Added by both.
+                (None, Some(t)) => if o == t {res.push(o.clone())}
+                    else {res.push(self.conflict(&path, None, Some(o), Some(t)))},
+                (None, None) => res.push(o.clone()),
+            }
+        }
+        for t in theirs {
+            let name = decl_key(t).unwrap();
+            if find_named(ours, name).is_some() {continue}
+            match find_named(base, name) {
+                // Removed by us.
+                Some(b) => if t != b {self.conflict(&path.name(name), Some(b), None, Some(t));},
+                None => res.push(t.clone()),
+            }
+        }
+        res
+    }
+
+    /// Merges an item matched by name.
+    fn item(&mut self, base: &Root, ours: &Root, theirs: &Root, path: &Path) -> Root {
+        use Root::*;
+
+        match (base, ours, theirs) {
+            // The path of a field refers to its value.
+            (Field(b), Field(o), Field(t)) =>
+                Field(Box::new((o.0.clone(), self.node(&b.1, &o.1, &t.1, path)))),
+            (Avatar(b), Avatar(o), Avatar(t)) =>
+                Avatar(Box::new((o.0.clone(), self.node(&b.1, &o.1, &t.1, &path.index(1))))),
+            _ => self.node(base, ours, theirs, path),
+        }
+    }
+
+    /// Merges items by position.
+    ///
+    /// Items added at the end by both versions are concatenated when `concat` is `true`.
+    fn positional(
+        &mut self,
+        base: &[Root],
+        ours: &[Root],
+        theirs: &[Root],
+        path: &Path,
+        concat: bool,
+    ) -> Vec<Root> {
+        let mut res = vec![];
+        for (i, b) in base.iter().enumerate() {
+            let path = path.index(i);
+            match (ours.get(i), theirs.get(i)) {
+                (Some(o), Some(t)) => res.push(self.node(b, o, t, &path)),
+                (Some(o), None) => if o != b {res.push(self.conflict(&path, Some(b), Some(o), None))},
+                (None, Some(t)) => if t != b {self.conflict(&path, Some(b), None, Some(t));},
+                (None, None) => {}
+            }
+        }
+        let n = base.len();
+        let added_ours = ours.get(n..).unwrap_or(&[]);
+        let added_theirs = theirs.get(n..).unwrap_or(&[]);
+        if added_ours == added_theirs || added_theirs.is_empty() {
+            res.extend(added_ours.iter().cloned());
+        } else if added_ours.is_empty() || concat {
+            res.extend(added_ours.iter().cloned());
+            res.extend(added_theirs.iter().cloned());
+        } else {
+            res.extend(added_ours.iter().cloned());
+            self.conflict(&path.index(n), None,
+                Some(&Root::Tup(added_ours.to_vec())), Some(&Root::Tup(added_theirs.to_vec())));
+        }
+        res
+    }
+
+    /// Merges the declarations and instances of a document.
+    ///
+    /// Items are kept in our order, and an item added by them is placed after the item
+    /// preceding it in their version.
+    /// Instances refer to declarations and references refer to instances by index,
+    /// which are renumbered from the indices in every version to the indices in the result.
+    fn document(&mut self, base: &[Root], ours: &[Root], theirs: &[Root]) -> Vec<Root> {
+        let decls = |items: &[Root]| -> Vec<Root> {
+            items.iter().filter(|it| decl_key(it).is_some()).cloned().collect()
+        };
+        let merged = self.named(&decls(base), &decls(ours), &decls(theirs), &Path::root());
+        let (b, o, t) = (self.instances(base), self.instances(ours), self.instances(theirs));
+        // Matched instances of base, our and their version.
+        let mut matched: Vec<[Option<&Ins>; 3]> = vec![];
+        let mut used = vec![false; t.len()];
+        for x in &o {
+            let path = Path::root().index(x.index);
+            let ti = t.iter().position(|y| y.class == x.class && y.ident == x.ident);
+            match (find_ins(&b, x), ti) {
+                (Some(bx), Some(ti)) => {
+                    used[ti] = true;
+                    matched.push([Some(bx), Some(x), Some(&t[ti])]);
+                }
+                // Removed by them.
+                (Some(bx), None) => if data(bx.item) != data(x.item) {
+                    self.conflict(&path, Some(bx.item), Some(x.item), None);
+                    matched.push([Some(bx), Some(x), None]);
+                }
+                // Added by both, which is a conflict only for the same key.
+                (None, Some(ti)) => {
+                    if data(t[ti].item) == data(x.item) {
+                        used[ti] = true;
+                    } else if matches!(x.ident, Ident::Key(_)) {
+                        used[ti] = true;
+                        self.conflict(&path, None, Some(x.item), Some(t[ti].item));
+                    }
+                    matched.push([None, Some(x), if used[ti] {Some(&t[ti])} else {None}]);
+                }
+                (None, None) => matched.push([None, Some(x), None]),
+            }
+        }
+        for (y, used) in t.iter().zip(used) {
+            if used {continue}
+            match find_ins(&b, y) {
+                // Removed by us.
+                Some(by) if find_ins(&o, y).is_none() => if data(by.item) != data(y.item) {
+                    self.conflict(&Path::root().index(y.index), Some(by.item), None, Some(y.item));
+                },
+                _ => matched.push([None, None, Some(y)]),
+            }
+        }
+        // Drop instances of removed types.
+        let matched: Vec<[Option<&Ins>; 3]> = matched.into_iter().filter(|m| {
+            let ins = m[1].or(m[2]).unwrap();
+            if !matches!(ins.item, Root::Instance {..}) ||
+                merged.iter().any(|d| decl_key(d) == Some(&ins.class)) {return true}
+            self.conflict(&Path::root().index(ins.index), None, Some(ins.item), None);
+            false
+        }).collect();
+
+        // Our index of the item matching an item in their version.
+        let mut ours_of: HashMap<usize, usize> = HashMap::new();
+        for (j, y) in theirs.iter().enumerate() {
+            if let Some(i) = decl_key(y).and_then(|name| ours.iter().position(|x| decl_key(x) == Some(name))) {
+                ours_of.insert(j, i);
+            }
+        }
+        for m in &matched {
+            if let (Some(x), Some(y)) = (m[1], m[2]) {ours_of.insert(y.index, x.index);}
+        }
+        let order = |ours_index: Option<usize>, theirs_index: usize| match ours_index {
+            Some(i) => (Some(i), 0, 0),
+            None => ((0..theirs_index).rev().find_map(|j| ours_of.get(&j).copied()), 1, theirs_index),
+        };
+        let mut slots: Vec<(_, Slot)> = vec![];
+        for (i, d) in merged.iter().enumerate() {
+            let name = decl_key(d);
+            let ours_index = ours.iter().position(|x| decl_key(x) == name);
+            let theirs_index = theirs.iter().position(|y| decl_key(y) == name).unwrap_or(0);
+            slots.push((order(ours_index, theirs_index), Slot::Decl(i)));
+        }
+        for (k, m) in matched.iter().enumerate() {
+            slots.push((order(m[1].map(|x| x.index), m[2].map(|y| y.index).unwrap_or(0)), Slot::Ins(k)));
+        }
+        slots.sort_by_key(|(order, _)| *order);
+
+        // Place declarations and map the index of an instance in every version to its index in the result.
+        let mut res = vec![Root::Tup(vec![]); slots.len()];
+        let mut classes: HashMap<&Arc<String>, usize> = HashMap::new();
+        let mut at = vec![0; matched.len()];
+        let mut maps: [HashMap<usize, usize>; 3] = Default::default();
+        for (p, (_, slot)) in slots.iter().enumerate() {
+            match *slot {
+                Slot::Decl(i) => {
+                    classes.insert(decl_key(&merged[i]).unwrap(), p);
+                    res[p] = merged[i].clone();
+                }
+                Slot::Ins(k) => {
+                    at[k] = p;
+                    for (map, ins) in maps.iter_mut().zip(&matched[k]) {
+                        if let Some(ins) = ins {map.insert(ins.index, p);}
+                    }
+                }
+            }
+        }
+        // References to instances that are not in the result are marked by counting down from `usize::MAX`.
+        let renumbered = |ins: &Option<&Ins>, map: &HashMap<usize, usize>| ins.map(|ins| {
+            let mut item = ins.item.clone();
+            reference::renumber(&mut item, &|i| map.get(&i).copied().unwrap_or(usize::MAX - i));
+            item
+        });
+        for (k, m) in matched.iter().enumerate() {
+            let ins = m[1].or(m[2]).unwrap();
+            let path = Path::root().index(ins.index);
+            let mut item = match (renumbered(&m[0], &maps[0]), renumbered(&m[1], &maps[1]),
+                renumbered(&m[2], &maps[2])) {
+                (Some(b), Some(o), Some(t)) => self.instance(&b, &o, &t, &path),
+                (_, o, t) => o.or(t).unwrap(),
+            };
+            let dangling = Cell::new(false);
+            reference::renumber(&mut item, &|i| if i > usize::MAX / 2 {
+                dangling.set(true);
+                usize::MAX - i
+            } else {i});
+            if dangling.get() {
+                self.conflict(&path, m[0].map(|b| b.item), m[1].map(|o| o.item), m[2].map(|t| t.item));
+            }
+            res[at[k]] = match item {
+                Root::Instance {data, ..} => Root::Instance {class: classes[&ins.class], data},
+                x => x,
+            };
+        }
+        res
+    }
+
+    /// Merges a matched instance.
+    fn instance(&mut self, base: &Root, ours: &Root, theirs: &Root, path: &Path) -> Root {
+        match (base, ours, theirs) {
+            (Root::Instance {data: Some(b), ..}, Root::Instance {class, data: Some(o)},
+                Root::Instance {data: Some(t), ..}) => Root::Instance {
+                class: *class,
+                data: Some(Box::new(self.node(b, o, t, &path.name(&Arc::new("data".into()))))),
+            },
+            (b, o, t) => self.node(b, o, t, path),
+        }
+    }
+
+    /// Lists the instances of a document, with their type and identity.
+    fn instances<'b>(&self, items: &'b [Root]) -> Vec<Ins<'b>> {
+        let mut res: Vec<Ins<'b>> = vec![];
+        for (index, item) in items.iter().enumerate() {
+            let (decl, class) = match item {
+                Root::Instance {class, ..} => match items.get(*class).and_then(|d| decl_key(d)) {
+                    Some(name) => (items.get(*class), name.clone()),
+                    None => (None, Arc::new(format!("{}", class))),
+                },
+                Root::InstanceTy {ty, ..} => {
+                    let name = match TypeRef::from_root(ty, &[]) {
+                        Ok(TypeRef::Named(name)) => name,
+                        Ok(TypeRef::Applied(base, _)) => match *base {
+                            TypeRef::Named(name) => name,
+                            _ => Arc::new(format!("{}", ty)),
+                        },
+                        _ => Arc::new(format!("{}", ty)),
+                    };
+                    (find_decl(items, ty), name)
+                }
+                _ => continue,
+            };
+            let key = self.keys.iter().find(|k| k.ty == *class)
+                .and_then(|k| key_value(decl, data(item)?, &k.field));
+            let ident = match key {
+                Some(key) => Ident::Key(key.clone()),
+                None => Ident::Rank(res.iter().filter(|x| x.class == class).count()),
+            };
+            res.push(Ins {index, class, ident, item});
+        }
+        res
+    }
+
+    /// Records a conflict and returns our version of the node, if any.
+    fn conflict(
+        &mut self,
+        path: &Path,
+        base: Option<&Root>,
+        ours: Option<&Root>,
+        theirs: Option<&Root>,
+    ) -> Root {
+        self.conflicts.push(Conflict {
+            path: path.clone(),
+            base: base.cloned(),
+            ours: ours.cloned(),
+            theirs: theirs.cloned(),
+            error: None,
+        });
+        ours.cloned().unwrap_or(Root::Tup(vec![]))
+    }
+
+    /// Records a conflict for every instance of a merged document that does not conform to its type.
+    fn check(&mut self, items: &[Root]) {
+        let invalid = |path: Path, error: String| Conflict {
+            path, base: None, ours: None, theirs: None, error: Some(error),
+        };
+        let schema = match Schema::from_root(&Root::Tup(items.to_vec())) {
+            Ok(x) => x,
+            Err(err) => return self.conflicts.push(invalid(Path::root(), err)),
+        };
+        for i in 0..items.len() {
+            if let Err(err) = value::read_instance(&schema, items, i) {
+                self.conflicts.push(invalid(Path::root().index(i), err));
+            }
+        }
+    }
+}
+
+/// Returns the name of a declaration, field or variant.
+fn decl_key(item: &Root) -> Option<&Arc<String>> {
+    match item {
+        Root::Struct {name, ..} | Root::Enum {name, ..} => match &**name {
+            Root::Str(name) => Some(name),
+            Root::Avatar(ab) => if let Root::Str(name) = &ab.0 {Some(name)} else {None},
+            _ => None,
+        },
+        _ => key(item),
+    }
+}
+
+fn find_named<'b>(items: &'b [Root], name: &Arc<String>) -> Option<&'b Root> {
+    items.iter().find(|it| decl_key(it) == Some(name))
+}
+
+fn find_ins<'b, 'c>(list: &'b [Ins<'c>], x: &Ins) -> Option<&'b Ins<'c>> {
+    list.iter().find(|y| y.class == x.class && y.ident == x.ident)
+}
+
+/// Returns `true` if all items have distinct names.
+fn is_named(items: &[Root]) -> bool {
+    items.iter().enumerate().all(|(i, it)| match decl_key(it) {
+        Some(name) => items[..i].iter().all(|x| decl_key(x) != Some(name)),
+        None => false,
+    })
+}
+
+fn is_document(items: &[Root]) -> bool {
+    items.iter().any(|it| matches!(it, Root::Struct {..} | Root::Enum {..} |
+        Root::Instance {..} | Root::InstanceTy {..}))
+}
+
+/// Returns the data of an instance.
+fn data(item: &Root) -> Option<&Root> {
+    match item {
+        Root::Instance {data, ..} | Root::InstanceTy {data, ..} => data.as_deref(),
+        _ => None,
+    }
+}
+
+/// Finds the value of a field in a record, which is named or positional.
+fn key_value<'b>(decl: Option<&Root>, data: &'b Root, field: &str) -> Option<&'b Root> {
+    let items = if let Root::Tup(items) = data {items} else {return None};
+    if let Some(v) = items.iter().filter_map(|it| it.as_field()).find(|(n, _)| n.as_str() == field) {
+        return Some(v.1);
+    }
+    match decl {
+        Some(Root::Struct {fields, ..}) => {
+            let i = fields.iter().filter_map(|f| f.as_field()).position(|(n, _)| n.as_str() == field)?;
+            items.get(i)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data;
+
+    #[test]
+    fn test_document() {
+        let base = data(include_str!("../source/test/person/person-with-instance.txt"));
+        let ours = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>, age: f64},
+            (ins 0 ["Donald", "Duck", 90]),
+            (ins 0 ["Dolly", "Duck", 85]),
+            (ins 0 ["Scrooge", "McDuck", 75]),
+        ]"#);
+        let theirs = data(r#"[
+            enum Title {Mr, Ms},
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 1 ["Donald", "Duck"]),
+            (ins 1 ["Della", "Duck"]),
+            (ins 1 ["Gladstone", "Gander"]),
+        ]"#);
+        // Their new instance has no value for our new field.
+        let merged = merge(&base, &ours, &theirs, &[]);
+        assert_eq!(merged.conflicts.len(), 1, "{:?}", merged.conflicts);
+        assert_eq!(format!("{}", merged.conflicts[0]), "[4].data: Missing field `age`");
+        assert_eq!(merged.root, data(r#"[
+            enum Title {Mr, Ms},
+            struct Person {first_name: Arc<String>, last_name: Arc<String>, age: f64},
+            (ins 1 ["Donald", "Duck", 90]),
+            (ins 1 ["Della", "Duck", 85]),
+            (ins 1 ["Gladstone", "Gander"]),
+            (ins 1 ["Scrooge", "McDuck", 75]),
+        ]"#));
+
+        let theirs = data(r#"[
+            enum Title {Mr, Ms},
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 1 ["Donald", "Duck"]),
+            (ins 1 ["Della", "Duck"]),
+        ]"#);
+        let merged = merge(&base, &ours, &theirs, &[]);
+        assert!(merged.conflicts.is_empty(), "{:?}", merged.conflicts);
+        assert_eq!(merged.root, data(r#"[
+            enum Title {Mr, Ms},
+            struct Person {first_name: Arc<String>, last_name: Arc<String>, age: f64},
+            (ins 1 ["Donald", "Duck", 90]),
+            (ins 1 ["Della", "Duck", 85]),
+            (ins 1 ["Scrooge", "McDuck", 75]),
+        ]"#));
+
+        let theirs = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Donald", "Duck"]),
+            (ins 0 ["Dolly", "Dux"]),
+        ]"#);
+        let ours = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Donald", "Duck"]),
+            (ins 0 ["Dolly", "Ducks"]),
+        ]"#);
+        let merged = merge(&base, &ours, &theirs, &[]);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(format!("{}", merged.conflicts[0]),
+            r#"[2].data[1]: ours `"Ducks"` conflicts with theirs `"Dux"`"#);
+        assert_eq!(merged.root, ours);
+    }
+
+    #[test]
+    fn test_keys() {
+        let base = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Donald", "Duck"]),
+            (ins 0 ["Dolly", "Duck"]),
+        ]"#);
+        // Removing an instance shifts positions, but not keys.
+        let ours = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Dolly", "Duck"]),
+        ]"#);
+        let theirs = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Donald", "Duck"]),
+            (ins 0 ["Dolly", "Dux"]),
+        ]"#);
+        let keys = [Key::parse("Person.first_name").unwrap()];
+        let merged = merge(&base, &ours, &theirs, &keys);
+        assert!(merged.conflicts.is_empty(), "{:?}", merged.conflicts);
+        assert_eq!(merged.root, data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Dolly", "Dux"]),
+        ]"#));
+        assert!(!merge(&base, &ours, &theirs, &[]).conflicts.is_empty());
+
+        let theirs = data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Dolly", "Duck"]),
+            (ins 0 ["Donald", "Dux"]),
+        ]"#);
+        let merged = merge(&base, &ours, &theirs, &keys);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].ours, None);
+    }
+
+    #[test]
+    fn test_references() {
+        let base = data(r#"[
+            enum Node {Str(Arc<String>)},
+            (ins 0 Str("Donald")),
+            (ins 0 Str("Dolly")),
+            struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+            (ins 3 [(ref 1), (ref 2)]),
+        ]"#);
+        let merged = merge(&base, &base, &base, &[]);
+        assert_eq!(merged.root, base);
+
+        let theirs = data(r#"[
+            enum Node {Str(Arc<String>)},
+            (ins 0 Str("Donald")),
+            (ins 0 Str("Dolly")),
+            struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+            (ins 3 [(ref 1), (ref 2)]),
+            (ins 0 Str("Scrooge")),
+            (ins 3 [(ref 5), (ref 1)]),
+        ]"#);
+        let ours = data(r#"[
+            enum Node {Str(Arc<String>)},
+            (ins 0 Str("Donald")),
+            (ins 0 Str("Dolly")),
+            struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+            (ins 3 [(ref 2), (ref 1)]),
+        ]"#);
+        let merged = merge(&base, &ours, &theirs, &[]);
+        assert!(merged.conflicts.is_empty(), "{:?}", merged.conflicts);
+        assert_eq!(merged.root, data(r#"[
+            enum Node {Str(Arc<String>)},
+            (ins 0 Str("Donald")),
+            (ins 0 Str("Dolly")),
+            struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+            (ins 3 [(ref 2), (ref 1)]),
+            (ins 0 Str("Scrooge")),
+            (ins 3 [(ref 5), (ref 1)]),
+        ]"#));
+        assert!(reference::check(&merged.root).unwrap().is_empty());
+
+        // Our new declaration shifts the instances referred to by their new edge.
+        let ours = data(r#"[
+            enum Node {Str(Arc<String>)},
+            enum Color {Red, Blue},
+            (ins 0 Str("Donald")),
+            (ins 0 Str("Dolly")),
+            struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+            (ins 4 [(ref 2), (ref 3)]),
+        ]"#);
+        let merged = merge(&base, &ours, &theirs, &[]);
+        assert!(merged.conflicts.is_empty(), "{:?}", merged.conflicts);
+        assert_eq!(merged.root, data(r#"[
+            enum Node {Str(Arc<String>)},
+            enum Color {Red, Blue},
+            (ins 0 Str("Donald")),
+            (ins 0 Str("Dolly")),
+            struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+            (ins 4 [(ref 2), (ref 3)]),
+            (ins 0 Str("Scrooge")),
+            (ins 4 [(ref 6), (ref 2)]),
+        ]"#));
+
+        // Their new edge refers to an instance removed by us.
+        let ours = data(r#"[
+            enum Node {Str(Arc<String>)},
+            (ins 0 Str("Donald")),
+            struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+        ]"#);
+        let theirs = data(r#"[
+            enum Node {Str(Arc<String>)},
+            (ins 0 Str("Donald")),
+            (ins 0 Str("Dolly")),
+            struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+            (ins 3 [(ref 1), (ref 2)]),
+            (ins 3 [(ref 2), (ref 1)]),
+        ]"#);
+        let merged = merge(&base, &ours, &theirs, &[]);
+        assert_eq!(merged.conflicts.len(), 1, "{:?}", merged.conflicts);
+        assert_eq!(merged.conflicts[0].path, Path::parse("[5]").unwrap());
+    }
+
+    #[test]
+    fn test_declarations() {
+        let base = data(include_str!("../source/test/graph/edge_dir.txt"));
+        let ours = data("enum EdgeDir {Left, Right, Both, None, Up}");
+        let theirs = data("enum EdgeDir {Left, Right, Both, Down}");
+        let merged = merge(&base, &ours, &theirs, &[]);
+        assert_eq!(merged.root, data("enum EdgeDir {Left, Right, Both, Up, Down}"));
+
+        let theirs = data("enum EdgeDir {Left, Right, Both, None, Up(f64)}");
+        let merged = merge(&base, &ours, &theirs, &[]);
+        assert_eq!(merged.conflicts[0].path, Path::parse(".Up").unwrap());
+    }
+}
@@ -23,6 +23,9 @@ fn parse_expr(node: &str, dirs: &[String], mut convert: Convert, ignored: &mut V
         } else if let Ok((range, val)) = parse_tup(dirs, convert, ignored) {
             convert.update(range);
             expr = Some(val);
+        } else if let Ok((range, val)) = parse_field(dirs, convert, ignored) {
+            convert.update(range);
+            expr = Some(val);
         } else if let Ok((range, val)) = parse_ava(dirs, convert, ignored) {
             convert.update(range);
             expr = Some(val);
@@ -32,6 +35,21 @@ fn parse_expr(node: &str, dirs: &[String], mut convert: Convert, ignored: &mut V
         } else if let Ok((range, val)) = parse_ins(dirs, convert, ignored) {
             convert.update(range);
             expr = Some(val);
+        } else if let Ok((range, val)) = parse_params(dirs, convert, ignored) {
+            convert.update(range);
+            expr = Some(val);
+        } else if let Ok((range, val)) = parse_ref(dirs, convert, ignored) {
+            convert.update(range);
+            expr = Some(val);
+        } else if let Ok((range, val)) = parse_bound(dirs, convert, ignored) {
+            convert.update(range);
+            expr = Some(val);
+        } else if let Ok((range, val)) = parse_attr(dirs, convert, ignored) {
+            convert.update(range);
+            expr = Some(val);
+        } else if let Ok((range, val)) = parse_default(dirs, convert, ignored) {
+            convert.update(range);
+            expr = Some(val);
         } else if let Ok((range, val)) = parse_enum_var(dirs, convert, ignored) {
             convert.update(range);
             expr = Some(val);
@@ -62,6 +80,12 @@ fn parse_expr(node: &str, dirs: &[String], mut convert: Convert, ignored: &mut V
         } else if let Ok((range, _)) = convert.meta_bool("vec") {
             convert.update(range);
             expr = Some(Root::ty_vec());
+        } else if let Ok((range, val)) = convert.meta_bool("bool_val") {
+            convert.update(range);
+            expr = Some(Root::Bool(val));
+        } else if let Ok((range, _)) = convert.meta_bool("empty_str") {
+            convert.update(range);
+            expr = Some(Root::Str(Arc::new(String::new())));
         } else if let Ok((range, val)) = convert.meta_string("str") {
             convert.update(range);
             expr = Some(Root::Str(val));
@@ -183,12 +207,117 @@ fn parse_ava(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) ->
     let start_range = convert.start_node(node)?;
     convert.update(start_range);
 
+    let mut a: Option<Root> = None;
+    let mut b: Vec<Root> = vec![];
+    loop {
+        if let Ok(range) = convert.end_node(node) {
+            convert.update(range);
+            break;
+        } else if let Ok((range, val)) = convert.meta_string("a") {
+            convert.update(range);
+            a = Some(Root::Str(val));
+        } else if let Ok((range, val)) = parse_expr("a", dirs, convert, ignored) {
+            convert.update(range);
+            a = Some(val);
+        } else if let Ok((range, val)) = parse_bound(dirs, convert, ignored) {
+            convert.update(range);
+            b.push(val);
+        } else if let Ok((range, val)) = parse_expr("b", dirs, convert, ignored) {
+            convert.update(range);
+            b.push(val);
+        } else {
+            let range = convert.ignore();
+            convert.update(range);
+            ignored.push(range);
+        }
+    }
+
+    let a = a.ok_or(())?;
+    let b = match b.len() {
+        0 => return Err(()),
+        1 => b.pop().unwrap(),
+        _ => Root::Params(b),
+    };
+    Ok((convert.subtract(start), Root::Avatar(Box::new((a, b)))))
+}
+
+fn parse_params(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
+    let node = "params";
+
+    let start = convert;
+    let start_range = convert.start_node(node)?;
+    convert.update(start_range);
+
+    let mut items: Vec<Root> = vec![];
+    loop {
+        if let Ok(range) = convert.end_node(node) {
+            convert.update(range);
+            break;
+        } else if let Ok((range, val)) = parse_expr("item", dirs, convert, ignored) {
+            convert.update(range);
+            items.push(val);
+        } else {
+            let range = convert.ignore();
+            convert.update(range);
+            ignored.push(range);
+        }
+    }
+
+    Ok((convert.subtract(start), Root::Params(items)))
+}
+
+fn parse_bound(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
+    let node = "bound";
+
+    let start = convert;
+    let start_range = convert.start_node(node)?;
+    convert.update(start_range);
+
+    let mut param: Option<Root> = None;
+    let mut bounds: Vec<Root> = vec![];
+    loop {
+        if let Ok(range) = convert.end_node(node) {
+            convert.update(range);
+            break;
+        } else if let Ok((range, val)) = parse_expr("param", dirs, convert, ignored) {
+            convert.update(range);
+            param = Some(val);
+        } else if let Ok((range, val)) = convert.meta_string("bound") {
+            convert.update(range);
+            bounds.push(Root::Ty(val));
+        } else if let Ok((range, val)) = parse_expr("bounds", dirs, convert, ignored) {
+            convert.update(range);
+            bounds.push(val);
+        } else {
+            let range = convert.ignore();
+            convert.update(range);
+            ignored.push(range);
+        }
+    }
+
+    let param = param.ok_or(())?;
+    let bounds = if bounds.len() == 1 {bounds.pop().unwrap()} else {Root::Tup(bounds)};
+    Ok((convert.subtract(start), Root::Bound(Box::new((param, bounds)))))
+}
+
+fn parse_field(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
+    let node = "field";
+
+    let start = convert;
+    let start_range = convert.start_node(node)?;
+    convert.update(start_range);
+
     let mut a: Option<Root> = None;
     let mut b: Option<Root> = None;
+    let mut default: Option<Root> = None;
+    let mut attrs: Vec<Root> = vec![];
     loop {
         if let Ok(range) = convert.end_node(node) {
             convert.update(range);
             break;
+        } else if let Ok((range, val)) = parse_attribute(dirs, convert, ignored) {
+            convert.update(range);
+            attrs.push(val);
         } else if let Ok((range, val)) = convert.meta_string("a") {
             convert.update(range);
             a = Some(Root::Str(val));
@@ -198,6 +327,9 @@ fn parse_ava(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) ->
         } else if let Ok((range, val)) = parse_expr("b", dirs, convert, ignored) {
             convert.update(range);
             b = Some(val);
+        } else if let Ok((range, val)) = parse_expr("default", dirs, convert, ignored) {
+            convert.update(range);
+            default = Some(val);
         } else {
             let range = convert.ignore();
             convert.update(range);
@@ -206,8 +338,138 @@ fn parse_ava(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) ->
     }
 
     let a = a.ok_or(())?;
-    let b = b.ok_or(())?;
-    Ok((convert.subtract(start), Root::Avatar(Box::new((a, b)))))
+    let mut b = b.ok_or(())?;
+    if let Some(default) = default {
+        b = Root::Default(Box::new((b, default)));
+    }
+    for attr in attrs.into_iter().rev() {
+        b = Root::Attr(Box::new((attr, b)));
+    }
+    Ok((convert.subtract(start), Root::Field(Box::new((a, b)))))
+}
+
+fn parse_ref(_dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
+    let node = "ref";
+
+    let start = convert;
+    let start_range = convert.start_node(node)?;
+    convert.update(start_range);
+
+    let mut ind: Option<usize> = None;
+    loop {
+        if let Ok(range) = convert.end_node(node) {
+            convert.update(range);
+            break;
+        } else if let Ok((range, val)) = convert.meta_f64("ind") {
+            convert.update(range);
+            ind = Some(val as usize);
+        } else {
+            let range = convert.ignore();
+            convert.update(range);
+            ignored.push(range);
+        }
+    }
+
+    let ind = ind.ok_or(())?;
+    Ok((convert.subtract(start), Root::Ref(ind)))
+}
+
+fn parse_attribute(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
+    let node = "attribute";
+
+    let start = convert;
+    let start_range = convert.start_node(node)?;
+    convert.update(start_range);
+
+    let mut name: Option<Arc<String>> = None;
+    let mut args: Option<Vec<Root>> = None;
+    loop {
+        if let Ok(range) = convert.end_node(node) {
+            convert.update(range);
+            break;
+        } else if let Ok((range, val)) = convert.meta_string("name") {
+            convert.update(range);
+            name = Some(val);
+        } else if let Ok((range, val)) = parse_expr("arg", dirs, convert, ignored) {
+            convert.update(range);
+            args.get_or_insert_with(Vec::new).push(val);
+        } else if let Ok((range, val)) = convert.meta_string("arg") {
+            convert.update(range);
+            args.get_or_insert_with(Vec::new).push(Root::Str(val));
+        } else {
+            let range = convert.ignore();
+            convert.update(range);
+            ignored.push(range);
+        }
+    }
+
+    let name = Root::Str(name.ok_or(())?);
+    Ok((convert.subtract(start), match args {
+        None => name,
+        Some(args) => Root::Avatar(Box::new((name, Root::Tup(args)))),
+    }))
+}
+
+fn parse_attr(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
+    let node = "attr";
+
+    let start = convert;
+    let start_range = convert.start_node(node)?;
+    convert.update(start_range);
+
+    let mut attr: Option<Root> = None;
+    let mut value: Option<Root> = None;
+    loop {
+        if let Ok(range) = convert.end_node(node) {
+            convert.update(range);
+            break;
+        } else if let Ok((range, val)) = parse_expr("attr", dirs, convert, ignored) {
+            convert.update(range);
+            attr = Some(val);
+        } else if let Ok((range, val)) = parse_expr("value", dirs, convert, ignored) {
+            convert.update(range);
+            value = Some(val);
+        } else {
+            let range = convert.ignore();
+            convert.update(range);
+            ignored.push(range);
+        }
+    }
+
+    let attr = attr.ok_or(())?;
+    let value = value.ok_or(())?;
+    Ok((convert.subtract(start), Root::Attr(Box::new((attr, value)))))
+}
+
+fn parse_default(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
+    let node = "default";
+
+    let start = convert;
+    let start_range = convert.start_node(node)?;
+    convert.update(start_range);
+
+    let mut ty: Option<Root> = None;
+    let mut value: Option<Root> = None;
+    loop {
+        if let Ok(range) = convert.end_node(node) {
+            convert.update(range);
+            break;
+        } else if let Ok((range, val)) = parse_expr("ty", dirs, convert, ignored) {
+            convert.update(range);
+            ty = Some(val);
+        } else if let Ok((range, val)) = parse_expr("value", dirs, convert, ignored) {
+            convert.update(range);
+            value = Some(val);
+        } else {
+            let range = convert.ignore();
+            convert.update(range);
+            ignored.push(range);
+        }
+    }
+
+    let ty = ty.ok_or(())?;
+    let value = value.ok_or(())?;
+    Ok((convert.subtract(start), Root::Default(Box::new((ty, value)))))
 }
 
 fn parse_ty(_dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
@@ -269,7 +531,7 @@ fn parse_enum_var(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range
     }
 
     let ty = ty.ok_or(())?;
-    Ok((convert.subtract(start), Root::InstanceTy {ty: Box::new(ty), data: data}))
+    Ok((convert.subtract(start), Root::InstanceTy {ty: Box::new(ty), data}))
 }
 
 fn parse_ins(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) -> Result<(Range, Root), ()> {
@@ -303,42 +565,66 @@ fn parse_ins(dirs: &[String], mut convert: Convert, ignored: &mut Vec<Range>) ->
     }
 
     if let Some(ty) = ty {
-        Ok((convert.subtract(start), Root::InstanceTy {ty: Box::new(ty), data: data}))
+        Ok((convert.subtract(start), Root::InstanceTy {ty: Box::new(ty), data}))
     } else {
         let class = class.ok_or(())?;
-        Ok((convert.subtract(start), Root::Instance {class, data: data}))
+        Ok((convert.subtract(start), Root::Instance {class, data}))
     }
 }
 
-/// Parses an expression string.
-pub fn parse_str(data: &str, dirs: &[String]) -> Result<Root, String> {
-    use piston_meta::{parse_errstr, syntax_errstr};
+/// A compiled grammar, which can be shared between threads.
+#[derive(Debug)]
+pub struct Parser {
+    syntax: piston_meta::Syntax,
+}
+
+impl Parser {
+    /// Compiles the grammar.
+    pub fn new() -> Result<Parser, String> {
+        let syntax = piston_meta::syntax_errstr(include_str!("../assets/syntax.txt"))?;
+        Ok(Parser {syntax})
+    }
 
-    let syntax_src = include_str!("../assets/syntax.txt");
-    let syntax = syntax_errstr(syntax_src)?;
+    /// Returns a parser that is compiled once and shared by all threads.
+    pub fn shared() -> &'static Parser {
+        use std::sync::OnceLock;
 
-    let mut meta_data = vec![];
-    parse_errstr(&syntax, &data, &mut meta_data)?;
+        static PARSER: OnceLock<Parser> = OnceLock::new();
+        PARSER.get_or_init(|| Parser::new().expect("Could not compile grammar"))
+    }
 
-    // piston_meta::json::print(&meta_data);
+    /// Parses an expression string.
+    pub fn parse_str(&self, data: &str, dirs: &[String]) -> Result<Root, String> {
+        let mut meta_data = vec![];
+        piston_meta::parse_errstr(&self.syntax, data, &mut meta_data)?;
+
+        // piston_meta::json::print(&meta_data);
+
+        let convert = Convert::new(&meta_data);
+        let mut ignored = vec![];
+        match parse_expr("expr", dirs, convert, &mut ignored) {
+            Err(()) => Err("Could not convert meta data".into()),
+            Ok((_, mut expr)) => {
+                symbol::share_root(&mut expr);
+                Ok(expr)
+            }
+        }
+    }
 
-    let convert = Convert::new(&meta_data);
-    let mut ignored = vec![];
-    match parse_expr("expr", dirs, convert, &mut ignored) {
-        Err(()) => Err("Could not convert meta data".into()),
-        Ok((_, expr)) => Ok(expr),
+    /// Parses an expression source file.
+    pub fn parse(&self, source: &str, dirs: &[String]) -> Result<Root, String> {
+        let data = std::fs::read_to_string(source).map_err(|err|
+            format!("Could not open `{}`, {}", source, err))?;
+        self.parse_str(&data, dirs)
     }
 }
 
+/// Parses an expression string.
+pub fn parse_str(data: &str, dirs: &[String]) -> Result<Root, String> {
+    Parser::shared().parse_str(data, dirs)
+}
+
 /// Parses an expression source file.
 pub fn parse(source: &str, dirs: &[String]) -> Result<Root, String> {
-    use std::fs::File;
-    use std::io::Read;
-
-    let mut data_file = File::open(source).map_err(|err|
-        format!("Could not open `{}`, {}", source, err))?;
-    let mut data = String::new();
-    data_file.read_to_string(&mut data).unwrap();
-
-    parse_str(&data, dirs)
+    Parser::shared().parse(source, dirs)
 }
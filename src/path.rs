@@ -0,0 +1,304 @@
+//! Paths addressing nodes inside a `Root`.
+//!
+//! A path is a sequence of segments, e.g. `[3].data.dir`:
+//!
+//! - `[i]` selects the i-th child, e.g. an item of a tuple, a field of a struct
+//!   declaration or a variant of an enum declaration
+//! - `.name` selects a named value, e.g. the type of a field in a struct declaration,
+//!   a variant of an enum declaration, the value of a named field in a tuple,
+//!   `data` or `ty` of an instance, or the data of a variant such as `Str("Donald")`
+//!
+//! Inside a document, positional records of an instance `(ins N ..)`
+//! can be addressed by field name, using the declaration at index `N`.
+
+use crate::*;
+
+/// A path to a node inside a `Root`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Path {
+    /// Segments of path, from the root.
+    pub segments: Vec<Segment>,
+}
+
+/// A segment of a path.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Segment {
+    /// Selects a child by index, e.g. `[3]`.
+    Index(usize),
+    /// Selects a named value, e.g. `.dir`.
+    Name(Arc<String>),
+}
+
+impl Path {
+    /// The empty path, addressing the root.
+    pub fn root() -> Path {
+        Path {segments: vec![]}
+    }
+
+    /// Parses a path, e.g. `[3].data.dir`.
+    ///
+    /// The leading `.` of the first name may be omitted, e.g. `Avatar` or `Struct.name`.
+    pub fn parse(text: &str) -> Result<Path, String> {
+        let mut segments = vec![];
+        let mut rest = text.trim();
+        if rest == "." {return Ok(Path::root())}
+        if !rest.is_empty() && !rest.starts_with('.') && !rest.starts_with('[') {
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            segments.push(Segment::Name(Arc::new(rest[..end].into())));
+            rest = &rest[end..];
+        }
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix('[') {
+                let end = r.find(']').ok_or_else(|| format!("Expected `]` in `{}`", text))?;
+                let ind = r[..end].trim().parse()
+                    .map_err(|_| format!("Expected index, found `{}` in `{}`", &r[..end], text))?;
+                segments.push(Segment::Index(ind));
+                rest = &r[end + 1..];
+            } else if let Some(r) = rest.strip_prefix('.') {
+                let end = r.find(['.', '[']).unwrap_or(r.len());
+                if end == 0 {return Err(format!("Expected name in `{}`", text))}
+                segments.push(Segment::Name(Arc::new(r[..end].into())));
+                rest = &r[end..];
+            } else {
+                return Err(format!("Expected `.` or `[` in `{}`", text));
+            }
+        }
+        Ok(Path {segments})
+    }
+
+    /// Returns a new path with an index appended.
+    pub fn index(&self, ind: usize) -> Path {
+        let mut res = self.clone();
+        res.segments.push(Segment::Index(ind));
+        res
+    }
+
+    /// Returns a new path with a name appended.
+    pub fn name(&self, name: &Arc<String>) -> Path {
+        let mut res = self.clone();
+        res.segments.push(Segment::Name(name.clone()));
+        res
+    }
+
+    /// Returns the path without its last segment, if any.
+    pub fn parent(&self) -> Option<Path> {
+        let n = self.segments.len();
+        if n == 0 {None} else {Some(Path {segments: self.segments[..n - 1].to_vec()})}
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        if self.segments.is_empty() {return write!(w, ".")}
+        for s in &self.segments {
+            match s {
+                Segment::Index(i) => write!(w, "[{}]", i)?,
+                Segment::Name(name) => write!(w, ".{}", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Root {
+    /// Selects a node by path.
+    pub fn select(&self, path: &Path) -> Option<&Root> {
+        let mut node = self;
+        for &i in &self.steps(path)? {
+            node = node.child(i)?;
+        }
+        Some(node)
+    }
+
+    /// Selects a mutable node by path.
+    pub fn select_mut(&mut self, path: &Path) -> Option<&mut Root> {
+        let steps = self.steps(path)?;
+        let mut node = self;
+        for &i in &steps {
+            node = node.child_mut(i)?;
+        }
+        Some(node)
+    }
+
+    /// Returns the child at an index, using the same order as `[i]` in paths.
+    ///
+    /// Avatars, fields, bounds, defaults and attributes have two children,
+    /// instances have `ty` (if explicit) and `data`.
+    pub fn child(&self, ind: usize) -> Option<&Root> {
+        use Root::*;
+
+        match self {
+            Avatar(ab) | Field(ab) | Bound(ab) | Default(ab) | Attr(ab) =>
+                match ind {0 => Some(&ab.0), 1 => Some(&ab.1), _ => None},
+            Tup(items) | Params(items) => items.get(ind),
+            Struct {fields: items, ..} | Enum {variants: items, ..} => items.get(ind),
+            Instance {data, ..} => if ind == 0 {data.as_deref()} else {None},
+            InstanceTy {ty, data} => match ind {
+                0 => Some(ty),
+                1 => data.as_deref(),
+                _ => None,
+            },
+            Ty(_) | Str(_) | F64(_) | Bool(_) | Ref(_) => None,
+        }
+    }
+
+    /// Returns the mutable child at an index.
+    pub fn child_mut(&mut self, ind: usize) -> Option<&mut Root> {
+        use Root::*;
+
+        match self {
+            Avatar(ab) | Field(ab) | Bound(ab) | Default(ab) | Attr(ab) =>
+                match ind {0 => Some(&mut ab.0), 1 => Some(&mut ab.1), _ => None},
+            Tup(items) | Params(items) => items.get_mut(ind),
+            Struct {fields: items, ..} | Enum {variants: items, ..} => items.get_mut(ind),
+            Instance {data, ..} => if ind == 0 {data.as_deref_mut()} else {None},
+            InstanceTy {ty, data} => match ind {
+                0 => Some(ty),
+                1 => data.as_deref_mut(),
+                _ => None,
+            },
+            Ty(_) | Str(_) | F64(_) | Bool(_) | Ref(_) => None,
+        }
+    }
+
+    /// Resolves a path into child indices.
+    fn steps(&self, path: &Path) -> Option<Vec<usize>> {
+        let doc = if let Root::Tup(items) = self {&items[..]} else {&[]};
+        let mut steps = vec![];
+        let mut node = self;
+        // Declaration of a positional record.
+        let mut decl: Option<&Root> = None;
+        for seg in &path.segments {
+            let (next, next_decl) = match seg {
+                Segment::Index(i) => {
+                    let next_decl = match node.child(*i)? {
+                        Root::Instance {class, ..} if std::ptr::eq(node, self) => doc.get(*class),
+                        Root::InstanceTy {ty, ..} => find_decl(doc, ty),
+                        _ => None,
+                    };
+                    steps.push(*i);
+                    (node.child(*i)?, next_decl)
+                }
+                Segment::Name(name) => {
+                    let (ind, value) = named_child(node, name, decl)?;
+                    steps.extend(ind);
+                    let next_decl = match node {
+                        Root::Instance {..} | Root::InstanceTy {..} => decl,
+                        _ => field_ty(decl, name).and_then(|ty| find_decl(doc, ty)),
+                    };
+                    (value, next_decl)
+                }
+            };
+            node = next;
+            decl = next_decl;
+        }
+        Some(steps)
+    }
+}
+
+/// Finds the child indices of a named value.
+fn named_child<'a>(node: &'a Root, name: &str, decl: Option<&Root>) -> Option<(Vec<usize>, &'a Root)> {
+    use Root::*;
+
+    let is = |r: &Root| matches!(r, Str(x) if x.as_str() == name);
+    match node {
+        Struct {fields: items, ..} | Tup(items) => {
+            if let Some(i) = items.iter().position(|f| matches!(f, Field(kv) if is(&kv.0))) {
+                return Some((vec![i, 1], node.child(i)?.child(1)?));
+            }
+            if let Tup(items) = node {
+                // Positional record described by declaration.
+                if let Some(Struct {fields, ..}) = decl {
+                    let i = fields.iter().position(|f| matches!(f, Field(kv) if is(&kv.0)))?;
+                    return Some((vec![i], items.get(i)?));
+                }
+            }
+            None
+        }
+        Enum {variants, ..} => {
+            let i = variants.iter().position(|v| match v {
+                Str(_) => is(v),
+                Avatar(ab) => is(&ab.0),
+                Struct {name, ..} => is(name),
+                _ => false,
+            })?;
+            Some((vec![i], &variants[i]))
+        }
+        Avatar(ab) if is(&ab.0) => Some((vec![1], &ab.1)),
+        Instance {data: Some(data), ..} if name == "data" => Some((vec![0], data)),
+        InstanceTy {ty, ..} if name == "ty" => Some((vec![0], ty)),
+        InstanceTy {data: Some(data), ..} if name == "data" => Some((vec![1], data)),
+        _ => None,
+    }
+}
+
+/// Returns the type of a field in a struct declaration.
+pub(crate) fn field_ty<'a>(decl: Option<&'a Root>, name: &str) -> Option<&'a Root> {
+    if let Some(Root::Struct {fields, ..}) = decl {
+        fields.iter().filter_map(|f| f.as_field()).find(|(n, _)| n.as_str() == name).map(|(_, ty)| ty)
+    } else {
+        None
+    }
+}
+
+/// Finds a declaration by the name of a type, e.g. `(ty EdgeDir)`.
+pub(crate) fn find_decl<'a>(doc: &'a [Root], ty: &Root) -> Option<&'a Root> {
+    let ty_name = match ty {
+        Root::Ty(name) | Root::Str(name) => name,
+        Root::Avatar(ab) => match &ab.0 {
+            Root::Ty(name) | Root::Str(name) => name,
+            _ => return None,
+        },
+        Root::Default(tv) => return find_decl(doc, &tv.0),
+        Root::Attr(av) => return find_decl(doc, &av.1),
+        _ => return None,
+    };
+    doc.iter().find(|it| match it {
+        Root::Struct {name, ..} | Root::Enum {name, ..} => match &**name {
+            Root::Str(n) => n == ty_name,
+            Root::Avatar(ab) => matches!(&ab.0, Root::Str(n) if n == ty_name),
+            _ => false,
+        },
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for text in ["[3].data.dir", ".Avatar", ".Struct.name", "[0][1]", "."] {
+            assert_eq!(format!("{}", Path::parse(text).unwrap()), text);
+        }
+        assert_eq!(Path::parse("Avatar").unwrap(), Path::parse(".Avatar").unwrap());
+        assert!(Path::parse("[x]").is_err());
+        assert!(Path::parse("a..b").is_err());
+    }
+
+    #[test]
+    fn test_select() {
+        use Root::*;
+
+        let root = root_self();
+        assert_eq!(root.select(&Path::parse("Avatar").unwrap()), root.child(4));
+        assert_eq!(root.select(&Path::parse("Struct.name").unwrap()),
+            Some(&Avatar(Box::new((Root::ty_box(), Root::ty_self())))));
+        assert!(root.select(&Path::parse("Foo").unwrap()).is_none());
+
+        let mut doc = parsing::parse_str(
+            include_str!("../source/test/graph/graph-with-instances.txt"), &[]).unwrap();
+        let dir = Path::parse("[6].data.dir").unwrap();
+        assert_eq!(doc.select(&dir), Some(&InstanceTy {
+            ty: Box::new(Ty(Arc::new("EdgeDir".into()))),
+            data: Some(Box::new(Str(Arc::new("Both".into())))),
+        }));
+        assert_eq!(doc.select(&Path::parse("[3].data.Str").unwrap()),
+            Some(&Str(Arc::new("Donald".into()))));
+        *doc.select_mut(&dir).unwrap() = Str(Arc::new("Left".into()));
+        assert_eq!(doc.select(&Path::parse("[6].data[2]").unwrap()),
+            Some(&Str(Arc::new("Left".into()))));
+        assert!(doc.select(&Path::parse("[6].data.weight").unwrap()).is_none());
+    }
+}
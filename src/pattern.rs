@@ -0,0 +1,384 @@
+//! Patterns matching strings, used by the `#[pattern(..)]` attribute.
+//!
+//! Supports a subset of regular expressions:
+//!
+//! - Literals, `.` for any character and escapes such as `\.`
+//! - Classes, e.g. `[a-z_]`, `[^0-9]`, `\d`, `\w` and `\s`
+//! - Anchors `^` and `$`
+//! - Repetition with `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`
+//! - Groups with alternatives, e.g. `(Mr|Ms)`
+//!
+//! A pattern matches a string if it matches any substring,
+//! so use anchors to match the whole string, e.g. `^[A-Z][a-z]*$`.
+//!
+//! Patterns are compiled to a non-deterministic automaton, which is simulated
+//! one character at a time, such that matching takes linear time in the length of text.
+//! Patterns come from documents, so this keeps validation of untrusted files fast.
+
+use std::fmt;
+
+/// A compiled pattern.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    source: String,
+    prog: Vec<Inst>,
+}
+
+/// The maximum number of instructions of a compiled pattern.
+const MAX_INSTS: usize = 100_000;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Char(char),
+    Any,
+    Class {ranges: Vec<(char, char)>, negated: bool},
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat {node: Box<Node>, min: usize, max: Option<usize>},
+}
+
+/// An instruction of a compiled pattern.
+#[derive(Clone, Debug, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class {ranges: Vec<(char, char)>, negated: bool},
+    Start,
+    End,
+    /// Continues at both instructions.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Pattern {
+    /// Parses a pattern, e.g. `^[A-Z][a-z]*$`.
+    pub fn parse(source: &str) -> Result<Pattern, String> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut parser = Parser {chars: &chars, pos: 0};
+        let alt = parser.alt()
+            .map_err(|err| format!("{} in pattern `{}`", err, source))?;
+        if parser.pos < chars.len() {
+            return Err(format!("Unmatched `)` in pattern `{}`", source));
+        }
+        let mut compiler = Compiler {prog: vec![]};
+        compiler.alt(&alt)
+            .map_err(|err| format!("{} in pattern `{}`", err, source))?;
+        compiler.prog.push(Inst::Match);
+        Ok(Pattern {source: source.into(), prog: compiler.prog})
+    }
+
+    /// Returns `true` if the pattern matches some part of text.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars: Vec<char> = text.chars().collect();
+        let mut m = Matcher {prog: &self.prog, len: chars.len(), seen: vec![usize::MAX; self.prog.len()]};
+        let mut threads = vec![];
+        for pos in 0..=chars.len() {
+            // A match may start at every position.
+            if m.add(&mut threads, 0, pos) {return true}
+            let ch = if let Some(&ch) = chars.get(pos) {ch} else {break};
+            let mut next = vec![];
+            for pc in std::mem::take(&mut threads) {
+                let ok = match &self.prog[pc] {
+                    Inst::Char(c) => *c == ch,
+                    Inst::Any => true,
+                    Inst::Class {ranges, negated} =>
+                        ranges.iter().any(|&(a, b)| a <= ch && ch <= b) != *negated,
+                    _ => false,
+                };
+                if ok && m.add(&mut next, pc + 1, pos + 1) {return true}
+            }
+            threads = next;
+        }
+        false
+    }
+
+    /// Returns the source of pattern.
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(w, "{}", self.source)
+    }
+}
+
+struct Parser<'a> {
+    chars: &'a [char],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let ch = self.peek().ok_or_else(|| "Unexpected end".to_string())?;
+        self.pos += 1;
+        Ok(ch)
+    }
+
+    fn alt(&mut self) -> Result<Vec<Vec<Node>>, String> {
+        let mut res = vec![self.seq()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            res.push(self.seq()?);
+        }
+        Ok(res)
+    }
+
+    fn seq(&mut self) -> Result<Vec<Node>, String> {
+        let mut res = vec![];
+        while let Some(ch) = self.peek() {
+            if ch == '|' || ch == ')' {break}
+            let atom = self.atom()?;
+            res.push(self.repeat(atom)?);
+        }
+        Ok(res)
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        Ok(match self.next()? {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                let alt = self.alt()?;
+                if self.next()? != ')' {return Err("Expected `)`".into())}
+                Node::Group(alt)
+            }
+            '[' => self.class()?,
+            '\\' => escape(self.next()?),
+            ch @ ('*' | '+' | '?' | '{') => return Err(format!("Nothing to repeat before `{}`", ch)),
+            ch => Node::Char(ch),
+        })
+    }
+
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {self.pos += 1}
+        let mut ranges = vec![];
+        loop {
+            let ch = match self.next()? {
+                ']' if !ranges.is_empty() => break,
+                '\\' => match escape(self.next()?) {
+                    Node::Char(ch) => ch,
+                    Node::Class {ranges: r, negated: false} => {ranges.extend(r); continue}
+                    _ => return Err("Unsupported escape in class".into()),
+                },
+                ch => ch,
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let end = self.next()?;
+                if end < ch {return Err(format!("Invalid range `{}-{}`", ch, end))}
+                ranges.push((ch, end));
+            } else {
+                ranges.push((ch, ch));
+            }
+        }
+        Ok(Node::Class {ranges, negated})
+    }
+
+    fn repeat(&mut self, node: Node) -> Result<Node, String> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => {
+                self.pos += 1;
+                let min = self.number()?;
+                let max = match self.next()? {
+                    '}' => return self.repeat_node(node, min, Some(min)),
+                    ',' if self.peek() == Some('}') => None,
+                    ',' => Some(self.number()?),
+                    _ => return Err("Expected `,` or `}`".into()),
+                };
+                if self.next()? != '}' {return Err("Expected `}`".into())}
+                return self.repeat_node(node, min, max);
+            }
+            _ => return Ok(node),
+        };
+        self.pos += 1;
+        self.repeat_node(node, min, max)
+    }
+
+    fn repeat_node(&mut self, node: Node, min: usize, max: Option<usize>) -> Result<Node, String> {
+        if max.is_some_and(|max| max < min) {
+            return Err(format!("Invalid repetition `{{{},{}}}`", min, max.unwrap()));
+        }
+        self.repeat(Node::Repeat {node: Box::new(node), min, max})
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {self.pos += 1}
+        self.chars[start..self.pos].iter().collect::<String>().parse()
+            .map_err(|_| "Expected number".to_string())
+    }
+}
+
+fn escape(ch: char) -> Node {
+    let class = |ranges: &[(char, char)], negated| Node::Class {ranges: ranges.to_vec(), negated};
+    let digit = [('0', '9')];
+    let word = [('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')];
+    let space = [(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r')];
+    match ch {
+        'd' => class(&digit, false),
+        'D' => class(&digit, true),
+        'w' => class(&word, false),
+        'W' => class(&word, true),
+        's' => class(&space, false),
+        'S' => class(&space, true),
+        'n' => Node::Char('\n'),
+        't' => Node::Char('\t'),
+        ch => Node::Char(ch),
+    }
+}
+
+/// Compiles a pattern to instructions.
+struct Compiler {
+    prog: Vec<Inst>,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> Result<usize, String> {
+        if self.prog.len() >= MAX_INSTS {return Err("Pattern is too large".into())}
+        self.prog.push(inst);
+        Ok(self.prog.len() - 1)
+    }
+
+    fn alt(&mut self, alt: &[Vec<Node>]) -> Result<(), String> {
+        let mut jumps = vec![];
+        for (i, seq) in alt.iter().enumerate() {
+            if i + 1 == alt.len() {
+                self.seq(seq)?;
+                break;
+            }
+            let split = self.push(Inst::Split(0, 0))?;
+            self.seq(seq)?;
+            jumps.push(self.push(Inst::Jump(0))?);
+            self.prog[split] = Inst::Split(split + 1, self.prog.len());
+        }
+        for jump in jumps {self.prog[jump] = Inst::Jump(self.prog.len())}
+        Ok(())
+    }
+
+    fn seq(&mut self, nodes: &[Node]) -> Result<(), String> {
+        for node in nodes {self.node(node)?}
+        Ok(())
+    }
+
+    fn node(&mut self, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Char(c) => {self.push(Inst::Char(*c))?;}
+            Node::Any => {self.push(Inst::Any)?;}
+            Node::Class {ranges, negated} =>
+                {self.push(Inst::Class {ranges: ranges.clone(), negated: *negated})?;}
+            Node::Start => {self.push(Inst::Start)?;}
+            Node::End => {self.push(Inst::End)?;}
+            Node::Group(alt) => self.alt(alt)?,
+            Node::Repeat {node, min, max} => {
+                for _ in 0..*min {self.node(node)?}
+                match max {
+                    None => {
+                        let split = self.push(Inst::Split(0, 0))?;
+                        self.node(node)?;
+                        self.push(Inst::Jump(split))?;
+                        self.prog[split] = Inst::Split(split + 1, self.prog.len());
+                    }
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0))?);
+                            self.node(node)?;
+                        }
+                        let end = self.prog.len();
+                        for split in splits {self.prog[split] = Inst::Split(split + 1, end)}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Simulates the instructions of a pattern on all threads at once.
+struct Matcher<'a> {
+    prog: &'a [Inst],
+    len: usize,
+    /// The last position where an instruction was added, such that it is added once per position.
+    seen: Vec<usize>,
+}
+
+impl Matcher<'_> {
+    /// Adds a thread and follows instructions that consume no character.
+    ///
+    /// Returns `true` if the pattern matches.
+    fn add(&mut self, threads: &mut Vec<usize>, pc: usize, pos: usize) -> bool {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            if self.seen[pc] == pos {continue}
+            self.seen[pc] = pos;
+            match self.prog[pc] {
+                Inst::Match => return true,
+                Inst::Jump(to) => stack.push(to),
+                Inst::Split(a, b) => {stack.push(b); stack.push(a)}
+                Inst::Start => if pos == 0 {stack.push(pc + 1)},
+                Inst::End => if pos == self.len {stack.push(pc + 1)},
+                _ => threads.push(pc),
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match() {
+        let is_match = |pattern: &str, text: &str| Pattern::parse(pattern).unwrap().is_match(text);
+        assert!(is_match("^[A-Z][a-z]*$", "Donald"));
+        assert!(!is_match("^[A-Z][a-z]*$", "donald"));
+        assert!(!is_match("^[A-Z][a-z]*$", "Donald Duck"));
+        assert!(is_match("uck", "Donald Duck"));
+        assert!(is_match(r"^\d{3}-\d{4}$", "555-1234"));
+        assert!(!is_match(r"^\d{3}-\d{4}$", "55-1234"));
+        assert!(is_match("^(Mr|Ms)\\.? ", "Ms Dolly"));
+        assert!(is_match("^a(b|c)*d$", "abcbd"));
+        assert!(is_match("^[^0-9]+$", "abc"));
+        assert!(!is_match("^[^0-9]+$", "a1c"));
+        assert!(is_match("^x?$", ""));
+        assert!(is_match("^(a*)*b$", "aab"));
+        assert!(is_match("^a{2,3}$", "aaa"));
+        assert!(!is_match("^a{2,3}$", "aaaa"));
+        assert!(is_match("^(ab){2}$", "abab"));
+        assert!(is_match("^$", ""));
+
+        assert!(Pattern::parse("(ab").is_err());
+        assert!(Pattern::parse("ab)").is_err());
+        assert!(Pattern::parse("*a").is_err());
+        assert!(Pattern::parse("[z-a]").is_err());
+        assert!(Pattern::parse("(a{1000}){1000}").is_err());
+    }
+
+    #[test]
+    fn test_pathological() {
+        // These take exponential time with backtracking.
+        let text = format!("{}b", "a".repeat(10_000));
+        for pattern in ["^(a|a)*$", "^(a|aa)*$", "^(a*)*$", "^(a+)+$"] {
+            let pattern = Pattern::parse(pattern).unwrap();
+            assert!(!pattern.is_match(&text));
+            assert!(pattern.is_match(&text[..text.len() - 1]));
+        }
+        let pattern = Pattern::parse("^(a?){30}a{30}$").unwrap();
+        assert!(pattern.is_match(&"a".repeat(30)));
+        assert!(!pattern.is_match(&"a".repeat(61)));
+    }
+}
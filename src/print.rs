@@ -0,0 +1,335 @@
+//! Printing of documents in source syntax.
+//!
+//! The `Display` implementation of `Root` writes the internal structure,
+//! e.g. `(field "age" (ty "f64"))`, while the functions here write the syntax
+//! used in source files, e.g. `age: f64`.
+//! Parsing the output gives the same structure.
+//!
+//! Layout and comments of the original source are not preserved.
+
+use crate::*;
+
+use std::fmt::Write;
+
+/// Type names written without `.` in source.
+const BUILTIN: &[&str] = &["Self", "String", "Arc", "Option", "Vec", "f64", "bool", "usize"];
+
+/// Words read as keywords or builtin types when a name starts with them.
+const RESERVED: &[&str] = &[
+    "Self", "String", "Arc", "Box", "Option", "Vec", "f64", "bool", "usize",
+    "true", "false", "struct", "enum",
+];
+
+/// Writes a node in source syntax on a single line.
+pub fn source(root: &Root) -> String {
+    let mut p = Printer {out: String::new(), indent: None};
+    p.expr(root, Ctx::Data);
+    p.out
+}
+
+/// Writes a document in source syntax, with one item per line.
+///
+/// Fields of declarations and variants of enums are written on separate lines.
+pub fn document(root: &Root) -> String {
+    let mut p = Printer {out: String::new(), indent: Some(0)};
+    p.expr(root, Ctx::Data);
+    p.out.push('\n');
+    p.out
+}
+
+/// How a node is read at its position.
+#[derive(Clone, Copy, PartialEq)]
+enum Ctx {
+    /// Values, e.g. `[1, "a"]` or `Str("a")`.
+    Data,
+    /// Types, e.g. `(f64, bool)` or `Vec<Self>`.
+    Type,
+    /// Variants of an enum, e.g. `Str(Arc<String>)`.
+    Variant,
+}
+
+struct Printer {
+    out: String,
+    /// Current indention, or `None` when writing on a single line.
+    indent: Option<usize>,
+}
+
+impl Printer {
+    fn expr(&mut self, root: &Root, ctx: Ctx) {
+        use Root::*;
+
+        match root {
+            Ty(name) => self.ty(name),
+            Str(s) if ctx == Ctx::Variant && is_name(s) => self.out.push_str(s),
+            Str(s) => write!(self.out, "{:?}", s).unwrap(),
+            F64(v) => write!(self.out, "{}", v).unwrap(),
+            Bool(b) => write!(self.out, "{}", b).unwrap(),
+            Ref(ind) => write!(self.out, "(ref {})", ind).unwrap(),
+            Avatar(ab) => self.avatar(&ab.0, &ab.1, ctx),
+            Field(kv) => self.field(&kv.0, &kv.1, ctx),
+            Tup(items) => {
+                let (open, close) = if ctx == Ctx::Data {("[", "]")} else {("(", ")")};
+                let ctx = if ctx == Ctx::Variant {Ctx::Type} else {ctx};
+                self.list(open, items, close, ctx);
+            }
+            Params(items) => self.sexpr("params", items, ctx),
+            Bound(pb) => self.sexpr("bound", &[pb.0.clone(), pb.1.clone()], ctx),
+            Default(tv) => self.sexpr("default", &[tv.0.clone(), tv.1.clone()], ctx),
+            Attr(av) => self.sexpr("attr", &[av.0.clone(), av.1.clone()], ctx),
+            Struct {name, fields} => {
+                if ctx != Ctx::Variant {self.out.push_str("struct ")}
+                self.name(name);
+                self.out.push(' ');
+                self.list("{", fields, "}", Ctx::Type);
+            }
+            Enum {name, variants} => {
+                self.out.push_str("enum ");
+                self.name(name);
+                self.out.push(' ');
+                self.list("{", variants, "}", Ctx::Variant);
+            }
+            Instance {class, data} => {
+                write!(self.out, "(ins {}", class).unwrap();
+                if let Some(data) = data {
+                    self.out.push(' ');
+                    self.expr(data, Ctx::Data);
+                }
+                self.out.push(')');
+            }
+            InstanceTy {ty, data} => match (&**ty, data.as_deref()) {
+                (Ty(name), Some(data)) if is_name(name) && head(data).map(is_name) == Some(true) => {
+                    write!(self.out, "{}::", name).unwrap();
+                    match data {
+                        Str(s) => self.out.push_str(s),
+                        _ => self.expr(data, Ctx::Data),
+                    }
+                }
+                _ => {
+                    self.out.push_str("(ins ");
+                    self.expr(ty, Ctx::Type);
+                    if let Some(data) = data {
+                        self.out.push(' ');
+                        self.expr(data, Ctx::Data);
+                    }
+                    self.out.push(')');
+                }
+            }
+        }
+    }
+
+    fn ty(&mut self, name: &str) {
+        if name == "box" {
+            self.out.push_str("Box");
+        } else if BUILTIN.contains(&name) {
+            self.out.push_str(name);
+        } else if is_ident(name) {
+            write!(self.out, ".{}", name).unwrap();
+        } else {
+            write!(self.out, "(ty {:?})", name).unwrap();
+        }
+    }
+
+    /// Writes the name of a declaration, e.g. `Person` or `Map<.K: Ord, .V>`.
+    fn name(&mut self, name: &Root) {
+        match name {
+            Root::Str(s) if is_name(s) => self.out.push_str(s),
+            Root::Avatar(ab) if matches!(&ab.0, Root::Str(s) if is_name(s)) =>
+                self.avatar(&ab.0, &ab.1, Ctx::Type),
+            _ => self.expr(name, Ctx::Data),
+        }
+    }
+
+    /// Writes an avatar, e.g. `Vec<Self>` in types or `Str("a")` in data and variants.
+    fn avatar(&mut self, a: &Root, b: &Root, ctx: Ctx) {
+        let angle = match a {
+            Root::Ty(_) => true,
+            Root::Str(s) if is_name(s) => ctx == Ctx::Type,
+            _ => {
+                self.sexpr("ava", &[a.clone(), b.clone()], ctx);
+                return;
+            }
+        };
+        self.expr(a, Ctx::Variant);
+        if angle {
+            self.out.push('<');
+            let args = if let Root::Params(args) = b {&args[..]} else {std::slice::from_ref(b)};
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {self.out.push_str(", ")}
+                match arg {
+                    Root::Bound(pb) if bounds(&pb.1).is_some() => {
+                        self.expr(&pb.0, Ctx::Type);
+                        self.out.push_str(": ");
+                        self.out.push_str(&bounds(&pb.1).unwrap().join(" + "));
+                    }
+                    _ => self.expr(arg, Ctx::Type),
+                }
+            }
+            self.out.push('>');
+        } else {
+            self.out.push('(');
+            self.expr(b, if ctx == Ctx::Variant {Ctx::Type} else {Ctx::Data});
+            self.out.push(')');
+        }
+    }
+
+    /// Writes a field, e.g. `#[range(0, 150)] age: f64 = 0` in declarations or `age: 80` in data.
+    fn field(&mut self, key: &Root, value: &Root, ctx: Ctx) {
+        let name = match key {
+            Root::Str(s) if is_name(s) => s,
+            _ => return self.sexpr("field", &[key.clone(), value.clone()], ctx),
+        };
+        let mut value = value;
+        while let Root::Attr(av) = value {
+            match &av.0 {
+                Root::Str(s) if is_name(s) => write!(self.out, "#[{}] ", s).unwrap(),
+                Root::Avatar(ab) => match (&ab.0, &ab.1) {
+                    (Root::Str(s), Root::Tup(args)) if is_name(s) => {
+                        write!(self.out, "#[{}", s).unwrap();
+                        self.list("(", args, ")", Ctx::Variant);
+                        self.out.push_str("] ");
+                    }
+                    _ => break,
+                },
+                _ => break,
+            }
+            value = &av.1;
+        }
+        write!(self.out, "{}: ", name).unwrap();
+        let ctx = if ctx == Ctx::Data {Ctx::Data} else {Ctx::Type};
+        match value {
+            Root::Default(tv) => {
+                self.expr(&tv.0, ctx);
+                self.out.push_str(" = ");
+                self.expr(&tv.1, Ctx::Data);
+            }
+            _ => self.expr(value, ctx),
+        }
+    }
+
+    /// Writes an s-expression, for nodes without other source syntax.
+    fn sexpr(&mut self, name: &str, items: &[Root], ctx: Ctx) {
+        let ctx = if ctx == Ctx::Variant {Ctx::Type} else {ctx};
+        write!(self.out, "({}", name).unwrap();
+        for it in items {
+            self.out.push(' ');
+            self.expr(it, ctx);
+        }
+        self.out.push(')');
+    }
+
+    /// Writes items separated by commas, on separate lines when the list contains declarations.
+    fn list(&mut self, open: &str, items: &[Root], close: &str, ctx: Ctx) {
+        self.out.push_str(open);
+        let multiline = self.indent.is_some() &&
+            (open == "{" || items.iter().any(|it| matches!(it, Root::Struct {..} | Root::Enum {..})));
+        if multiline {
+            let outer = self.indent.unwrap();
+            self.indent = Some(outer + 2);
+            for it in items {
+                write!(self.out, "\n{:1$}", "", outer + 2).unwrap();
+                self.expr(it, ctx);
+                self.out.push(',');
+            }
+            self.indent = Some(outer);
+            write!(self.out, "\n{:1$}", "", outer).unwrap();
+        } else {
+            for (i, it) in items.iter().enumerate() {
+                if i > 0 {self.out.push_str(", ")}
+                self.expr(it, ctx);
+            }
+        }
+        self.out.push_str(close);
+    }
+}
+
+/// Returns the bound names, e.g. `Ord + Clone`, when written without s-expressions.
+fn bounds(root: &Root) -> Option<Vec<&str>> {
+    let names = match root {
+        Root::Tup(items) => items.iter().collect(),
+        _ => vec![root],
+    };
+    names.into_iter().map(|it| match it {
+        Root::Ty(name) if is_ident(name) => Some(&***name),
+        _ => None,
+    }).collect()
+}
+
+/// Returns the name of a unit or tuple variant, e.g. `Both` or `Str` in `Str("a")`.
+fn head(data: &Root) -> Option<&str> {
+    match data {
+        Root::Str(s) => Some(s),
+        Root::Avatar(ab) => if let Root::Str(s) = &ab.0 {Some(s)} else {None},
+        _ => None,
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_') &&
+        chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Whether a string can be written without quotes.
+fn is_name(s: &str) -> bool {
+    is_ident(s) && !RESERVED.iter().any(|r| s.starts_with(r))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source() {
+        assert_eq!(source(&data("age: f64")), "age: f64");
+        assert_eq!(source(&data("(ins 0 [\"Dolly\", \"Duck\"])")), "(ins 0 [\"Dolly\", \"Duck\"])");
+        assert_eq!(source(&data("struct Map<.K: Ord + Clone, .V> {keys: Vec<.K>, values: Vec<.V>}")),
+            "struct Map<.K: Ord + Clone, .V> {keys: Vec<.K>, values: Vec<.V>}");
+        assert_eq!(source(&data("[EdgeDir::Both, Str(\"Donald\"), (ref 3)]")),
+            "[EdgeDir::Both, Str(\"Donald\"), (ref 3)]");
+        assert_eq!(source(&data("[\"String\", \"true_name\"]")), "[\"String\", \"true_name\"]");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let files = [
+            include_str!("../assets/self_root.txt"),
+            include_str!("../source/test/generics/gen_map.txt"),
+            include_str!("../source/test/generics/gen_root.txt"),
+            include_str!("../source/test/graph/graph-with-refs.txt"),
+            include_str!("../source/test/person/person-constraints.txt"),
+            include_str!("../source/test/person/person-defaults.txt"),
+            include_str!("../source/test/person/meta-with-instances.txt"),
+            include_str!("../source/test/self/root-data.txt"),
+        ];
+        for src in files {
+            let root = data(src);
+            assert_eq!(data(&source(&root)), root);
+            assert_eq!(data(&document(&root)), root);
+        }
+        let doc = data(include_str!("../source/test/graph/graph-with-instances.txt"));
+        assert_eq!(document(&doc), "\
+[
+  enum Node {
+    Str(Arc<String>),
+    F64(f64),
+  },
+  enum EdgeDir {
+    Left,
+    Right,
+    Both,
+    None,
+  },
+  struct Edge {
+    from: usize,
+    to: usize,
+    dir: .EdgeDir,
+  },
+  (ins 0 Str(\"Donald\")),
+  (ins 0 Str(\"Dolly\")),
+  (ins 0 Str(\"Scrooge\")),
+  (ins 2 [3, 4, EdgeDir::Both]),
+  (ins 2 [5, 3, EdgeDir::Right]),
+]
+");
+    }
+}
@@ -0,0 +1,337 @@
+//! Query language over joined knowledge documents.
+//!
+//! A query is a pipeline of stages separated by `|`,
+//! starting with the name of a type whose instances are the initial rows:
+//!
+//! ```text
+//! Person | where last_name == "Duck" | select first_name
+//! Edge | where dir == Both | follow to Node
+//! Person | group last_name
+//! ```
+//!
+//! - `where <field> <op> <literal>` keeps rows where the comparison holds,
+//!   using `==`, `!=`, `<`, `<=`, `>` or `>=`
+//! - `select <field>, ...` keeps only the listed fields
+//! - `follow <field> <Type>` replaces each row by the instance of `Type`
+//!   referred to by the field, e.g. `from` in `Edge`, which is either a reference `(ref N)`
+//!   or an index, both counting all items of the document
+//! - `count` returns the number of rows
+//! - `group <field>` returns `[key, count]` for each distinct value of field
+//!
+//! Literals are numbers, strings, `true`, `false` or variant names, e.g. `Left`.
+//! The result is a tuple of rows, where struct rows are tuples of field values.
+
+use crate::*;
+use crate::value::{Record, Value};
+
+use std::cmp::Ordering;
+
+/// Evaluates a query against a document with declarations and instances.
+pub fn query(doc: &Root, query: &str) -> Result<Root, String> {
+    let instances = value::read_instances(doc)?;
+    let stages = parse_query(query)?;
+    let mut stages = stages.into_iter();
+    let mut rows: Vec<Value> = match stages.next() {
+        Some(Stage::From(class)) => instances.iter()
+            .filter(|ins| *ins.class == class)
+            .map(|ins| ins.value.clone())
+            .collect(),
+        _ => return Err("Expected type name at start of query".into()),
+    };
+    let mut out: Option<Vec<Root>> = None;
+    for stage in stages {
+        if out.is_some() {
+            return Err("`count` and `group` must be the last stage".into());
+        }
+        match stage {
+            Stage::From(_) => return Err("Type name is only allowed at start of query".into()),
+            Stage::Where(field, op, lit) => {
+                let mut res = vec![];
+                for row in rows {
+                    let val = field_of(&row, &field)?;
+                    if compare(val, &lit).map(|ord| op.holds(ord)).unwrap_or(false) {
+                        res.push(row);
+                    }
+                }
+                rows = res;
+            }
+            Stage::Select(fields) => {
+                rows = rows.into_iter().map(|row| {
+                    let rec = row.record().ok_or_else(|| format!("Can not select from `{}`",
+                        row.to_data()))?;
+                    let mut selected = vec![];
+                    for name in &fields {
+                        let f = rec.fields.iter().find(|(f, _)| f.name.as_str() == name)
+                            .ok_or_else(|| format!("`{}` has no field `{}`", rec.ty, name))?;
+                        selected.push(f.clone());
+                    }
+                    Ok(Value::Struct(Record {ty: rec.ty.clone(), fields: selected}))
+                }).collect::<Result<_, String>>()?;
+            }
+            Stage::Follow(field, class) => {
+                rows = rows.iter().map(|row| {
+                    let val = field_of(row, &field)?;
+                    let ind = match val.flatten() {
+                        Some(Value::Ref(r)) => r.index,
+                        _ => val.as_usize().ok_or_else(|| format!(
+                            "Expected index or reference in field `{}`, found `{}`", field, val.to_data()))?,
+                    };
+                    let ins = instances.iter().find(|ins| ins.index == ind).ok_or_else(|| format!(
+                        "Field `{}` refers to `{}`, which is not an instance", field, val.to_data()))?;
+                    if *ins.class != class {
+                        return Err(format!("Field `{}` refers to an instance of `{}`, expected `{}`",
+                            field, ins.class, class));
+                    }
+                    Ok(ins.value.clone())
+                }).collect::<Result<_, String>>()?;
+            }
+            Stage::Count => out = Some(vec![Root::F64(rows.len() as f64)]),
+            Stage::Group(field) => {
+                let mut groups: Vec<(Root, usize)> = vec![];
+                for row in &rows {
+                    let key = field_of(row, &field)?.to_data();
+                    match groups.iter_mut().find(|(k, _)| *k == key) {
+                        Some((_, n)) => *n += 1,
+                        None => groups.push((key, 1)),
+                    }
+                }
+                out = Some(groups.into_iter()
+                    .map(|(key, n)| Root::Tup(vec![key, Root::F64(n as f64)]))
+                    .collect());
+            }
+        }
+    }
+    Ok(Root::Tup(out.unwrap_or_else(|| rows.iter().map(|row| row.to_data()).collect())))
+}
+
+fn field_of<'a>(row: &'a Value, field: &str) -> Result<&'a Value, String> {
+    row.get(field).ok_or_else(|| format!("`{}` has no field `{}`",
+        row.type_name().unwrap_or("value"), field))
+}
+
+fn compare(val: &Value, lit: &Literal) -> Option<Ordering> {
+    let val = val.flatten()?;
+    match lit {
+        Literal::Num(x) => val.as_f64()?.partial_cmp(x),
+        Literal::Bool(x) => Some(val.as_bool()?.cmp(x)),
+        Literal::Str(x) => match val.variant() {
+            Some(name) => Some(name.cmp(x)),
+            None => Some(val.as_str()?.cmp(x)),
+        },
+        Literal::Ident(x) => Some(val.variant()?.cmp(x)),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Stage {
+    From(String),
+    Where(String, Op, Literal),
+    Select(Vec<String>),
+    Follow(String, String),
+    Count,
+    Group(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {Eq, Ne, Lt, Le, Gt, Ge}
+
+impl Op {
+    fn holds(self, ord: Ordering) -> bool {
+        match self {
+            Op::Eq => ord == Ordering::Equal,
+            Op::Ne => ord != Ordering::Equal,
+            Op::Lt => ord == Ordering::Less,
+            Op::Le => ord != Ordering::Greater,
+            Op::Gt => ord == Ordering::Greater,
+            Op::Ge => ord != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Literal {
+    Num(f64),
+    Str(String),
+    Bool(bool),
+    Ident(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Num(f64),
+    Str(String),
+    Op(Op),
+    Pipe,
+    Comma,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut res = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(i, ch)) = chars.peek() {
+        if ch.is_whitespace() {
+            chars.next();
+        } else if ch == '|' {
+            chars.next();
+            res.push(Token::Pipe);
+        } else if ch == ',' {
+            chars.next();
+            res.push(Token::Comma);
+        } else if ch == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => s.push(c),
+                        None => return Err("Unterminated string".into()),
+                    },
+                    Some((_, c)) => s.push(c),
+                    None => return Err("Unterminated string".into()),
+                }
+            }
+            res.push(Token::Str(s));
+        } else if "=!<>".contains(ch) {
+            chars.next();
+            let eq = chars.peek().map(|&(_, c)| c == '=').unwrap_or(false);
+            if eq {chars.next();}
+            res.push(Token::Op(match (ch, eq) {
+                ('=', true) => Op::Eq,
+                ('!', true) => Op::Ne,
+                ('<', false) => Op::Lt,
+                ('<', true) => Op::Le,
+                ('>', false) => Op::Gt,
+                ('>', true) => Op::Ge,
+                _ => return Err(format!("Unknown operator at {}", i)),
+            }));
+        } else if ch.is_ascii_digit() || ch == '-' {
+            let mut end = i;
+            while let Some(&(j, c)) = chars.peek() {
+                if c.is_ascii_digit() || c == '.' || c == '-' || c == 'e' || c == '_' {
+                    end = j + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let num = text[i..end].replace('_', "");
+            res.push(Token::Num(num.parse().map_err(|_|
+                format!("Invalid number `{}`", &text[i..end]))?));
+        } else if ch.is_alphanumeric() || ch == '_' {
+            let mut end = i;
+            while let Some(&(j, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    end = j + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            res.push(Token::Ident(text[i..end].into()));
+        } else {
+            return Err(format!("Unexpected character `{}` at {}", ch, i));
+        }
+    }
+    Ok(res)
+}
+
+fn parse_query(text: &str) -> Result<Vec<Stage>, String> {
+    let tokens = tokenize(text)?;
+    let mut stages = vec![];
+    for (i, part) in tokens.split(|t| *t == Token::Pipe).enumerate() {
+        let ident = |ind: usize| match part.get(ind) {
+            Some(Token::Ident(x)) => Ok(x.clone()),
+            x => Err(format!("Expected name, found `{:?}`", x)),
+        };
+        let end = |n: usize| if part.len() == n {Ok(())}
+            else {Err(format!("Unexpected tokens in stage {}", i))};
+        let stage = match part.first() {
+            _ if i == 0 => {end(1)?; Stage::From(ident(0)?)}
+            Some(Token::Ident(x)) => match x.as_str() {
+                "where" => {
+                    end(4)?;
+                    let op = if let Some(Token::Op(op)) = part.get(2) {*op}
+                        else {return Err("Expected operator in `where`".into())};
+                    let lit = match &part[3] {
+                        Token::Num(x) => Literal::Num(*x),
+                        Token::Str(x) => Literal::Str(x.clone()),
+                        Token::Ident(x) if x == "true" => Literal::Bool(true),
+                        Token::Ident(x) if x == "false" => Literal::Bool(false),
+                        Token::Ident(x) => Literal::Ident(x.clone()),
+                        x => return Err(format!("Expected literal, found `{:?}`", x)),
+                    };
+                    Stage::Where(ident(1)?, op, lit)
+                }
+                "select" => {
+                    let mut fields = vec![];
+                    for (j, t) in part.iter().enumerate().skip(1) {
+                        match (j % 2, t) {
+                            (1, Token::Ident(x)) => fields.push(x.clone()),
+                            (0, Token::Comma) => {}
+                            _ => return Err(format!("Unexpected `{:?}` in `select`", t)),
+                        }
+                    }
+                    if fields.is_empty() {return Err("Expected fields in `select`".into())}
+                    Stage::Select(fields)
+                }
+                "follow" => {end(3)?; Stage::Follow(ident(1)?, ident(2)?)}
+                "count" => {end(1)?; Stage::Count}
+                "group" => {end(2)?; Stage::Group(ident(1)?)}
+                x => return Err(format!("Unknown stage `{}`", x)),
+            },
+            _ => return Err(format!("Expected stage {}", i)),
+        };
+        stages.push(stage);
+    }
+    Ok(stages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(doc: &Root, q: &str) -> Root {
+        match query(doc, q) {
+            Ok(x) => x,
+            Err(err) => panic!("ERROR:\n{}", err),
+        }
+    }
+
+    #[test]
+    fn test_person() {
+        let doc = data(include_str!("../source/test/person/person-with-instance.txt"));
+        assert_eq!(run(&doc, "Person"), data(r#"[["Donald", "Duck"], ["Dolly", "Duck"]]"#));
+        assert_eq!(run(&doc, r#"Person | where first_name == "Dolly" | select last_name"#),
+            data(r#"[["Duck"]]"#));
+        assert_eq!(run(&doc, r#"Person | where first_name != "Dolly" | count"#), data("[1]"));
+        assert_eq!(run(&doc, "Person | group last_name"), data(r#"[["Duck", 2]]"#));
+        assert!(query(&doc, "Person | select age").is_err());
+        assert!(query(&doc, "Person | count | count").is_err());
+        assert!(query(&doc, "Person | sort").is_err());
+    }
+
+    #[test]
+    fn test_graph() {
+        let doc = data(include_str!("../source/test/graph/graph-with-instances.txt"));
+        assert_eq!(run(&doc, "Edge | where dir == Both | follow to Node"),
+            data(r#"[Str("Dolly")]"#));
+        assert_eq!(run(&doc, "Edge | where from >= 4 | select from, to"), data("[[5, 3]]"));
+        assert_eq!(run(&doc, "Edge | follow from Node"),
+            data(r#"[Str("Donald"), Str("Scrooge")]"#));
+        assert!(query(&doc, "Edge | follow dir Node").is_err());
+        assert_eq!(query(&doc, "Edge | follow from Edge").unwrap_err(),
+            "Field `from` refers to an instance of `Node`, expected `Edge`");
+    }
+
+    #[test]
+    fn test_refs() {
+        let doc = data(include_str!("../source/test/graph/graph-with-refs.txt"));
+        assert_eq!(run(&doc, "Edge | follow to Node"), data(r#"[Str("Dolly"), Str("Donald")]"#));
+        assert_eq!(run(&doc, "Edge | where dir == Right | follow from Node"),
+            data(r#"[Str("Scrooge")]"#));
+        let err = query(&doc, "Edge | follow to Edge").unwrap_err();
+        assert_eq!(err, "Field `to` refers to an instance of `Node`, expected `Edge`");
+    }
+}
@@ -0,0 +1,145 @@
+//! References between instances in a document.
+//!
+//! A field of type `Ref<T>` refers to an instance of `T` by its index in the document,
+//! written `(ref N)` in data:
+//!
+//! ```text
+//! [
+//!   enum Node {Str(Arc<String>), F64(f64)},
+//!   struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>},
+//!   (ins 0 Str("Donald")),
+//!   (ins 0 Str("Dolly")),
+//!   (ins 1 [(ref 2), (ref 3)]),
+//! ]
+//! ```
+//!
+//! A reference is dangling when the item at its index is not an instance of `T`.
+
+use crate::*;
+use crate::constraint::Violation;
+use crate::path::Path;
+use crate::schema::*;
+use crate::value::{Record, Reference, Value};
+use crate::visit::VisitorMut;
+
+/// Returns all references in the instances of a document, with their paths.
+pub fn references(doc: &Root) -> Result<Vec<(Path, Reference)>, String> {
+    let mut res = vec![];
+    for ins in value::read_instances(doc)? {
+        let path = Path::root().index(ins.index).name(&Arc::new("data".into()));
+        collect(&ins.value, &path, &mut res);
+    }
+    Ok(res)
+}
+
+fn collect(v: &Value, path: &Path, res: &mut Vec<(Path, Reference)>) {
+    match v {
+        Value::Ref(r) => res.push((path.clone(), r.clone())),
+        Value::Option(Some(x)) => collect(x, path, res),
+        Value::List(items) | Value::Tuple(items) => for (i, it) in items.iter().enumerate() {
+            collect(it, &path.index(i), res);
+        },
+        Value::Struct(rec) => collect_fields(rec, path, res),
+        Value::Variant(var) => match var.data.as_deref() {
+            Some(Value::Struct(rec)) if rec.ty == var.name => collect_fields(rec, path, res),
+            Some(data) => collect(data, &path.name(&var.name), res),
+            None => {}
+        },
+        Value::F64(_) | Value::Bool(_) | Value::Usize(_) | Value::Str(_) | Value::Option(None) => {}
+    }
+}
+
+fn collect_fields(rec: &Record, path: &Path, res: &mut Vec<(Path, Reference)>) {
+    for (f, v) in &rec.fields {
+        collect(v, &path.name(&f.name), res);
+    }
+}
+
+/// Returns the name of the type of an instance at an index in a document.
+///
+/// Returns `None` if the item is not an instance.
+pub fn class_of(doc: &Root, ind: usize) -> Option<Arc<String>> {
+    let items = if let Root::Tup(items) = doc {items} else {return None};
+    match items.get(ind)? {
+        Root::Instance {class, ..} => match items.get(*class)? {
+            decl @ Root::Struct {..} | decl @ Root::Enum {..} =>
+                Def::from_root(decl).ok().map(|d| d.name().clone()),
+            _ => None,
+        },
+        Root::InstanceTy {ty, ..} => match TypeRef::from_root(ty, &[]).ok()? {
+            TypeRef::Named(name) => Some(name),
+            TypeRef::Applied(base, _) => match *base {
+                TypeRef::Named(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Checks that every reference in a document refers to an instance of the right type.
+///
+/// Returns all dangling references.
+pub fn check(doc: &Root) -> Result<Vec<Violation>, String> {
+    Ok(references(doc)?.into_iter().filter_map(|(path, r)| {
+        let message = match class_of(doc, r.index) {
+            Some(class) if class == r.ty => return None,
+            Some(class) => format!("Reference `{}` to `{}` refers to an instance of `{}`",
+                Root::Ref(r.index), r.ty, class),
+            None => format!("Dangling reference `{}` to `{}`", Root::Ref(r.index), r.ty),
+        };
+        Some(Violation {path, message})
+    }).collect())
+}
+
+/// Replaces every reference `(ref N)` in a `Root` with `(ref map(N))`.
+///
+/// Used when instances are moved to other indices.
+pub fn renumber(root: &mut Root, map: &dyn Fn(usize) -> usize) {
+    struct Renumber<'a>(&'a dyn Fn(usize) -> usize);
+
+    impl VisitorMut for Renumber<'_> {
+        fn visit_ref_mut(&mut self, ind: &mut usize) {*ind = (self.0)(*ind)}
+    }
+
+    Renumber(map).visit_mut(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let doc = parsing::parse_str(
+            include_str!("../source/test/graph/graph-with-refs.txt"), &[]).unwrap();
+        assert_eq!(parsing::parse_str(&format!("{}", doc), &[]).unwrap(), doc);
+        validate::validate_document(&doc).unwrap();
+        let refs = references(&doc).unwrap();
+        assert_eq!(refs.len(), 4);
+        assert_eq!(format!("{}", refs[1].0), "[6].data.to");
+        assert_eq!(refs[1].1, Reference {ty: Arc::new("Node".into()), index: 4});
+
+        let mut doc = doc;
+        *doc.select_mut(&Path::parse("[7].data.from").unwrap()).unwrap() = Root::Ref(6);
+        *doc.select_mut(&Path::parse("[7].data.to").unwrap()).unwrap() = Root::Ref(9);
+        let violations: Vec<String> = check(&doc).unwrap().iter().map(|v| format!("{}", v)).collect();
+        assert_eq!(violations, vec![
+            "[7].data.from: Reference `(ref 6)` to `Node` refers to an instance of `Edge`",
+            "[7].data.to: Dangling reference `(ref 9)` to `Node`",
+        ]);
+        assert!(validate::validate_document(&doc).is_err());
+
+        let edge = parsing::parse_str(
+            "[struct Edge {from: Ref<(ty Node)>, to: Ref<(ty Node)>}, (ins 0 [0, 1])]", &[]).unwrap();
+        assert!(value::read_instances(&edge).unwrap_err().starts_with("[1].data.from: "));
+    }
+
+    #[test]
+    fn test_renumber() {
+        let mut doc = parsing::parse_str("[(ref 3), Foo((ref 4)), [a: (ref 5)]]", &[]).unwrap();
+        renumber(&mut doc, &|i| i + 1);
+        assert_eq!(doc, parsing::parse_str("[(ref 4), Foo((ref 5)), [a: (ref 6)]]", &[]).unwrap());
+    }
+}
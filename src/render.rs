@@ -0,0 +1,275 @@
+//! Rendering of formats and graph data as Graphviz DOT or Mermaid text.
+//!
+//! Type diagrams show one node per struct or enum declaration, listing its fields or variants,
+//! with an edge for every reference to another declaration or to `Self`.
+//! Edges are labeled with the field or variant and the types wrapping the reference,
+//! e.g. `fields: Vec` for `fields: Vec<Self>`.
+//!
+//! Graph data is rendered with arrows following `EdgeDir`.
+//!
+//! The output can be rendered offline, e.g. with `dot -Tsvg types.dot > types.svg`
+//! or the Mermaid command line tool.
+
+use crate::*;
+use crate::graph::{EdgeDir, Graph};
+use crate::schema::*;
+
+use std::fmt::Write;
+
+/// A reference from a declaration to a type.
+struct TypeEdge {
+    from: Arc<String>,
+    to: Arc<String>,
+    label: String,
+    /// Whether the reference is a `Ref<T>` to an instance, rather than contained data.
+    reference: bool,
+}
+
+/// Renders declarations as a Graphviz DOT type diagram.
+pub fn types_dot(schema: &Schema) -> String {
+    let mut out = String::from("digraph types {\n  node [shape=record];\n");
+    for def in &schema.defs {
+        let (kind, lines) = members(def);
+        let body: String = lines.iter().map(|l| format!("{}\\l", escape_record(l))).collect();
+        writeln!(out, "  {} [label=\"{{{} {}|{}}}\"];", quote(def.name()), kind,
+            escape_record(def.name()), body).unwrap();
+    }
+    for e in type_edges(schema) {
+        let style = if e.reference {", style=dashed"} else {""};
+        writeln!(out, "  {} -> {} [label={}{}];", quote(&e.from), quote(&e.to), quote(&e.label), style)
+            .unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Renders declarations as a Mermaid class diagram.
+pub fn types_mermaid(schema: &Schema) -> String {
+    let mut out = String::from("classDiagram\n");
+    for def in &schema.defs {
+        let (kind, lines) = members(def);
+        writeln!(out, "  class {} {{\n    <<{}>>", def.name(), kind).unwrap();
+        for l in lines {
+            writeln!(out, "    {}", mermaid_generics(&l)).unwrap();
+        }
+        out.push_str("  }\n");
+    }
+    for e in type_edges(schema) {
+        let arrow = if e.reference {"..>"} else {"-->"};
+        writeln!(out, "  {} {} {} : {}", e.from, arrow, e.to, mermaid_generics(&e.label)).unwrap();
+    }
+    out
+}
+
+/// Renders graph data as a Graphviz DOT graph.
+///
+/// Edges keep their `from` and `to` order, using `dir` to set the arrows.
+pub fn graph_dot(graph: &Graph) -> String {
+    let mut out = String::from("digraph data {\n");
+    for (i, node) in graph.nodes().iter().enumerate() {
+        writeln!(out, "  n{} [label={}];", i, quote(&data_label(node))).unwrap();
+    }
+    for e in graph.edges() {
+        let attrs = match e.dir {
+            EdgeDir::Right => "",
+            EdgeDir::Left => " [dir=back]",
+            EdgeDir::Both => " [dir=both]",
+            EdgeDir::None => " [dir=none, style=dashed]",
+        };
+        writeln!(out, "  n{} -> n{}{};", e.from, e.to, attrs).unwrap();
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Renders graph data as a Mermaid flowchart.
+pub fn graph_mermaid(graph: &Graph) -> String {
+    let mut out = String::from("flowchart LR\n");
+    for (i, node) in graph.nodes().iter().enumerate() {
+        writeln!(out, "  n{}[\"{}\"]", i, data_label(node).replace('"', "#quot;")).unwrap();
+    }
+    for e in graph.edges() {
+        match e.dir {
+            EdgeDir::Right => writeln!(out, "  n{} --> n{}", e.from, e.to),
+            EdgeDir::Left => writeln!(out, "  n{} --> n{}", e.to, e.from),
+            EdgeDir::Both => writeln!(out, "  n{} <--> n{}", e.from, e.to),
+            EdgeDir::None => writeln!(out, "  n{} --- n{}", e.from, e.to),
+        }.unwrap();
+    }
+    out
+}
+
+/// Returns the kind of declaration and a line per field or variant.
+///
+/// Variants with fields are written like tuple variants, e.g. `Circle(r: f64)`,
+/// since braces end a class in Mermaid.
+fn members(def: &Def) -> (&'static str, Vec<String>) {
+    let field = |f: &FieldDef| format!("{}: {}", f.name, f.ty);
+    match def {
+        Def::Struct(s) => ("struct", s.fields.iter().map(field).collect()),
+        Def::Enum(e) => ("enum", e.variants.iter().map(|v| match &v.data {
+            VariantData::Unit => format!("{}", v.name),
+            VariantData::Type(ty) => format!("{}({})", v.name, ty),
+            VariantData::Fields(fields) => format!("{}({})", v.name,
+                fields.iter().map(field).collect::<Vec<_>>().join(", ")),
+        }).collect()),
+    }
+}
+
+/// Returns references between declarations, including `Self` loops.
+///
+/// Named types that are not declared get an edge too, e.g. `Node` in a format of edges.
+fn type_edges(schema: &Schema) -> Vec<TypeEdge> {
+    let mut res = vec![];
+    for def in &schema.defs {
+        let mut add = |location: String, ty: &TypeRef| {
+            let mut targets = vec![];
+            targets_of(ty, &mut vec![], &mut targets);
+            for (to, wrappers) in targets {
+                let to = to.unwrap_or_else(|| def.name().clone());
+                let label = if wrappers.is_empty() {location.clone()}
+                    else {format!("{}: {}", location, wrappers.join(" "))};
+                let reference = wrappers.iter().any(|w| w == "Ref");
+                // Tuples may refer to the same type more than once, e.g. `box<(Self, Self)>`.
+                if res.iter().any(|e: &TypeEdge| *e.from == **def.name() && e.to == to && e.label == label) {
+                    continue;
+                }
+                res.push(TypeEdge {from: def.name().clone(), to, label, reference});
+            }
+        };
+        match def {
+            Def::Struct(s) => for f in &s.fields {add(format!("{}", f.name), &f.ty)},
+            Def::Enum(e) => for v in &e.variants {
+                match &v.data {
+                    VariantData::Unit => {}
+                    VariantData::Type(ty) => add(format!("{}", v.name), ty),
+                    VariantData::Fields(fields) => for f in fields {
+                        add(format!("{}.{}", v.name, f.name), &f.ty)
+                    },
+                }
+            },
+        }
+    }
+    res
+}
+
+/// Collects named types in a type with the wrappers around them, e.g. `Vec` in `Vec<Self>`.
+///
+/// `Self` is returned as `None`.
+fn targets_of(ty: &TypeRef, wrappers: &mut Vec<String>, res: &mut Vec<(Option<Arc<String>>, Vec<String>)>) {
+    match ty {
+        TypeRef::Named(name) => res.push((Some(name.clone()), wrappers.clone())),
+        TypeRef::SelfRef => res.push((None, wrappers.clone())),
+        TypeRef::Primitive(_) | TypeRef::Generic(_) => {}
+        TypeRef::Tuple(items) => for it in items {targets_of(it, wrappers, res)},
+        TypeRef::Applied(base, args) => {
+            targets_of(base, wrappers, res);
+            wrappers.push(match &**base {
+                TypeRef::Primitive(name) | TypeRef::Named(name) => format!("{}", name),
+                base => format!("{}", base),
+            });
+            for arg in args {targets_of(arg, wrappers, res)}
+            wrappers.pop();
+        }
+    }
+}
+
+/// Returns a short label for data, e.g. `Str("Donald")` or `[0, 1]`.
+fn data_label(data: &Root) -> String {
+    match data {
+        Root::Str(s) => format!("{:?}", s),
+        Root::F64(_) | Root::Bool(_) => format!("{}", data),
+        Root::Tup(items) => format!("[{}]", items.iter().map(data_label).collect::<Vec<_>>().join(", ")),
+        Root::Avatar(ab) => match &ab.0 {
+            Root::Str(name) => format!("{}({})", name, data_label(&ab.1)),
+            _ => format!("{}", data),
+        },
+        Root::Field(kv) => match &kv.0 {
+            Root::Str(name) => format!("{}: {}", name, data_label(&kv.1)),
+            _ => format!("{}", data),
+        },
+        Root::InstanceTy {ty, data: Some(inner)} => match (&**ty, &**inner) {
+            (Root::Ty(ty), Root::Str(name)) => format!("{}::{}", ty, name),
+            _ => format!("{}", data),
+        },
+        _ => format!("{}", data),
+    }
+}
+
+/// Quotes a DOT identifier or label.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Escapes characters with special meaning in DOT record labels.
+fn escape_record(text: &str) -> String {
+    let mut res = String::new();
+    for ch in text.chars() {
+        if matches!(ch, '{' | '}' | '|' | '<' | '>' | '"' | '\\') {res.push('\\')}
+        res.push(ch);
+    }
+    res
+}
+
+/// Mermaid writes generics as `Vec~Self~`.
+fn mermaid_generics(text: &str) -> String {
+    text.replace(['<', '>'], "~")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_types() {
+        let schema = Schema::from_root(&root_self()).unwrap();
+        let dot = types_dot(&schema);
+        assert!(dot.contains("\"Root\" [label=\"{enum Root|Ty(Arc\\<String\\>)\\lStr(Arc\\<String\\>)\\l"), "{}", dot);
+        assert!(dot.contains("  \"Root\" -> \"Root\" [label=\"Avatar: box\"];\n"));
+        assert!(dot.contains("  \"Root\" -> \"Root\" [label=\"Instance.data: Option box\"];\n"));
+
+        let mermaid = types_mermaid(&schema);
+        assert!(mermaid.starts_with("classDiagram\n  class Root {\n    <<enum>>\n    Ty(Arc~String~)\n"));
+        assert!(mermaid.contains("  Root --> Root : Tup: Vec\n"));
+
+        let doc = parsing::parse_str(include_str!("../source/test/graph/graph-with-refs.txt"), &[]).unwrap();
+        let schema = Schema::from_root(&doc).unwrap();
+        assert!(types_dot(&schema).contains("  \"Edge\" -> \"Node\" [label=\"from: Ref\", style=dashed];\n"));
+        assert!(types_mermaid(&schema).contains("  Edge --> EdgeDir : dir\n"));
+        assert!(types_mermaid(&schema).contains("  Edge ..> Node : to: Ref\n"));
+
+        let doc = parsing::parse_str("enum Shape {Circle {r: f64}, Rect {w: f64, h: f64}, Empty}", &[]).unwrap();
+        let schema = Schema::from_root(&doc).unwrap();
+        assert_eq!(types_mermaid(&schema), "classDiagram
+  class Shape {
+    <<enum>>
+    Circle(r: f64)
+    Rect(w: f64, h: f64)
+    Empty
+  }
+");
+        assert!(types_dot(&schema).contains("|Circle(r: f64)\\lRect(w: f64, h: f64)\\lEmpty\\l}"));
+    }
+
+    #[test]
+    fn test_graph() {
+        let doc = parsing::parse_str(
+            include_str!("../source/test/graph/graph-with-instances.txt"), &[]).unwrap();
+        let graph = Graph::from_document(&doc).unwrap();
+        assert_eq!(graph_dot(&graph), "digraph data {
+  n0 [label=\"Str(\\\"Donald\\\")\"];
+  n1 [label=\"Str(\\\"Dolly\\\")\"];
+  n2 [label=\"Str(\\\"Scrooge\\\")\"];
+  n0 -> n1 [dir=both];
+  n2 -> n0;
+}
+");
+        assert_eq!(graph_mermaid(&graph), "flowchart LR
+  n0[\"Str(#quot;Donald#quot;)\"]
+  n1[\"Str(#quot;Dolly#quot;)\"]
+  n2[\"Str(#quot;Scrooge#quot;)\"]
+  n0 <--> n1
+  n2 --> n0
+");
+    }
+}
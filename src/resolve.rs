@@ -0,0 +1,221 @@
+//! Resolution of type references into a recursive type graph.
+//!
+//! Every definition becomes a node and every reference to a definition,
+//! including `Self` and `Self<.T>`, becomes an edge to the referenced node.
+
+use crate::*;
+use crate::schema::*;
+
+/// Names of built-in types that store their content behind a pointer.
+///
+/// `Option` is not included, since it stores its content inline.
+pub const INDIRECTIONS: &[&str] = &["box", "Vec", "Arc"];
+
+/// A graph of type nodes, one per definition in a schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeGraph {
+    /// Type nodes, in the same order as the schema definitions.
+    pub nodes: Vec<TypeNode>,
+}
+
+/// A type node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeNode {
+    /// Name of definition.
+    pub name: Arc<String>,
+    /// References to other definitions or to itself.
+    pub edges: Vec<TypeEdge>,
+    /// Named types that are not declared in the schema.
+    pub unresolved: Vec<Arc<String>>,
+}
+
+/// A reference from one type node to another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeEdge {
+    /// Index of the referenced node.
+    pub target: usize,
+    /// Where the reference occurs, e.g. `Struct.name` or `Avatar`.
+    pub location: String,
+    /// Type arguments of reference, e.g. `.T` in `Self<.T>`.
+    pub args: Vec<TypeRef>,
+    /// Whether the reference goes through `Box`, `Vec` or `Arc`.
+    pub indirect: bool,
+}
+
+/// Resolves a schema into a type graph and checks that every type has finite size.
+pub fn resolve(schema: &Schema) -> Result<TypeGraph, String> {
+    let graph = TypeGraph::from_schema(schema);
+    graph.check_size()?;
+    Ok(graph)
+}
+
+impl TypeGraph {
+    /// Builds type graph from schema.
+    ///
+    /// `Self` is bound to the enclosing struct or enum.
+    /// Inside enum variants, `Self` refers to the enum, not the variant.
+    pub fn from_schema(schema: &Schema) -> TypeGraph {
+        let mut nodes = vec![];
+        for (i, def) in schema.defs.iter().enumerate() {
+            let mut node = TypeNode {name: def.name().clone(), edges: vec![], unresolved: vec![]};
+            let mut add = |location: String, ty: &TypeRef|
+                collect(schema, i, &location, ty, false, &mut node);
+            match def {
+                Def::Struct(s) => for f in &s.fields {add(format!("{}", f.name), &f.ty)},
+                Def::Enum(e) => for v in &e.variants {
+                    match &v.data {
+                        VariantData::Unit => {}
+                        VariantData::Type(ty) => add(format!("{}", v.name), ty),
+                        VariantData::Fields(fields) => for f in fields {
+                            add(format!("{}.{}", v.name, f.name), &f.ty)
+                        },
+                    }
+                },
+            }
+            nodes.push(node);
+        }
+        TypeGraph {nodes}
+    }
+
+    /// Finds a node by name.
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|n| n.name.as_str() == name)
+    }
+
+    /// Returns `true` if the type refers to itself, directly or through other types.
+    pub fn is_recursive(&self, node: usize) -> bool {
+        self.cycle_from(node, false).is_some()
+    }
+
+    /// Checks that no type contains itself without indirection,
+    /// similar to Rust's error E0072.
+    pub fn check_size(&self) -> Result<(), String> {
+        for i in 0..self.nodes.len() {
+            if let Some(cycle) = self.cycle_from(i, true) {
+                let path: Vec<String> = cycle.iter()
+                    .map(|&(n, e)| format!("{}.{}", self.nodes[n].name, self.nodes[n].edges[e].location))
+                    .collect();
+                return Err(format!("Recursive type `{}` has infinite size: {} -> {}\n\
+                    Insert indirection, e.g. `Box`, `Vec` or `Arc`",
+                    self.nodes[i].name, path.join(" -> "), self.nodes[i].name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds a cycle back to `start` as a list of `(node, edge)` pairs.
+    fn cycle_from(&self, start: usize, direct_only: bool) -> Option<Vec<(usize, usize)>> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack: Vec<(usize, usize)> = vec![];
+        self.search(start, start, direct_only, &mut visited, &mut stack)
+            .then_some(stack)
+    }
+
+    fn search(
+        &self,
+        start: usize,
+        node: usize,
+        direct_only: bool,
+        visited: &mut [bool],
+        stack: &mut Vec<(usize, usize)>,
+    ) -> bool {
+        for (e, edge) in self.nodes[node].edges.iter().enumerate() {
+            if direct_only && edge.indirect {continue}
+            stack.push((node, e));
+            if edge.target == start {return true}
+            if !visited[edge.target] {
+                visited[edge.target] = true;
+                if self.search(start, edge.target, direct_only, visited, stack) {return true}
+            }
+            stack.pop();
+        }
+        false
+    }
+}
+
+fn collect(
+    schema: &Schema,
+    current: usize,
+    location: &str,
+    ty: &TypeRef,
+    indirect: bool,
+    node: &mut TypeNode,
+) {
+    let mut edge = |target: usize, args: &[TypeRef]| node.edges.push(TypeEdge {
+        target,
+        location: location.into(),
+        args: args.to_vec(),
+        indirect,
+    });
+    match ty {
+        TypeRef::SelfRef => edge(current, &[]),
+        TypeRef::Named(name) => match schema.defs.iter().position(|d| d.name() == name) {
+            Some(target) => edge(target, &[]),
+            None => if !node.unresolved.contains(name) {node.unresolved.push(name.clone())},
+        },
+        TypeRef::Applied(base, args) => match &**base {
+            TypeRef::SelfRef => edge(current, args),
+            TypeRef::Named(name) => {
+                match schema.defs.iter().position(|d| d.name() == name) {
+                    Some(target) => edge(target, args),
+                    None => if !node.unresolved.contains(name) {node.unresolved.push(name.clone())},
+                }
+                for arg in args {collect(schema, current, location, arg, indirect, node)}
+            }
+            TypeRef::Primitive(name) => {
+                let indirect = indirect || INDIRECTIONS.contains(&name.as_str());
+                for arg in args {collect(schema, current, location, arg, indirect, node)}
+            }
+            _ => for arg in args {collect(schema, current, location, arg, indirect, node)},
+        },
+        TypeRef::Tuple(items) => for it in items {
+            collect(schema, current, location, it, indirect, node)
+        },
+        TypeRef::Primitive(_) | TypeRef::Generic(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(src: &str) -> Schema {
+        match parsing::parse_str(src, &[]) {
+            Ok(x) => Schema::from_root(&x).unwrap(),
+            Err(err) => panic!("ERROR:\n{}", err),
+        }
+    }
+
+    #[test]
+    fn test_self() {
+        let graph = resolve(&Schema::from_root(&root_self()).unwrap()).unwrap();
+        assert_eq!(graph.nodes.len(), 1);
+        assert!(graph.is_recursive(0));
+        assert!(graph.nodes[0].edges.iter().all(|e| e.target == 0 && e.indirect));
+        assert!(graph.nodes[0].edges.iter().any(|e| e.location == "Struct.name"));
+
+        let gen_root = schema(include_str!("../source/test/generics/gen_root.txt"));
+        let graph = resolve(&gen_root).unwrap();
+        assert_eq!(graph.nodes[0].edges[0].location, "Avatar");
+        assert_eq!(graph.nodes[0].edges[0].args, vec![TypeRef::Generic(Arc::new("T".into()))]);
+    }
+
+    #[test]
+    fn test_infinite_size() {
+        assert!(resolve(&schema("struct List {next: Self}")).is_err());
+        assert!(resolve(&schema("struct List {next: Option<Self>}")).is_err());
+        assert!(resolve(&schema("struct List {next: Option<Box<Self>>}")).is_ok());
+        assert!(resolve(&schema("[
+            struct A {b: (ty B)},
+            struct B {a: (ty A)},
+        ]")).is_err());
+        assert!(resolve(&schema("[
+            struct A {b: (ty B)},
+            struct B {a: Vec<(ty A)>},
+        ]")).is_ok());
+
+        let edge = resolve(&schema(include_str!("../source/test/graph/edge.txt"))).unwrap();
+        assert_eq!(edge.nodes[0].unresolved, vec![Arc::new("EdgeDir".into())]);
+        assert!(!edge.is_recursive(0));
+    }
+}
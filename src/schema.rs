@@ -0,0 +1,747 @@
+//! Semantic type model layered over `Root`.
+//!
+//! A `Schema` is a list of well-formed type definitions,
+//! converted from and back to the `Root` representation.
+
+use crate::*;
+
+/// Names of built-in types.
+pub const PRIMITIVES: &[&str] = &[
+    "String", "f64", "bool", "usize", "Arc", "box", "Option", "Vec", "Ref"
+];
+
+/// A collection of type definitions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    /// Type definitions.
+    pub defs: Vec<Def>,
+}
+
+/// A type definition.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Def {
+    /// A struct definition.
+    Struct(StructDef),
+    /// An enum definition.
+    Enum(EnumDef),
+}
+
+/// A struct definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructDef {
+    /// Name of struct.
+    pub name: Arc<String>,
+    /// Generic parameters.
+    pub params: Vec<ParamDef>,
+    /// Fields of struct.
+    pub fields: Vec<FieldDef>,
+}
+
+/// An enum definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnumDef {
+    /// Name of enum.
+    pub name: Arc<String>,
+    /// Generic parameters.
+    pub params: Vec<ParamDef>,
+    /// Variants of enum.
+    pub variants: Vec<VariantDef>,
+}
+
+/// A generic parameter, e.g. `.T: Ord`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParamDef {
+    /// Name of parameter.
+    pub name: Arc<String>,
+    /// Bounds that arguments must satisfy, e.g. `Ord`.
+    pub bounds: Vec<Arc<String>>,
+}
+
+/// A field definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDef {
+    /// Name of field.
+    pub name: Arc<String>,
+    /// Type of field.
+    pub ty: TypeRef,
+    /// Data used when the field is omitted, e.g. `0` in `age: f64 = 0`.
+    pub default: Option<Root>,
+    /// Attributes in declared order, e.g. `range(0, 150)` in `#[range(0, 150)] age: f64`.
+    pub attrs: Vec<Root>,
+}
+
+/// A variant definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VariantDef {
+    /// Name of variant.
+    pub name: Arc<String>,
+    /// Data of variant.
+    pub data: VariantData,
+}
+
+/// Data carried by an enum variant.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VariantData {
+    /// No data, e.g. `Left`.
+    Unit,
+    /// A single type, e.g. `F64(f64)`.
+    Type(TypeRef),
+    /// Named fields, e.g. `Instance {class: usize, data: ...}`.
+    Fields(Vec<FieldDef>),
+}
+
+/// A reference to a type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeRef {
+    /// A built-in type, e.g. `f64` or `Vec`.
+    Primitive(Arc<String>),
+    /// A user defined type, e.g. `EdgeDir`.
+    Named(Arc<String>),
+    /// A generic parameter, e.g. `.T`.
+    Generic(Arc<String>),
+    /// A type applied to arguments, e.g. `Vec<Self>`.
+    Applied(Box<TypeRef>, Vec<TypeRef>),
+    /// A tuple type, e.g. `(Self, Self)`.
+    Tuple(Vec<TypeRef>),
+    /// The `Self` type.
+    SelfRef,
+}
+
+impl Schema {
+    /// Converts a struct, an enum or a tuple of these into a schema.
+    ///
+    /// Instances in a tuple are skipped,
+    /// such that a document joined with data can be used.
+    pub fn from_root(root: &Root) -> Result<Schema, String> {
+        let mut defs = vec![];
+        match root {
+            Root::Tup(items) => {
+                for item in items {
+                    match item {
+                        Root::Instance {..} | Root::InstanceTy {..} => {}
+                        _ => defs.push(Def::from_root(item)?),
+                    }
+                }
+            }
+            _ => defs.push(Def::from_root(root)?),
+        }
+        Ok(Schema {defs})
+    }
+
+    /// Converts schema back into `Root`.
+    ///
+    /// A single definition is returned directly, otherwise wrapped in a tuple.
+    pub fn to_root(&self) -> Root {
+        if self.defs.len() == 1 {
+            self.defs[0].to_root()
+        } else {
+            Root::Tup(self.defs.iter().map(|d| d.to_root()).collect())
+        }
+    }
+
+    /// Finds a definition by name.
+    pub fn get(&self, name: &str) -> Option<&Def> {
+        self.defs.iter().find(|d| d.name().as_str() == name)
+    }
+
+    /// Instantiates the definition referenced by a type, e.g. `Foo<f64>`.
+    pub fn instantiate(&self, ty: &TypeRef) -> Result<Def, String> {
+        let (name, args) = match ty {
+            TypeRef::Named(name) => (name, &[][..]),
+            TypeRef::Applied(base, args) => match &**base {
+                TypeRef::Named(name) => (name, &args[..]),
+                _ => return Err(format!("Expected named type, found `{}`", ty)),
+            },
+            _ => return Err(format!("Expected named type, found `{}`", ty)),
+        };
+        let def = self.get(name).ok_or_else(|| format!("Unknown type `{}`", name))?;
+        def.instantiate(args)
+    }
+
+    /// Checks that every use of a generic type has the declared number of arguments.
+    pub fn check_arity(&self) -> Result<(), String> {
+        for def in &self.defs {
+            for ty in def.types() {
+                self.check_type_arity(ty, def)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_type_arity(&self, ty: &TypeRef, def: &Def) -> Result<(), String> {
+        let (base, args) = match ty {
+            TypeRef::Applied(base, args) => (&**base, &args[..]),
+            TypeRef::Tuple(items) => {
+                for it in items {self.check_type_arity(it, def)?}
+                return Ok(());
+            }
+            _ => (ty, &[][..]),
+        };
+        let expected = match base {
+            TypeRef::Primitive(name) => primitive_arity(name),
+            TypeRef::Named(name) => match self.get(name) {
+                Some(d) => d.params().len(),
+                // Types declared elsewhere can not be checked.
+                None => args.len(),
+            },
+            // `Self` may be used without arguments inside its own definition.
+            TypeRef::SelfRef if args.is_empty() => 0,
+            TypeRef::SelfRef => def.params().len(),
+            TypeRef::Generic(_) => 0,
+            _ => return Err(format!("Expected type constructor, found `{}`", base)),
+        };
+        if args.len() != expected {
+            return Err(format!("`{}` expects {} generic argument(s), found {} in `{}`",
+                base, expected, args.len(), ty));
+        }
+        for arg in args {self.check_type_arity(arg, def)?}
+        Ok(())
+    }
+}
+
+impl Def {
+    /// Converts a struct or enum declaration into a definition.
+    pub fn from_root(root: &Root) -> Result<Def, String> {
+        match root {
+            Root::Struct {name, fields} => {
+                let (name, params) = name_from_root(name)?;
+                let fields = fields_from_root(fields, &params)?;
+                Ok(Def::Struct(StructDef {name, params, fields}))
+            }
+            Root::Enum {name, variants} => {
+                let (name, params) = name_from_root(name)?;
+                let variants = variants.iter()
+                    .map(|v| VariantDef::from_root(v, &params))
+                    .collect::<Result<_, _>>()?;
+                Ok(Def::Enum(EnumDef {name, params, variants}))
+            }
+            _ => Err(format!("Expected struct or enum, found `{}`", root)),
+        }
+    }
+
+    /// Converts definition back into `Root`.
+    pub fn to_root(&self) -> Root {
+        match self {
+            Def::Struct(s) => Root::Struct {
+                name: Box::new(name_to_root(&s.name, &s.params)),
+                fields: s.fields.iter().map(|f| f.to_root()).collect(),
+            },
+            Def::Enum(e) => Root::Enum {
+                name: Box::new(name_to_root(&e.name, &e.params)),
+                variants: e.variants.iter().map(|v| v.to_root()).collect(),
+            },
+        }
+    }
+
+    /// Returns the name of definition.
+    pub fn name(&self) -> &Arc<String> {
+        match self {
+            Def::Struct(s) => &s.name,
+            Def::Enum(e) => &e.name,
+        }
+    }
+
+    /// Returns the generic parameters of definition.
+    pub fn params(&self) -> &[ParamDef] {
+        match self {
+            Def::Struct(s) => &s.params,
+            Def::Enum(e) => &e.params,
+        }
+    }
+
+    /// Returns all types used by fields and variants.
+    pub fn types(&self) -> Vec<&TypeRef> {
+        match self {
+            Def::Struct(s) => s.fields.iter().map(|f| &f.ty).collect(),
+            Def::Enum(e) => {
+                let mut res = vec![];
+                for v in &e.variants {
+                    match &v.data {
+                        VariantData::Unit => {}
+                        VariantData::Type(ty) => res.push(ty),
+                        VariantData::Fields(fields) => res.extend(fields.iter().map(|f| &f.ty)),
+                    }
+                }
+                res
+            }
+        }
+    }
+
+    /// Replaces generic parameters with concrete types,
+    /// e.g. `Foo<f64>` turns `struct Foo<.T> {inner: .T}` into `struct Foo {inner: f64}`.
+    pub fn instantiate(&self, args: &[TypeRef]) -> Result<Def, String> {
+        let params = self.params();
+        if params.len() != args.len() {
+            return Err(format!("`{}` expects {} generic argument(s), found {}",
+                self.name(), params.len(), args.len()));
+        }
+        for (p, arg) in params.iter().zip(args) {
+            for bound in &p.bounds {
+                if !arg.satisfies(bound) {
+                    return Err(format!("`{}` does not satisfy `{}` required by `.{}` in `{}`",
+                        arg, bound, p.name, self.name()));
+                }
+            }
+        }
+        let inst = |ty: &TypeRef| ty.substitute(params, args).without_self_args();
+        let inst_fields = |fields: &[FieldDef]| fields.iter()
+            .map(|f| FieldDef {name: f.name.clone(), ty: inst(&f.ty), default: f.default.clone(),
+                attrs: f.attrs.clone()})
+            .collect::<Vec<_>>();
+        Ok(match self {
+            Def::Struct(s) => Def::Struct(StructDef {
+                name: s.name.clone(),
+                params: vec![],
+                fields: inst_fields(&s.fields),
+            }),
+            Def::Enum(e) => Def::Enum(EnumDef {
+                name: e.name.clone(),
+                params: vec![],
+                variants: e.variants.iter().map(|v| VariantDef {
+                    name: v.name.clone(),
+                    data: match &v.data {
+                        VariantData::Unit => VariantData::Unit,
+                        VariantData::Type(ty) => VariantData::Type(inst(ty)),
+                        VariantData::Fields(fields) => VariantData::Fields(inst_fields(fields)),
+                    },
+                }).collect(),
+            }),
+        })
+    }
+}
+
+impl ParamDef {
+    /// Converts a generic parameter, e.g. `.T` or `.T: Ord + Clone`.
+    pub fn from_root(root: &Root) -> Result<ParamDef, String> {
+        let err = || format!("Expected generic parameter, found `{}`", root);
+        match root {
+            Root::Ty(name) => Ok(ParamDef {name: name.clone(), bounds: vec![]}),
+            Root::Bound(pb) => {
+                let name = if let Root::Ty(name) = &pb.0 {name.clone()} else {return Err(err())};
+                let bounds = match &pb.1 {
+                    Root::Ty(b) => vec![b.clone()],
+                    Root::Tup(bs) => bs.iter().map(|b| if let Root::Ty(b) = b {Ok(b.clone())}
+                        else {Err(err())}).collect::<Result<_, _>>()?,
+                    _ => return Err(err()),
+                };
+                Ok(ParamDef {name, bounds})
+            }
+            _ => Err(err()),
+        }
+    }
+
+    /// Converts generic parameter back into `Root`.
+    pub fn to_root(&self) -> Root {
+        let name = Root::Ty(self.name.clone());
+        match self.bounds.len() {
+            0 => name,
+            1 => Root::Bound(Box::new((name, Root::Ty(self.bounds[0].clone())))),
+            _ => Root::Bound(Box::new((
+                name,
+                Root::Tup(self.bounds.iter().map(|b| Root::Ty(b.clone())).collect()),
+            ))),
+        }
+    }
+}
+
+impl FieldDef {
+    /// Converts a field declaration into a field definition.
+    pub fn from_root(root: &Root, params: &[ParamDef]) -> Result<FieldDef, String> {
+        let (name, mut ty) = match root.as_field() {
+            Some(field) => field,
+            None => return Err(format!("Expected field, found `{}`", root)),
+        };
+        let mut attrs = vec![];
+        while let Root::Attr(av) = ty {
+            attrs.push(av.0.clone());
+            ty = &av.1;
+        }
+        let (ty, default) = match ty {
+            Root::Default(tv) => (&tv.0, Some(tv.1.clone())),
+            ty => (ty, None),
+        };
+        Ok(FieldDef {
+            name: name.clone(),
+            ty: TypeRef::from_root(ty, params)?,
+            default,
+            attrs,
+        })
+    }
+
+    /// Returns `true` if the field may be omitted in data,
+    /// because it has a default value or an `Option` type.
+    pub fn is_optional(&self) -> bool {
+        self.default.is_some() || self.ty.is_option()
+    }
+
+    /// Converts field definition back into `Root`.
+    pub fn to_root(&self) -> Root {
+        let mut ty = match &self.default {
+            Some(default) => Root::Default(Box::new((self.ty.to_root(), default.clone()))),
+            None => self.ty.to_root(),
+        };
+        for attr in self.attrs.iter().rev() {
+            ty = Root::Attr(Box::new((attr.clone(), ty)));
+        }
+        Root::Field(Box::new((Root::Str(self.name.clone()), ty)))
+    }
+}
+
+impl VariantDef {
+    /// Converts a variant declaration into a variant definition.
+    pub fn from_root(root: &Root, params: &[ParamDef]) -> Result<VariantDef, String> {
+        match root {
+            Root::Str(name) => Ok(VariantDef {name: name.clone(), data: VariantData::Unit}),
+            Root::Avatar(ab) => if let Root::Str(name) = &ab.0 {
+                Ok(VariantDef {
+                    name: name.clone(),
+                    data: VariantData::Type(TypeRef::from_root(&ab.1, params)?),
+                })
+            } else {
+                Err(format!("Expected variant name, found `{}`", ab.0))
+            }
+            Root::Struct {name, fields} => if let Root::Str(name) = &**name {
+                Ok(VariantDef {
+                    name: name.clone(),
+                    data: VariantData::Fields(fields_from_root(fields, params)?),
+                })
+            } else {
+                Err(format!("Expected variant name, found `{}`", name))
+            }
+            _ => Err(format!("Expected variant, found `{}`", root)),
+        }
+    }
+
+    /// Returns the type of variant data, if it is a single type.
+    pub fn data_ty(&self) -> Option<&TypeRef> {
+        if let VariantData::Type(ty) = &self.data {Some(ty)} else {None}
+    }
+
+    /// Converts variant definition back into `Root`.
+    pub fn to_root(&self) -> Root {
+        match &self.data {
+            VariantData::Unit => Root::Str(self.name.clone()),
+            VariantData::Type(ty) =>
+                Root::Avatar(Box::new((Root::Str(self.name.clone()), ty.to_root()))),
+            VariantData::Fields(fields) => Root::Struct {
+                name: Box::new(Root::Str(self.name.clone())),
+                fields: fields.iter().map(|f| f.to_root()).collect(),
+            },
+        }
+    }
+}
+
+impl TypeRef {
+    /// Converts a type expression into a type reference.
+    ///
+    /// Names listed in `params` become generic parameters.
+    pub fn from_root(root: &Root, params: &[ParamDef]) -> Result<TypeRef, String> {
+        match root {
+            Root::Ty(name) | Root::Str(name) => Ok(TypeRef::from_name(name, params)),
+            Root::Avatar(ab) => Ok(TypeRef::Applied(
+                Box::new(TypeRef::from_root(&ab.0, params)?),
+                match &ab.1 {
+                    Root::Params(args) => args.iter()
+                        .map(|a| TypeRef::from_root(a, params))
+                        .collect::<Result<_, _>>()?,
+                    core => vec![TypeRef::from_root(core, params)?],
+                },
+            )),
+            Root::Tup(items) => Ok(TypeRef::Tuple(items.iter()
+                .map(|it| TypeRef::from_root(it, params))
+                .collect::<Result<_, _>>()?)),
+            _ => Err(format!("Expected type, found `{}`", root)),
+        }
+    }
+
+    /// Classifies a type name.
+    pub fn from_name(name: &Arc<String>, params: &[ParamDef]) -> TypeRef {
+        if &**name == "Self" {
+            TypeRef::SelfRef
+        } else if params.iter().any(|p| &p.name == name) {
+            TypeRef::Generic(name.clone())
+        } else if PRIMITIVES.contains(&name.as_str()) {
+            TypeRef::Primitive(name.clone())
+        } else {
+            TypeRef::Named(name.clone())
+        }
+    }
+
+    /// Parses a type expression, e.g. `Foo<Arc<String>>`.
+    pub fn parse(text: &str) -> Result<TypeRef, String> {
+        TypeRef::from_root(&parsing::parse_str(text, &[])?, &[])
+    }
+
+    /// Replaces generic parameters by the argument at the same position.
+    pub fn substitute(&self, params: &[ParamDef], args: &[TypeRef]) -> TypeRef {
+        match self {
+            TypeRef::Generic(name) => match params.iter().position(|p| &p.name == name) {
+                Some(i) if i < args.len() => args[i].clone(),
+                _ => self.clone(),
+            },
+            TypeRef::Applied(base, xs) => TypeRef::Applied(
+                Box::new(base.substitute(params, args)),
+                xs.iter().map(|x| x.substitute(params, args)).collect(),
+            ),
+            TypeRef::Tuple(xs) =>
+                TypeRef::Tuple(xs.iter().map(|x| x.substitute(params, args)).collect()),
+            _ => self.clone(),
+        }
+    }
+
+    /// Returns `true` if the type is known to satisfy a bound, e.g. `Ord`.
+    ///
+    /// Only bounds of built-in types are known,
+    /// so user defined types and unknown bounds are accepted.
+    pub fn satisfies(&self, bound: &str) -> bool {
+        match self {
+            TypeRef::Primitive(name) => !matches!((name.as_str(), bound),
+                ("f64", "Eq") | ("f64", "Ord") | ("f64", "Hash") | ("String", "Copy")),
+            TypeRef::Applied(base, args) => match (&**base, bound) {
+                (TypeRef::Primitive(name), "Copy") if name.as_str() != "Option" => false,
+                (TypeRef::Primitive(_), _) => args.iter().all(|a| a.satisfies(bound)),
+                _ => true,
+            },
+            TypeRef::Tuple(items) => items.iter().all(|it| it.satisfies(bound)),
+            _ => true,
+        }
+    }
+
+    /// Returns `true` if the type is `Option<..>`.
+    pub fn is_option(&self) -> bool {
+        matches!(self, TypeRef::Applied(base, _)
+            if matches!(&**base, TypeRef::Primitive(name) if name.as_str() == "Option"))
+    }
+
+    /// Replaces `Self<..>` with `Self`, used when a definition is instantiated.
+    fn without_self_args(self) -> TypeRef {
+        match self {
+            TypeRef::Applied(base, _) if *base == TypeRef::SelfRef => TypeRef::SelfRef,
+            TypeRef::Applied(base, xs) => TypeRef::Applied(
+                Box::new(base.without_self_args()),
+                xs.into_iter().map(|x| x.without_self_args()).collect(),
+            ),
+            TypeRef::Tuple(xs) =>
+                TypeRef::Tuple(xs.into_iter().map(|x| x.without_self_args()).collect()),
+            x => x,
+        }
+    }
+
+    /// Converts type reference back into `Root`.
+    pub fn to_root(&self) -> Root {
+        match self {
+            TypeRef::Primitive(name) |
+            TypeRef::Named(name) |
+            TypeRef::Generic(name) => Root::Ty(name.clone()),
+            TypeRef::SelfRef => Root::ty_self(),
+            TypeRef::Applied(base, args) => {
+                let base = if let TypeRef::Named(name) = &**base {
+                    Root::Str(name.clone())
+                } else {
+                    base.to_root()
+                };
+                let core = if args.len() == 1 {
+                    args[0].to_root()
+                } else {
+                    Root::Params(args.iter().map(|a| a.to_root()).collect())
+                };
+                Root::Avatar(Box::new((base, core)))
+            }
+            TypeRef::Tuple(items) => Root::Tup(items.iter().map(|it| it.to_root()).collect()),
+        }
+    }
+}
+
+impl fmt::Display for TypeRef {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            TypeRef::Primitive(name) | TypeRef::Named(name) => write!(w, "{}", name),
+            TypeRef::Generic(name) => write!(w, ".{}", name),
+            TypeRef::SelfRef => write!(w, "Self"),
+            TypeRef::Applied(base, args) => {
+                write!(w, "{}<", base)?;
+                for (i, a) in args.iter().enumerate() {
+                    if i > 0 {write!(w, ", ")?}
+                    write!(w, "{}", a)?;
+                }
+                write!(w, ">")
+            }
+            TypeRef::Tuple(items) => {
+                write!(w, "(")?;
+                for (i, it) in items.iter().enumerate() {
+                    if i > 0 {write!(w, ", ")?}
+                    write!(w, "{}", it)?;
+                }
+                write!(w, ")")
+            }
+        }
+    }
+}
+
+fn primitive_arity(name: &str) -> usize {
+    match name {
+        "Arc" | "box" | "Option" | "Vec" | "Ref" => 1,
+        _ => 0,
+    }
+}
+
+fn name_from_root(root: &Root) -> Result<(Arc<String>, Vec<ParamDef>), String> {
+    match root {
+        Root::Str(name) => Ok((name.clone(), vec![])),
+        Root::Avatar(ab) => {
+            let name = if let Root::Str(name) = &ab.0 {name.clone()}
+                else {return Err(format!("Expected name, found `{}`", ab.0))};
+            let params = match &ab.1 {
+                Root::Params(ps) => ps.iter().map(ParamDef::from_root).collect::<Result<_, _>>()?,
+                p => vec![ParamDef::from_root(p)?],
+            };
+            Ok((name, params))
+        }
+        _ => Err(format!("Expected name, found `{}`", root)),
+    }
+}
+
+fn name_to_root(name: &Arc<String>, params: &[ParamDef]) -> Root {
+    match params.len() {
+        0 => Root::Str(name.clone()),
+        1 => Root::Avatar(Box::new((Root::Str(name.clone()), params[0].to_root()))),
+        _ => Root::Avatar(Box::new((
+            Root::Str(name.clone()),
+            Root::Params(params.iter().map(|p| p.to_root()).collect()),
+        ))),
+    }
+}
+
+fn fields_from_root(fields: &[Root], params: &[ParamDef]) -> Result<Vec<FieldDef>, String> {
+    fields.iter().map(|f| FieldDef::from_root(f, params)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self() {
+        let schema = Schema::from_root(&root_self()).unwrap();
+        assert_eq!(schema.to_root(), root_self());
+        if let Def::Enum(e) = &schema.defs[0] {
+            assert_eq!(e.variants.len(), 16);
+            assert_eq!(e.variants[4].data, VariantData::Type(TypeRef::Applied(
+                Box::new(TypeRef::Primitive(Arc::new("box".into()))),
+                vec![TypeRef::Tuple(vec![TypeRef::SelfRef, TypeRef::SelfRef])],
+            )));
+        } else {
+            panic!("Expected enum");
+        }
+    }
+
+    #[test]
+    fn test_documents() {
+        let doc = parsing::parse_str(
+            include_str!("../source/test/person/person-with-instance.txt"), &[]).unwrap();
+        let schema = Schema::from_root(&doc).unwrap();
+        assert_eq!(schema.defs.len(), 1);
+        assert!(schema.get("Person").is_some());
+
+        let edge = parsing::parse_str(
+            include_str!("../source/test/graph/edge.txt"), &[]).unwrap();
+        let schema = Schema::from_root(&edge).unwrap();
+        assert_eq!(schema.to_root(), edge);
+        if let Def::Struct(s) = &schema.defs[0] {
+            assert_eq!(s.fields[2].ty, TypeRef::Named(Arc::new("EdgeDir".into())));
+        } else {
+            panic!("Expected struct");
+        }
+
+        let gen_root = parsing::parse_str(
+            include_str!("../source/test/generics/gen_root.txt"), &[]).unwrap();
+        let schema = Schema::from_root(&gen_root).unwrap();
+        assert_eq!(schema.to_root(), gen_root);
+        assert_eq!(schema.defs[0].params()[0].name, Arc::new("T".into()));
+        if let Def::Enum(e) = &schema.defs[0] {
+            assert_eq!(e.variants[1].data,
+                VariantData::Type(TypeRef::Generic(Arc::new("T".into()))));
+            assert_eq!(format!("{}", e.variants[2].data_ty().unwrap()),
+                "box<(Self<.T>, Self<.T>)>");
+        } else {
+            panic!("Expected enum");
+        }
+
+        assert!(Schema::from_root(&Root::F64(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_instantiate() {
+        let gen_struct = parsing::parse_str(
+            include_str!("../source/test/generics/gen_struct.txt"), &[]).unwrap();
+        let schema = Schema::from_root(&gen_struct).unwrap();
+        let foo = schema.instantiate(&TypeRef::parse("Foo<f64>").unwrap()).unwrap();
+        assert_eq!(foo.to_root(), parsing::parse_str("struct Foo {inner: f64}", &[]).unwrap());
+        assert!(schema.instantiate(&TypeRef::parse("(ty Foo)").unwrap()).is_err());
+
+        let gen_root = parsing::parse_str(
+            include_str!("../source/test/generics/gen_root.txt"), &[]).unwrap();
+        let schema = Schema::from_root(&gen_root).unwrap();
+        schema.check_arity().unwrap();
+        let root = schema.instantiate(&TypeRef::parse("Root<f64>").unwrap()).unwrap();
+        if let Def::Enum(e) = &root {
+            assert_eq!(format!("{}", e.variants[2].data_ty().unwrap()), "box<(Self, Self)>");
+        } else {
+            panic!("Expected enum");
+        }
+
+        let bad = parsing::parse_str("[
+            struct Foo<.T> {inner: .T},
+            struct Bar {foo: (ty Foo)},
+        ]", &[]).unwrap();
+        assert!(Schema::from_root(&bad).unwrap().check_arity().is_err());
+    }
+
+    #[test]
+    fn test_multiple_params() {
+        use Root::*;
+
+        let map = match parsing::parse_str(
+            include_str!("../source/test/generics/gen_map.txt"), &[]) {
+            Ok(x) => x,
+            Err(err) => panic!("ERROR:\n{}", err),
+        };
+        if let Struct {name, ..} = &map {
+            assert_eq!(**name, Avatar(Box::new((
+                Str(Arc::new("Map".into())),
+                Params(vec![
+                    Bound(Box::new((Ty(Arc::new("K".into())), Ty(Arc::new("Ord".into()))))),
+                    Ty(Arc::new("V".into())),
+                ]),
+            ))));
+        } else {
+            panic!("Expected struct");
+        }
+        assert_eq!(parsing::parse_str(&format!("{}", map), &[]).unwrap(), map);
+        assert_ne!(parsing::parse_str("Map<(.K, .V)>", &[]).unwrap(),
+                   parsing::parse_str("Map<.K, .V>", &[]).unwrap());
+
+        let schema = Schema::from_root(&map).unwrap();
+        assert_eq!(schema.to_root(), map);
+        assert_eq!(schema.defs[0].params()[0].bounds, vec![Arc::new("Ord".into())]);
+        let inst = schema.instantiate(&TypeRef::parse("Map<String, f64>").unwrap()).unwrap();
+        assert_eq!(inst.to_root(), parsing::parse_str(
+            "struct Map {keys: Vec<String>, values: Vec<f64>}", &[]).unwrap());
+        assert!(schema.instantiate(&TypeRef::parse("Map<f64, f64>").unwrap()).is_err());
+        assert!(schema.instantiate(&TypeRef::parse("Map<String>").unwrap()).is_err());
+
+        let result = parsing::parse_str(
+            include_str!("../source/test/generics/gen_result.txt"), &[]).unwrap();
+        let schema = Schema::from_root(&result).unwrap();
+        assert_eq!(schema.to_root(), result);
+        let inst = schema.instantiate(&TypeRef::parse("Result<f64, Arc<String>>").unwrap()).unwrap();
+        assert_eq!(inst.to_root(), parsing::parse_str(
+            "enum Result {Ok(f64), Err(Arc<String>)}", &[]).unwrap());
+
+        let bounds = parsing::parse_str("struct Set<.T: Ord + Hash> {items: Vec<.T>}", &[]).unwrap();
+        let schema = Schema::from_root(&bounds).unwrap();
+        assert_eq!(schema.defs[0].params()[0].bounds.len(), 2);
+        assert_eq!(schema.to_root(), bounds);
+        assert_eq!(parsing::parse_str(&format!("{}", bounds), &[]).unwrap(), bounds);
+    }
+}
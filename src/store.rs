@@ -0,0 +1,318 @@
+//! Embedded, file-backed store of formats and instances.
+//!
+//! The store is an append-only log of text lines, with an in-memory index
+//! from type and key to the latest data of every instance.
+//! Values on a line are separated by tabs:
+//!
+//! ```text
+//! format  struct "Person" {...}
+//! put  Person  donald  (tup "Donald" "Duck" )
+//! del  Person  donald
+//! commit
+//! ```
+//!
+//! Changes are made in transactions, which append their lines followed by `commit`.
+//! When a store is opened, lines after the last `commit` are from an interrupted
+//! transaction and are removed.
+//!
+//! Updates and deletes leave old lines in the log until the store is compacted.
+//! The content of a store can be exported as a document with declarations followed by instances.
+
+use crate::*;
+use crate::schema::*;
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Location of data in the log.
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    offset: u64,
+    len: usize,
+}
+
+/// A file-backed store of formats and instances, keyed by type and key.
+#[derive(Debug)]
+pub struct Store {
+    path: PathBuf,
+    file: File,
+    /// Length of log, in bytes.
+    len: u64,
+    schema: Schema,
+    /// Data of instances by type and key.
+    index: BTreeMap<(Arc<String>, Arc<String>), Span>,
+}
+
+impl Store {
+    /// Opens a store, creating the file if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Store, String> {
+        let path = path.as_ref().to_path_buf();
+        let err = |err: std::io::Error| format!("{}: {}", path.display(), err);
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&path).map_err(err)?;
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(err)?;
+
+        let mut store = Store {path: path.clone(), file, len: 0, schema: Schema {defs: vec![]}, index: BTreeMap::new()};
+        let mut pending: Vec<(&str, Span)> = vec![];
+        let mut offset = 0;
+        for (i, line) in text.split_inclusive('\n').enumerate() {
+            let start = offset;
+            offset += line.len();
+            if !line.ends_with('\n') {break}
+            let line = &line[..line.len() - 1];
+            if line == "commit" {
+                for (line, span) in pending.drain(..) {
+                    store.replay(line, span).map_err(|e| format!("{}:{}: {}", path.display(), i + 1, e))?;
+                }
+                store.len = offset as u64;
+            } else {
+                pending.push((line, Span {offset: start as u64, len: line.len()}));
+            }
+        }
+        if store.len < text.len() as u64 {
+            // Removes an interrupted transaction.
+            store.file.set_len(store.len).map_err(err)?;
+        }
+        Ok(store)
+    }
+
+    /// Applies a committed line of the log.
+    fn replay(&mut self, line: &str, span: Span) -> Result<(), String> {
+        let parts: Vec<&str> = line.splitn(4, '\t').collect();
+        match parts[..] {
+            ["format", decl] => {
+                let def = Def::from_root(&parsing::parse_str(decl, &[])?)?;
+                self.schema.defs.retain(|d| d.name() != def.name());
+                self.schema.defs.push(def);
+            }
+            ["put", class, key, data] => {
+                let data_offset = span.offset + (line.len() - data.len()) as u64;
+                self.index.insert((Arc::new(class.into()), Arc::new(key.into())),
+                    Span {offset: data_offset, len: data.len()});
+            }
+            ["del", class, key] => {
+                self.index.remove(&(Arc::new(class.into()), Arc::new(key.into())));
+            }
+            _ => return Err(format!("Invalid log entry `{}`", line)),
+        }
+        Ok(())
+    }
+
+    /// Returns the declarations in the store.
+    pub fn schema(&self) -> &Schema {&self.schema}
+
+    /// Returns the keys of instances of a type, sorted.
+    pub fn keys(&self, class: &str) -> Vec<Arc<String>> {
+        self.index.keys().filter(|(c, _)| **c == class).map(|(_, k)| k.clone()).collect()
+    }
+
+    /// Returns the data of an instance by type and key.
+    pub fn get(&self, class: &str, key: &str) -> Result<Option<Root>, String> {
+        match self.index.get(&(Arc::new(class.into()), Arc::new(key.into()))) {
+            Some(span) => Ok(Some(parsing::parse_str(&self.read(*span)?, &[])?)),
+            None => Ok(None),
+        }
+    }
+
+    fn read(&self, span: Span) -> Result<String, String> {
+        let err = |err: std::io::Error| format!("{}: {}", self.path.display(), err);
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(span.offset)).map_err(err)?;
+        let mut buf = vec![0; span.len];
+        file.read_exact(&mut buf).map_err(err)?;
+        String::from_utf8(buf).map_err(|err| format!("{}: {}", self.path.display(), err))
+    }
+
+    /// Starts a transaction.
+    ///
+    /// Nothing is written until the transaction is committed.
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        Transaction {schema: self.schema.clone(), store: self, lines: vec![], exists: BTreeMap::new()}
+    }
+
+    /// Returns the length of the log, in bytes.
+    pub fn log_len(&self) -> u64 {self.len}
+
+    /// Rewrites the log with only the latest declarations and instances.
+    pub fn compact(&mut self) -> Result<(), String> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let err = |err: std::io::Error| format!("{}: {}", tmp.display(), err);
+        let mut text = String::new();
+        for def in &self.schema.defs {
+            text.push_str(&format!("format\t{}\n", def.to_root()));
+        }
+        for ((class, key), span) in &self.index {
+            text.push_str(&format!("put\t{}\t{}\t{}\n", class, key, self.read(*span)?));
+        }
+        text.push_str("commit\n");
+        let mut file = File::create(&tmp).map_err(err)?;
+        file.write_all(text.as_bytes()).map_err(err)?;
+        file.sync_all().map_err(err)?;
+        std::fs::rename(&tmp, &self.path).map_err(err)?;
+        *self = Store::open(&self.path)?;
+        Ok(())
+    }
+
+    /// Exports declarations followed by instances, ordered by declaration and key.
+    pub fn export(&self) -> Result<Root, String> {
+        let mut res: Vec<Root> = self.schema.defs.iter().map(|d| d.to_root()).collect();
+        for (class, def) in self.schema.defs.iter().enumerate() {
+            for key in self.keys(def.name()) {
+                let data = self.get(def.name(), &key)?;
+                res.push(Root::Instance {class, data: data.map(Box::new)});
+            }
+        }
+        Ok(Root::Tup(res))
+    }
+
+    /// Exports the store as text, with one declaration or instance per line.
+    pub fn to_text(&self) -> Result<String, String> {
+        let mut out = String::from("(tup\n");
+        if let Root::Tup(items) = self.export()? {
+            for it in items {
+                out.push_str(&format!("  {}\n", it));
+            }
+        }
+        out.push_str(")\n");
+        Ok(out)
+    }
+}
+
+/// Changes to a store, written together on commit.
+///
+/// Dropping a transaction without committing discards its changes.
+pub struct Transaction<'a> {
+    store: &'a mut Store,
+    /// Declarations, including those in this transaction.
+    schema: Schema,
+    lines: Vec<String>,
+    /// Whether instances exist after the changes of this transaction.
+    exists: BTreeMap<(Arc<String>, Arc<String>), bool>,
+}
+
+impl Transaction<'_> {
+    /// Declares a struct or enum, replacing an earlier declaration of the same name.
+    ///
+    /// Stored instances are not checked against the new declaration.
+    pub fn define(&mut self, decl: &Root) -> Result<(), String> {
+        let def = Def::from_root(decl)?;
+        self.lines.push(format!("format\t{}", def.to_root()));
+        self.schema.defs.retain(|d| d.name() != def.name());
+        self.schema.defs.push(def);
+        Ok(())
+    }
+
+    fn exists(&self, class: &str, key: &str) -> bool {
+        let id = (Arc::new(class.to_string()), Arc::new(key.to_string()));
+        self.exists.get(&id).copied().unwrap_or_else(|| self.store.index.contains_key(&id))
+    }
+
+    fn put(&mut self, class: &str, key: &str, data: &Root) -> Result<(), String> {
+        if key.is_empty() || key.contains(['\t', '\n']) {
+            return Err(format!("Invalid key `{:?}`", key));
+        }
+        let ty = TypeRef::Named(Arc::new(class.into()));
+        value::read_type(&self.schema, &ty, data).map_err(|err| format!("{} {}: {}", class, key, err))?;
+        self.lines.push(format!("put\t{}\t{}\t{}", class, key, data));
+        self.exists.insert((Arc::new(class.into()), Arc::new(key.into())), true);
+        Ok(())
+    }
+
+    /// Inserts an instance of a declared type.
+    ///
+    /// Returns an error if the key is already used or the data does not match the declaration.
+    pub fn insert(&mut self, class: &str, key: &str, data: &Root) -> Result<(), String> {
+        if self.exists(class, key) {return Err(format!("{} {}: Key already exists", class, key))}
+        self.put(class, key, data)
+    }
+
+    /// Replaces the data of an instance.
+    pub fn update(&mut self, class: &str, key: &str, data: &Root) -> Result<(), String> {
+        if !self.exists(class, key) {return Err(format!("{} {}: No such instance", class, key))}
+        self.put(class, key, data)
+    }
+
+    /// Deletes an instance.
+    pub fn delete(&mut self, class: &str, key: &str) -> Result<(), String> {
+        if !self.exists(class, key) {return Err(format!("{} {}: No such instance", class, key))}
+        self.lines.push(format!("del\t{}\t{}", class, key));
+        self.exists.insert((Arc::new(class.into()), Arc::new(key.into())), false);
+        Ok(())
+    }
+
+    /// Writes the changes to the log and syncs the file.
+    pub fn commit(self) -> Result<(), String> {
+        if self.lines.is_empty() {return Ok(())}
+        let store = self.store;
+        let mut text = String::new();
+        let mut spans = vec![];
+        for line in &self.lines {
+            spans.push(Span {offset: store.len + text.len() as u64, len: line.len()});
+            text.push_str(line);
+            text.push('\n');
+        }
+        text.push_str("commit\n");
+        let err = |err: std::io::Error| format!("{}: {}", store.path.display(), err);
+        store.file.write_all(text.as_bytes()).map_err(err)?;
+        store.file.sync_data().map_err(err)?;
+        store.len += text.len() as u64;
+        for (line, span) in self.lines.iter().zip(spans) {
+            store.replay(line, span)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store() {
+        let path = std::env::temp_dir().join(format!("iknow-store-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut store = Store::open(&path).unwrap();
+        let mut tx = store.transaction();
+        tx.define(&data(include_str!("../source/test/person/person.txt"))).unwrap();
+        tx.insert("Person", "donald", &data(r#"["Donald", "Duck"]"#)).unwrap();
+        tx.insert("Person", "dolly", &data(r#"["Dolly", "Duck"]"#)).unwrap();
+        assert!(tx.insert("Person", "dolly", &data(r#"["Dolly", "Duck"]"#)).is_err());
+        assert!(tx.insert("Person", "scrooge", &data(r#"["Scrooge"]"#)).is_err());
+        tx.commit().unwrap();
+
+        let mut tx = store.transaction();
+        tx.update("Person", "donald", &data(r#"["Donald", "McDuck"]"#)).unwrap();
+        tx.delete("Person", "dolly").unwrap();
+        assert!(tx.update("Person", "dolly", &data(r#"["Dolly", "Duck"]"#)).is_err());
+        drop(tx);
+        assert_eq!(store.keys("Person").len(), 2);
+
+        let mut tx = store.transaction();
+        tx.delete("Person", "dolly").unwrap();
+        tx.update("Person", "donald", &data(r#"["Donald", "McDuck"]"#)).unwrap();
+        tx.commit().unwrap();
+        assert_eq!(store.get("Person", "donald").unwrap(), Some(data(r#"["Donald", "McDuck"]"#)));
+        assert_eq!(store.get("Person", "dolly").unwrap(), None);
+
+        // An interrupted transaction is removed when opened.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"del\tPerson\tdonald\n").unwrap();
+        let mut store = Store::open(&path).unwrap();
+        assert_eq!(store.keys("Person"), vec![Arc::new("donald".to_string())]);
+
+        let len = store.log_len();
+        store.compact().unwrap();
+        assert!(store.log_len() < len);
+        let doc = data(&store.to_text().unwrap());
+        assert_eq!(doc, store.export().unwrap());
+        assert_eq!(validate::complete(&doc).unwrap(), data(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Donald", "McDuck"]),
+        ]"#));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+//! Interning of strings used as type names, names and string values.
+//!
+//! Interned strings with the same content share one allocation,
+//! such that they can be compared with `Arc::ptr_eq`.
+//! The parser and the `Root::ty_*` constructors intern strings in a global interner,
+//! where strings no longer used elsewhere are removed by `collect`.
+//! The parser shares the strings of a document after parsing it, see `share_root`,
+//! such that documents can be parsed in parallel without waiting for the global interner.
+//!
+//! A symbol table lists the distinct strings of a document in order of first occurrence.
+//! It is written as one quoted string per line, e.g. `"Person"`,
+//! and when read back, strings of documents can be shared with the table.
+
+use crate::*;
+use crate::visit::{Visitor, VisitorMut};
+
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+
+/// A shared string, which can be looked up by `&str`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Shared(Arc<String>);
+
+impl Borrow<str> for Shared {
+    fn borrow(&self) -> &str {&self.0}
+}
+
+/// A set of shared strings.
+#[derive(Clone, Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Shared>,
+}
+
+impl Interner {
+    /// Creates an empty interner.
+    pub fn new() -> Interner {Interner::default()}
+
+    /// Returns the shared string with the same content.
+    pub fn intern(&mut self, s: &str) -> Arc<String> {
+        if let Some(s) = self.strings.get(s) {return s.0.clone()}
+        let s = Arc::new(s.to_string());
+        self.strings.insert(Shared(s.clone()));
+        s
+    }
+
+    /// Returns the shared string with the same content, reusing the allocation if it is new.
+    pub fn share(&mut self, s: Arc<String>) -> Arc<String> {
+        if let Some(s) = self.strings.get(s.as_str()) {return s.0.clone()}
+        self.strings.insert(Shared(s.clone()));
+        s
+    }
+
+    /// Removes strings that are not used outside the interner.
+    pub fn collect(&mut self) {
+        self.strings.retain(|s| Arc::strong_count(&s.0) > 1);
+    }
+
+    /// Returns the number of strings.
+    pub fn len(&self) -> usize {self.strings.len()}
+
+    /// Returns `true` if there are no strings.
+    pub fn is_empty(&self) -> bool {self.strings.is_empty()}
+}
+
+fn global() -> &'static Mutex<Interner> {
+    static GLOBAL: OnceLock<Mutex<Interner>> = OnceLock::new();
+    GLOBAL.get_or_init(Default::default)
+}
+
+/// Returns the string with the same content from the global interner.
+pub fn intern(s: &str) -> Arc<String> {
+    global().lock().unwrap().intern(s)
+}
+
+/// Returns the string with the same content from the global interner,
+/// reusing the allocation if it is new.
+pub fn share(s: Arc<String>) -> Arc<String> {
+    global().lock().unwrap().share(s)
+}
+
+/// Shares the strings of `Ty` and `Str` in a document with equal strings in the global interner.
+///
+/// Strings are collected in a local interner first,
+/// such that the global interner is locked once, for the distinct strings only.
+pub fn share_root(root: &mut Root) {
+    struct Share<'a>(&'a mut Interner);
+
+    impl VisitorMut for Share<'_> {
+        fn visit_ty_mut(&mut self, s: &mut Arc<String>) {self.visit_str_mut(s)}
+        fn visit_str_mut(&mut self, s: &mut Arc<String>) {*s = self.0.share(s.clone())}
+    }
+
+    let mut local = Interner::new();
+    Share(&mut local).visit_mut(root);
+    {
+        let mut global = global().lock().unwrap();
+        local.strings = local.strings.drain().map(|s| Shared(global.share(s.0))).collect();
+    }
+    Share(&mut local).visit_mut(root);
+}
+
+/// Names of builtin types, see `Root::ty_self` and others.
+const BUILTIN: [&str; 9] = ["Self", "Arc", "String", "f64", "bool", "box", "usize", "Option", "Vec"];
+
+/// Returns the name of a builtin type from the global interner, without locking it.
+pub(crate) fn builtin(name: &str) -> Arc<String> {
+    static NAMES: OnceLock<Vec<Arc<String>>> = OnceLock::new();
+    let names = NAMES.get_or_init(|| BUILTIN.iter().map(|s| intern(s)).collect());
+    let i = BUILTIN.iter().position(|s| *s == name).expect("Expected builtin type");
+    names[i].clone()
+}
+
+/// Removes strings from the global interner that are not used elsewhere.
+///
+/// Call this after dropping large documents.
+pub fn collect() {
+    global().lock().unwrap().collect()
+}
+
+/// Distinct strings of a document, numbered in order of first occurrence.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SymbolTable {
+    symbols: Vec<Arc<String>>,
+    index: HashMap<Arc<String>, usize>,
+}
+
+impl SymbolTable {
+    /// Creates an empty symbol table.
+    pub fn new() -> SymbolTable {SymbolTable::default()}
+
+    /// Collects the strings of `Ty` and `Str` in a document.
+    pub fn from_root(root: &Root) -> SymbolTable {
+        struct Collect(SymbolTable);
+
+        impl Visitor for Collect {
+            fn visit_ty(&mut self, s: &Arc<String>) {self.0.insert(s);}
+            fn visit_str(&mut self, s: &Arc<String>) {self.0.insert(s);}
+        }
+
+        let mut collect = Collect(SymbolTable::new());
+        collect.visit(root);
+        collect.0
+    }
+
+    /// Adds a string, returning its number.
+    pub fn insert(&mut self, s: &Arc<String>) -> usize {
+        if let Some(&i) = self.index.get(s) {return i}
+        self.symbols.push(s.clone());
+        self.index.insert(s.clone(), self.symbols.len() - 1);
+        self.symbols.len() - 1
+    }
+
+    /// Returns the number of a string.
+    pub fn index_of(&self, s: &str) -> Option<usize> {
+        self.index.get(&Arc::new(s.to_string())).copied()
+    }
+
+    /// Returns the string with a number.
+    pub fn get(&self, i: usize) -> Option<&Arc<String>> {self.symbols.get(i)}
+
+    /// Returns the strings, in order.
+    pub fn symbols(&self) -> &[Arc<String>] {&self.symbols}
+
+    /// Returns the number of strings.
+    pub fn len(&self) -> usize {self.symbols.len()}
+
+    /// Returns `true` if there are no strings.
+    pub fn is_empty(&self) -> bool {self.symbols.is_empty()}
+
+    /// Replaces strings in a document with those in the table, which share memory.
+    ///
+    /// Strings that are not in the table are left as they are.
+    pub fn share(&self, root: &mut Root) {
+        struct Share<'a>(&'a SymbolTable);
+
+        impl VisitorMut for Share<'_> {
+            fn visit_ty_mut(&mut self, s: &mut Arc<String>) {self.visit_str_mut(s)}
+            fn visit_str_mut(&mut self, s: &mut Arc<String>) {
+                if let Some(&i) = self.0.index.get(s) {*s = self.0.symbols[i].clone()}
+            }
+        }
+
+        Share(self).visit_mut(root)
+    }
+
+    /// Writes the table as one quoted string per line.
+    pub fn to_text(&self) -> String {
+        self.symbols.iter().map(|s| format!("{:?}\n", s)).collect()
+    }
+
+    /// Reads a table written by `to_text`, interning the strings in the global interner.
+    pub fn from_text(text: &str) -> Result<SymbolTable, String> {
+        let mut table = SymbolTable::new();
+        for (i, line) in text.lines().enumerate() {
+            let s = unquote(line).ok_or_else(|| format!("Line {}: Expected quoted string", i + 1))?;
+            table.insert(&intern(&s));
+        }
+        Ok(table)
+    }
+}
+
+/// Reads a string written with `{:?}`.
+fn unquote(line: &str) -> Option<String> {
+    let inner = line.strip_prefix('"')?.strip_suffix('"')?;
+    let mut res = String::new();
+    let mut chars = inner.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            res.push(ch);
+            continue;
+        }
+        match chars.next()? {
+            'n' => res.push('\n'),
+            'r' => res.push('\r'),
+            't' => res.push('\t'),
+            '0' => res.push('\0'),
+            'u' => {
+                let hex: String = chars.by_ref().skip(1).take_while(|&c| c != '}').collect();
+                res.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+            }
+            ch => res.push(ch),
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern() {
+        let a = intern("Person");
+        assert!(Arc::ptr_eq(&a, &intern("Person")));
+        assert!(Arc::ptr_eq(&a, &share(Arc::new("Person".into()))));
+        if let (Root::Ty(a), Root::Ty(b)) = (Root::ty_arc(), Root::ty_arc()) {
+            assert!(Arc::ptr_eq(&a, &b));
+        }
+
+        let doc = parsing::parse_str(include_str!("../source/test/person/table-data.txt"), &[]).unwrap();
+        if let Root::Tup(rows) = &doc {
+            match (&rows[0], &rows[1]) {
+                (Root::Tup(a), Root::Tup(b)) => match (&a[1], &b[1]) {
+                    (Root::Str(a), Root::Str(b)) => assert!(Arc::ptr_eq(a, b)),
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            }
+        }
+
+        // Documents parsed in parallel share strings.
+        let src = include_str!("../source/test/person/person.txt");
+        let (a, b) = std::thread::scope(|s| {
+            let a = s.spawn(|| parsing::parse_str(src, &[]).unwrap());
+            let b = s.spawn(|| parsing::parse_str(src, &[]).unwrap());
+            (a.join().unwrap(), b.join().unwrap())
+        });
+        match (&a, &b) {
+            (Root::Struct {fields: a, ..}, Root::Struct {fields: b, ..}) => {
+                let (a, b) = (a[0].as_field().unwrap().0, b[0].as_field().unwrap().0);
+                assert!(Arc::ptr_eq(a, b));
+                let field = Root::field("first_name", Root::ty_string());
+                assert!(Arc::ptr_eq(a, field.as_field().unwrap().0));
+            }
+            _ => panic!(),
+        }
+
+        let mut interner = Interner::new();
+        let s = interner.intern("Donald");
+        interner.intern("Dolly");
+        interner.collect();
+        assert_eq!(interner.len(), 1);
+        assert!(Arc::ptr_eq(&s, &interner.intern("Donald")));
+    }
+
+    #[test]
+    fn test_table() {
+        let doc = parsing::parse_str(include_str!("../source/test/person/person-with-instance.txt"), &[]).unwrap();
+        let table = SymbolTable::from_root(&doc);
+        assert_eq!(table.index_of("Person"), Some(0));
+        assert_eq!(table.index_of("Duck"), Some(6));
+        assert_eq!(table.len(), 8);
+
+        let mut escaped = table.clone();
+        escaped.insert(&Arc::new("tab\t\"quote\" \u{7f}".into()));
+        let text = escaped.to_text();
+        assert!(text.starts_with("\"Person\"\n\"first_name\"\n"));
+        assert_eq!(SymbolTable::from_text(&text).unwrap(), escaped);
+        assert!(SymbolTable::from_text("Person").is_err());
+
+        let mut doc = doc;
+        table.share(&mut doc);
+        assert_eq!(SymbolTable::from_root(&doc), table);
+    }
+}
@@ -0,0 +1,585 @@
+//! Subject-predicate-object triples of instances in a document.
+//!
+//! Every instance becomes a subject named by its type in lower case and its rank
+//! among instances of the same type, with a triple per field:
+//!
+//! ```text
+//! person#0    first_name    "Donald"
+//! person#0    last_name     "Duck"
+//! ```
+//!
+//! - Lists give a triple per item, in order, and `Option::None` gives no triple
+//! - Nested structs, tuples and variants with data become subjects named by their location,
+//!   e.g. `person#0/address`, or `person#0/addresses/1` inside lists
+//! - Unit variants are strings, e.g. `"Left"`, and the unit variant of an instance
+//!   is written with the predicate `variant`
+//! - References `(ref N)` refer to the subject of the instance, e.g. `node#1`
+//!
+//! Triples are written as tab separated values, or as N-Triples with IRIs below a base,
+//! e.g. `<iknow:person#0>`.
+//! Triples are read back into instances using a format declaration,
+//! with records written by field name, such that omitted fields get their defaults.
+
+use crate::*;
+use crate::schema::*;
+use crate::value::{Record, Value, Variant};
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Default base of IRIs in N-Triples.
+pub const BASE: &str = "iknow:";
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// A subject-predicate-object triple.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Triple {
+    /// Subject, e.g. `person#0`.
+    pub subject: String,
+    /// Predicate, e.g. `first_name`.
+    pub predicate: String,
+    /// Object, e.g. `"Donald"`.
+    pub object: Object,
+}
+
+/// The object of a triple.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Object {
+    /// A string.
+    Str(Arc<String>),
+    /// A number.
+    F64(f64),
+    /// An integer, e.g. of a `usize` field.
+    Usize(usize),
+    /// A boolean.
+    Bool(bool),
+    /// Another subject, e.g. `node#1`.
+    Node(String),
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Str(s) => write!(w, "{}", quote(s)),
+            Object::F64(x) => write!(w, "{}", x),
+            Object::Usize(x) => write!(w, "{}", x),
+            Object::Bool(x) => write!(w, "{}", x),
+            Object::Node(id) => write!(w, "{}", id),
+        }
+    }
+}
+
+impl fmt::Display for Triple {
+    fn fmt(&self, w: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(w, "{}\t{}\t{}", self.subject, self.predicate, self.object)
+    }
+}
+
+/// Returns the subject of an instance, e.g. `person#0`.
+pub fn subject(class: &str, rank: usize) -> String {
+    format!("{}#{}", class.to_lowercase(), rank)
+}
+
+/// Converts the instances of a document into triples.
+pub fn to_triples(doc: &Root) -> Result<Vec<Triple>, String> {
+    let instances = value::read_instances(doc)?;
+    let mut ranks: HashMap<&str, usize> = HashMap::new();
+    let mut subjects: HashMap<usize, String> = HashMap::new();
+    for ins in &instances {
+        let rank = ranks.entry(&ins.class).or_insert(0);
+        subjects.insert(ins.index, subject(&ins.class, *rank));
+        *rank += 1;
+    }
+    let mut exporter = Exporter {subjects: &subjects, triples: vec![]};
+    for ins in &instances {
+        exporter.body(&subjects[&ins.index], &ins.value)?;
+    }
+    Ok(exporter.triples)
+}
+
+struct Exporter<'a> {
+    /// Subjects of instances by index in document.
+    subjects: &'a HashMap<usize, String>,
+    triples: Vec<Triple>,
+}
+
+impl Exporter<'_> {
+    /// Adds triples describing a struct or variant value of a subject.
+    fn body(&mut self, subject: &str, v: &Value) -> Result<(), String> {
+        match v {
+            Value::Struct(rec) => self.fields(subject, rec),
+            Value::Variant(var) => self.variant(subject, var),
+            _ => Err(format!("{}: Expected struct or variant", subject)),
+        }
+    }
+
+    fn fields(&mut self, subject: &str, rec: &Record) -> Result<(), String> {
+        for (f, v) in &rec.fields {
+            self.value(subject, &f.name, v, format!("{}/{}", subject, f.name))?;
+        }
+        Ok(())
+    }
+
+    fn variant(&mut self, subject: &str, var: &Variant) -> Result<(), String> {
+        match var.data.as_deref() {
+            None => {
+                self.push(subject, "variant", Object::Str(var.name.clone()));
+                Ok(())
+            }
+            Some(data) => self.value(subject, &var.name, data, format!("{}/{}", subject, var.name)),
+        }
+    }
+
+    /// Adds triples for the value of a predicate, using `child` as subject of nested values.
+    fn value(&mut self, subject: &str, predicate: &str, v: &Value, child: String) -> Result<(), String> {
+        let object = match v {
+            Value::F64(x) => Object::F64(*x),
+            Value::Usize(x) => Object::Usize(*x),
+            Value::Bool(x) => Object::Bool(*x),
+            Value::Str(x) => Object::Str(x.clone()),
+            Value::Option(None) => return Ok(()),
+            Value::Option(Some(x)) => return self.value(subject, predicate, x, child),
+            Value::List(items) => {
+                for (i, it) in items.iter().enumerate() {
+                    self.value(subject, predicate, it, format!("{}/{}", child, i))?;
+                }
+                return Ok(());
+            }
+            Value::Variant(var) if var.data.is_none() => Object::Str(var.name.clone()),
+            Value::Ref(r) => Object::Node(self.subjects.get(&r.index).cloned()
+                .ok_or_else(|| format!("{}: Dangling reference `{}`", subject, Root::Ref(r.index)))?),
+            Value::Tuple(items) => {
+                self.push(subject, predicate, Object::Node(child.clone()));
+                for (i, it) in items.iter().enumerate() {
+                    self.value(&child, &format!("{}", i), it, format!("{}/{}", child, i))?;
+                }
+                return Ok(());
+            }
+            Value::Struct(_) | Value::Variant(_) => {
+                self.push(subject, predicate, Object::Node(child.clone()));
+                return self.body(&child, v);
+            }
+        };
+        self.push(subject, predicate, object);
+        Ok(())
+    }
+
+    fn push(&mut self, subject: &str, predicate: &str, object: Object) {
+        self.triples.push(Triple {subject: subject.into(), predicate: predicate.into(), object});
+    }
+}
+
+/// Writes triples as tab separated values, one triple per line.
+///
+/// Strings are quoted, e.g. `"Donald"`, while other subjects are written as is, e.g. `node#1`.
+pub fn to_tsv(triples: &[Triple]) -> String {
+    triples.iter().map(|t| format!("{}\n", t)).collect()
+}
+
+/// Reads triples from tab separated values.
+pub fn from_tsv(text: &str) -> Result<Vec<Triple>, String> {
+    let mut res = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {continue}
+        let parts: Vec<&str> = line.splitn(3, '\t').collect();
+        let (subject, predicate, object) = match parts[..] {
+            [s, p, o] => (s, p, o),
+            _ => return Err(format!("Line {}: Expected three values separated by tabs", i + 1)),
+        };
+        let object = if object.starts_with('"') {
+            let (s, rest) = unquote(object).map_err(|err| format!("Line {}: {}", i + 1, err))?;
+            if !rest.is_empty() {return Err(format!("Line {}: Unexpected `{}`", i + 1, rest))}
+            Object::Str(Arc::new(s))
+        } else if let Ok(x) = object.parse::<usize>() {
+            Object::Usize(x)
+        } else if let Ok(x) = object.parse::<f64>() {
+            Object::F64(x)
+        } else if let Ok(x) = object.parse::<bool>() {
+            Object::Bool(x)
+        } else {
+            Object::Node(object.into())
+        };
+        res.push(Triple {subject: subject.into(), predicate: predicate.into(), object});
+    }
+    Ok(res)
+}
+
+/// Writes triples as N-Triples, with subjects and predicates as IRIs below a base.
+pub fn to_ntriples(triples: &[Triple], base: &str) -> String {
+    let mut out = String::new();
+    for t in triples {
+        let object = match &t.object {
+            Object::Str(s) => quote(s),
+            Object::F64(x) => format!("{}^^<{}double>", quote(&format!("{}", x)), XSD),
+            Object::Usize(x) => format!("{}^^<{}integer>", quote(&format!("{}", x)), XSD),
+            Object::Bool(x) => format!("{}^^<{}boolean>", quote(&format!("{}", x)), XSD),
+            Object::Node(id) => format!("<{}{}>", base, id),
+        };
+        out.push_str(&format!("<{}{}> <{}{}> {} .\n", base, t.subject, base, t.predicate, object));
+    }
+    out
+}
+
+/// Reads N-Triples with subjects and predicates below a base.
+pub fn from_ntriples(text: &str, base: &str) -> Result<Vec<Triple>, String> {
+    let mut res = vec![];
+    for (i, line) in text.lines().enumerate() {
+        let err = |msg: String| format!("Line {}: {}", i + 1, msg);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {continue}
+        let (subject, rest) = iri(line, base).map_err(err)?;
+        let (predicate, rest) = iri(rest.trim_start(), base).map_err(err)?;
+        let rest = rest.trim_start();
+        let (object, rest) = if rest.starts_with('<') {
+            let (id, rest) = iri(rest, base).map_err(err)?;
+            (Object::Node(id), rest)
+        } else {
+            let (s, rest) = unquote(rest).map_err(err)?;
+            if let Some(rest) = rest.strip_prefix("^^") {
+                let end = rest.find('>').ok_or_else(|| err("Expected `>`".into()))?;
+                let ty = rest[..end].trim_start_matches('<');
+                let ty = ty.strip_prefix(XSD).unwrap_or(ty);
+                let invalid = || err(format!("Invalid `{}` literal `{}`", ty, s));
+                (match ty {
+                    "integer" => Object::Usize(s.parse().map_err(|_| invalid())?),
+                    "double" | "decimal" | "float" => Object::F64(s.parse().map_err(|_| invalid())?),
+                    "boolean" => Object::Bool(s.parse().map_err(|_| invalid())?),
+                    _ => Object::Str(Arc::new(s)),
+                }, &rest[end + 1..])
+            } else if let Some(rest) = rest.strip_prefix('@') {
+                // Language tags are ignored.
+                let end = rest.find(|c: char| c.is_whitespace() || c == '.').unwrap_or(rest.len());
+                (Object::Str(Arc::new(s)), &rest[end..])
+            } else {
+                (Object::Str(Arc::new(s)), rest)
+            }
+        };
+        if rest.trim() != "." {return Err(err("Expected `.` at end of triple".into()))}
+        res.push(Triple {subject, predicate, object});
+    }
+    Ok(res)
+}
+
+/// Reads an IRI below a base, e.g. `<iknow:person#0>`, returning the rest of text.
+fn iri<'a>(text: &'a str, base: &str) -> Result<(String, &'a str), String> {
+    let end = text.find('>').filter(|_| text.starts_with('<'))
+        .ok_or_else(|| format!("Expected IRI, found `{}`", text))?;
+    let iri = &text[1..end];
+    let id = iri.strip_prefix(base)
+        .ok_or_else(|| format!("Expected IRI below `{}`, found `{}`", base, iri))?;
+    Ok((id.into(), &text[end + 1..]))
+}
+
+/// Writes a string literal with escapes used by N-Triples and tab separated values.
+///
+/// Control characters other than `\n`, `\r` and `\t` are written as `\uXXXX`.
+fn quote(s: &str) -> String {
+    let mut res = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            ch if ch.is_control() => res.push_str(&format!("\\u{:04X}", ch as u32)),
+            ch => res.push(ch),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// Reads a quoted string literal, returning the rest of text.
+fn unquote(text: &str) -> Result<(String, &str), String> {
+    let mut chars = text.char_indices();
+    if !matches!(chars.next(), Some((_, '"'))) {return Err(format!("Expected string, found `{}`", text))}
+    let mut res = String::new();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => return Ok((res, &text[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => res.push('\n'),
+                Some((_, 'r')) => res.push('\r'),
+                Some((_, 't')) => res.push('\t'),
+                Some((j, u @ ('u' | 'U'))) => {
+                    let n = if u == 'u' {4} else {8};
+                    let ch = text.get(j + 1..j + 1 + n)
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("Invalid escape in string `{}`", text))?;
+                    res.push(ch);
+                    for _ in 0..n {chars.next();}
+                }
+                Some((_, ch)) => res.push(ch),
+                None => break,
+            },
+            ch => res.push(ch),
+        }
+    }
+    Err(format!("Unterminated string `{}`", text))
+}
+
+/// Groups triples into instances of the declarations in a format.
+///
+/// Returns a document with the declarations followed by instances,
+/// ordered by declaration and then by rank, e.g. `person#0` before `person#1`.
+pub fn from_triples(format: &Root, triples: &[Triple]) -> Result<Root, String> {
+    let schema = Schema::from_root(format)?;
+    let mut res: Vec<Root> = match format {
+        Root::Tup(items) => items.iter()
+            .filter(|it| !matches!(it, Root::Instance {..} | Root::InstanceTy {..}))
+            .cloned()
+            .collect(),
+        _ => vec![format.clone()],
+    };
+    let mut by_subject: HashMap<&str, Vec<&Triple>> = HashMap::new();
+    let mut top: Vec<(usize, usize, &str)> = vec![];
+    for t in triples {
+        let triples = by_subject.entry(&t.subject).or_default();
+        if !triples.is_empty() || t.subject.contains('/') {
+            triples.push(t);
+            continue;
+        }
+        triples.push(t);
+        let (ty, rank) = t.subject.split_once('#')
+            .and_then(|(ty, rank)| Some((ty, rank.parse::<usize>().ok()?)))
+            .ok_or_else(|| format!("Expected subject of instance, e.g. `person#0`, found `{}`", t.subject))?;
+        let def = schema.defs.iter().position(|d| d.name().to_lowercase() == ty)
+            .ok_or_else(|| format!("{}: No declaration of type `{}`", t.subject, ty))?;
+        top.push((def, rank, &t.subject));
+    }
+    top.sort();
+    let start = res.len();
+    let index: HashMap<&str, usize> = top.iter().enumerate()
+        .map(|(i, (_, _, subject))| (*subject, start + i)).collect();
+    let importer = Importer {schema: &schema, by_subject: &by_subject, index: &index};
+    for &(def, _, subject) in &top {
+        let name = schema.defs[def].name();
+        let decl = res.iter().position(|d| matches!(d, Root::Struct {..} | Root::Enum {..}) &&
+            Def::from_root(d).map(|d| d.name() == name).unwrap_or(false))
+            .ok_or_else(|| format!("{}: No declaration of type `{}`", subject, name))?;
+        let data = importer.subject(subject, &schema.defs[def])?;
+        res.push(Root::Instance {class: decl, data: Some(Box::new(data))});
+    }
+    Ok(Root::Tup(res))
+}
+
+struct Importer<'a> {
+    schema: &'a Schema,
+    by_subject: &'a HashMap<&'a str, Vec<&'a Triple>>,
+    /// Index of instances in document by subject.
+    index: &'a HashMap<&'a str, usize>,
+}
+
+impl Importer<'_> {
+    /// Returns the data of a subject with a struct or enum type.
+    fn subject(&self, subject: &str, def: &Def) -> Result<Root, String> {
+        let triples = self.by_subject.get(subject).map(|x| &x[..]).unwrap_or(&[]);
+        match def {
+            Def::Struct(s) => self.fields(subject, triples, &s.fields, def.name()),
+            Def::Enum(e) => {
+                let t = match triples {
+                    [t] => t,
+                    _ => return Err(format!("{}: Expected one triple for variant of `{}`", subject, e.name)),
+                };
+                if t.predicate == "variant" {
+                    if let Object::Str(name) = &t.object {return Ok(Root::Str(name.clone()))}
+                }
+                let v = e.variants.iter().find(|v| *v.name == t.predicate)
+                    .ok_or_else(|| format!("{}: `{}` has no variant `{}`", subject, e.name, t.predicate))?;
+                let name = Root::Str(v.name.clone());
+                match &v.data {
+                    VariantData::Unit => Ok(name),
+                    VariantData::Type(ty) => Ok(Root::Avatar(Box::new((name,
+                        self.object(subject, &t.predicate, &t.object, ty, def.name())?)))),
+                    VariantData::Fields(fields) => match &t.object {
+                        Object::Node(child) => {
+                            let triples = self.by_subject.get(&child[..]).map(|x| &x[..]).unwrap_or(&[]);
+                            match self.fields(child, triples, fields, def.name())? {
+                                Root::Tup(fields) => Ok(Root::Struct {name: Box::new(name), fields}),
+                                _ => unreachable!(),
+                            }
+                        }
+                        x => Err(format!("{}: Expected subject for `{}`, found `{}`", subject, v.name, x)),
+                    },
+                }
+            }
+        }
+    }
+
+    /// Returns a record with fields by name, leaving out fields without triples.
+    fn fields(
+        &self,
+        subject: &str,
+        triples: &[&Triple],
+        fields: &[FieldDef],
+        self_ty: &Arc<String>,
+    ) -> Result<Root, String> {
+        if let Some(t) = triples.iter().find(|t| fields.iter().all(|f| *f.name != t.predicate)) {
+            return Err(format!("{}: Unknown field `{}`", subject, t.predicate));
+        }
+        let mut res = vec![];
+        for f in fields {
+            let objects: Vec<&Object> = triples.iter()
+                .filter(|t| *t.predicate == **f.name).map(|t| &t.object).collect();
+            let data = match (strip(&f.ty), &objects[..]) {
+                (TypeRef::Applied(base, args), _) if is_primitive(base, "Vec") => Root::Tup(objects.iter()
+                    .map(|o| self.object(subject, &f.name, o, &args[0], self_ty))
+                    .collect::<Result<_, _>>()?),
+                (_, []) => continue,
+                (_, [o]) => self.object(subject, &f.name, o, &f.ty, self_ty)?,
+                _ => return Err(format!("{}: Expected one value of `{}`", subject, f.name)),
+            };
+            res.push(Root::Field(Box::new((Root::Str(f.name.clone()), data))));
+        }
+        Ok(Root::Tup(res))
+    }
+
+    /// Returns the data of an object with a type.
+    fn object(
+        &self,
+        subject: &str,
+        predicate: &str,
+        object: &Object,
+        ty: &TypeRef,
+        self_ty: &Arc<String>,
+    ) -> Result<Root, String> {
+        let mismatch = || format!("{} {}: Expected `{}`, found `{}`", subject, predicate, ty, object);
+        match (strip(ty), object) {
+            (TypeRef::Primitive(name), _) => match (name.as_str(), object) {
+                ("f64", Object::F64(x)) => Ok(Root::F64(*x)),
+                ("f64", Object::Usize(x)) | ("usize", Object::Usize(x)) => Ok(Root::F64(*x as f64)),
+                ("bool", Object::Bool(x)) => Ok(Root::Bool(*x)),
+                ("String", Object::Str(x)) => Ok(Root::Str(x.clone())),
+                _ => Err(mismatch()),
+            },
+            (TypeRef::Applied(base, args), _) if is_primitive(base, "Option") =>
+                self.object(subject, predicate, object, &args[0], self_ty),
+            (TypeRef::Applied(base, _), Object::Node(id)) if is_primitive(base, "Ref") =>
+                self.index.get(&id[..]).map(|i| Root::Ref(*i))
+                    .ok_or_else(|| format!("{} {}: Unknown instance `{}`", subject, predicate, id)),
+            (TypeRef::Tuple(tys), Object::Node(id)) => {
+                let triples = self.by_subject.get(&id[..]).map(|x| &x[..]).unwrap_or(&[]);
+                if triples.len() != tys.len() {return Err(mismatch())}
+                Ok(Root::Tup(triples.iter().zip(tys)
+                    .map(|(t, ty)| self.object(id, &t.predicate, &t.object, ty, self_ty))
+                    .collect::<Result<_, _>>()?))
+            }
+            (TypeRef::Named(_), _) | (TypeRef::Applied(..), _) | (TypeRef::SelfRef, _) => {
+                let ty = if value::is_self(ty) {TypeRef::Named(self_ty.clone())} else {ty.clone()};
+                let def = self.schema.instantiate(&ty)
+                    .map_err(|err| format!("{} {}: {}", subject, predicate, err))?;
+                match (&def, object) {
+                    (Def::Enum(_), Object::Str(name)) => Ok(Root::Str(name.clone())),
+                    (_, Object::Node(id)) => self.subject(id, &def),
+                    _ => Err(mismatch()),
+                }
+            }
+            _ => Err(mismatch()),
+        }
+    }
+}
+
+/// Removes `Arc` and `box`, which do not change how data is written.
+fn strip(ty: &TypeRef) -> &TypeRef {
+    match ty {
+        TypeRef::Applied(base, args) if args.len() == 1 &&
+            (is_primitive(base, "Arc") || is_primitive(base, "box")) => strip(&args[0]),
+        _ => ty,
+    }
+}
+
+fn is_primitive(ty: &TypeRef, name: &str) -> bool {
+    matches!(ty, TypeRef::Primitive(n) if n.as_str() == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_person() {
+        let doc = data(include_str!("../source/test/person/person-with-instance.txt"));
+        let triples = to_triples(&doc).unwrap();
+        let tsv = to_tsv(&triples);
+        assert_eq!(tsv, "person#0\tfirst_name\t\"Donald\"
+person#0\tlast_name\t\"Duck\"
+person#1\tfirst_name\t\"Dolly\"
+person#1\tlast_name\t\"Duck\"
+");
+        assert_eq!(from_tsv(&tsv).unwrap(), triples);
+        let nt = to_ntriples(&triples, BASE);
+        assert!(nt.starts_with("<iknow:person#0> <iknow:first_name> \"Donald\" .\n"));
+        assert_eq!(from_ntriples(&nt, BASE).unwrap(), triples);
+
+        let escaped = vec![Triple {
+            subject: "person#0".into(),
+            predicate: "nick".into(),
+            object: Object::Str(Arc::new("tab\t \"quote\" back\\slash \u{1b}\0 ok".into())),
+        }];
+        let tsv = to_tsv(&escaped);
+        assert_eq!(tsv, "person#0\tnick\t\"tab\\t \\\"quote\\\" back\\\\slash \\u001B\\u0000 ok\"\n");
+        assert_eq!(from_tsv(&tsv).unwrap(), escaped);
+        assert_eq!(from_ntriples(&to_ntriples(&escaped, BASE), BASE).unwrap(), escaped);
+
+        let format = data(include_str!("../source/test/person/person.txt"));
+        let res = from_triples(&format, &triples).unwrap();
+        assert_eq!(validate::complete(&res).unwrap(), doc);
+
+        let defaults = data(include_str!("../source/test/person/person-defaults.txt"));
+        let res = from_triples(&defaults, &from_tsv("person#0\tfirst_name\t\"Donald\"
+person#0\tlast_name\t\"Duck\"
+person#0\tage\t80
+").unwrap()).unwrap();
+        assert_eq!(validate::complete(&res).unwrap(), data(r#"[
+            struct Person {
+              first_name: Arc<String>,
+              last_name: Arc<String>,
+              age: f64 = 0,
+              nick: Option<Arc<String>>,
+            },
+            (ins 0 ["Donald", "Duck", 80, Option::None]),
+        ]"#));
+        assert!(from_triples(&defaults, &from_tsv("person#0\tweight\t1\n").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_graph() {
+        let doc = data(include_str!("../source/test/graph/graph-with-refs.txt"));
+        let triples = to_triples(&doc).unwrap();
+        let tsv = to_tsv(&triples);
+        assert!(tsv.starts_with("node#0\tStr\t\"Donald\"\n"));
+        assert!(tsv.contains("edge#1\tfrom\tnode#2\nedge#1\tto\tnode#0\nedge#1\tdir\t\"Right\"\n"));
+        let nt = to_ntriples(&triples, "http://example.org/");
+        assert!(nt.contains("<http://example.org/edge#1> <http://example.org/from> <http://example.org/node#2> .\n"));
+        assert_eq!(from_ntriples(&nt, "http://example.org/").unwrap(), triples);
+
+        let format = match &doc {Root::Tup(items) => Root::Tup(items[..3].to_vec()), _ => unreachable!()};
+        let res = from_triples(&format, &triples).unwrap();
+        assert_eq!(validate::complete(&res).unwrap(), validate::complete(&doc).unwrap());
+    }
+
+    #[test]
+    fn test_nested() {
+        let doc = data(r#"[
+            struct Person {name: Arc<String>, address: (ty Address), tags: Vec<Arc<String>>},
+            struct Address {city: Arc<String>, pos: (f64, f64)},
+            (ins 0 ["Donald", ["Duckburg", (1, 2)], ["duck", "nephews"]]),
+        ]"#);
+        let triples = to_triples(&doc).unwrap();
+        assert_eq!(to_tsv(&triples), "person#0\tname\t\"Donald\"
+person#0\taddress\tperson#0/address
+person#0/address\tcity\t\"Duckburg\"
+person#0/address\tpos\tperson#0/address/pos
+person#0/address/pos\t0\t1
+person#0/address/pos\t1\t2
+person#0\ttags\t\"duck\"
+person#0\ttags\t\"nephews\"
+");
+        let format = match &doc {Root::Tup(items) => Root::Tup(items[..2].to_vec()), _ => unreachable!()};
+        let res = from_triples(&format, &from_tsv(&to_tsv(&triples)).unwrap()).unwrap();
+        assert_eq!(validate::complete(&res).unwrap(), validate::complete(&doc).unwrap());
+    }
+}
@@ -0,0 +1,157 @@
+//! Validation of data against a schema.
+
+use crate::*;
+use crate::path::Path;
+use crate::schema::*;
+
+/// Validates data against a type.
+///
+/// Generic types are instantiated before their data is checked,
+/// e.g. `Foo<Arc<String>>` checks `inner` against `Arc<String>`.
+pub fn validate(schema: &Schema, ty: &TypeRef, data: &Root) -> Result<(), String> {
+    value::read_type(schema, ty, data).map(|_| ())
+}
+
+/// Validates instances in a document against declarations in the same document.
+///
+/// An instance `(ins N ..)` refers to the declaration at index `N`,
+/// while an instance with explicit type, e.g. `(ins Foo<f64> ..)`, is looked up by name.
+/// Instances are read the same way as by `value::read_instances`,
+/// so the explicit type must be a declared type, possibly with arguments.
+/// Constraints declared with attributes and references between instances are checked,
+/// reporting all violations, one per line.
+pub fn validate_document(doc: &Root) -> Result<(), String> {
+    let schema = Schema::from_root(doc)?;
+    schema.check_arity()?;
+    check_defaults(&schema)?;
+    value::read_instances(doc)?;
+    let mut violations = constraint::check(doc)?;
+    violations.extend(reference::check(doc)?);
+    if violations.is_empty() {return Ok(())}
+    let lines: Vec<String> = violations.iter().map(|v| format!("{}", v)).collect();
+    Err(lines.join("\n"))
+}
+
+/// Validates a document and returns it with omitted fields filled in.
+///
+/// Omitted fields get their default value, or `Option::None` for `Option` fields,
+/// and all records are written positionally, e.g. `["Donald", "Duck", 0]`.
+pub fn complete(doc: &Root) -> Result<Root, String> {
+    validate_document(doc)?;
+    let mut res = match doc {
+        Root::Tup(items) => items.clone(),
+        _ => return Ok(doc.clone()),
+    };
+    for ins in value::read_instances(doc)? {
+        match &mut res[ins.index] {
+            Root::Instance {data, ..} | Root::InstanceTy {data, ..} =>
+                *data = Some(Box::new(ins.value.to_data())),
+            _ => {}
+        }
+    }
+    Ok(Root::Tup(res))
+}
+
+/// Checks that default values of fields conform to their types.
+///
+/// Fields of generic types are checked when the default is used.
+fn check_defaults(schema: &Schema) -> Result<(), String> {
+    for def in &schema.defs {
+        let fields: Vec<&FieldDef> = match def {
+            Def::Struct(s) => s.fields.iter().collect(),
+            Def::Enum(e) => e.variants.iter().flat_map(|v| match &v.data {
+                VariantData::Fields(fields) => &fields[..],
+                _ => &[],
+            }).collect(),
+        };
+        for f in fields {
+            if let (Some(default), true) = (&f.default, def.params().is_empty()) {
+                let path = Path::root().name(def.name()).name(&f.name);
+                value::read_type_at(schema, &f.ty, default, &path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generic_instances() {
+        let doc = match parsing::parse_str(
+            include_str!("../source/test/generics/gen_struct-with-instance.txt"), &[]) {
+            Ok(x) => x,
+            Err(err) => panic!("ERROR:\n{}", err),
+        };
+        validate_document(&doc).unwrap();
+
+        let schema = Schema::from_root(&doc).unwrap();
+        let foo_str = TypeRef::parse("Foo<Arc<String>>").unwrap();
+        assert!(validate(&schema, &foo_str, &parsing::parse_str(r#"["hi"]"#, &[]).unwrap()).is_ok());
+        assert!(validate(&schema, &foo_str, &parsing::parse_str("[1]", &[]).unwrap()).is_err());
+        assert!(validate(&schema, &TypeRef::parse("(ty Foo)").unwrap(),
+            &parsing::parse_str("[1]", &[]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_documents() {
+        let doc = parsing::parse_str(
+            include_str!("../source/test/person/person-with-instance.txt"), &[]).unwrap();
+        validate_document(&doc).unwrap();
+
+        let doc = parsing::parse_str(r#"[
+            struct Person {first_name: Arc<String>, last_name: Arc<String>},
+            (ins 0 ["Donald"]),
+        ]"#, &[]).unwrap();
+        assert!(validate_document(&doc).is_err());
+        let err = validate_document(&parsing::parse_str("[(ins 1 [])]", &[]).unwrap()).unwrap_err();
+        assert_eq!(err, "[0]: Instance refers to `1`, which is not a declaration");
+
+        let doc = parsing::parse_str(r#"[
+            enum EdgeDir {Left, Right, Both, None},
+            struct Edge {from: usize, to: usize, dir: (ty EdgeDir)},
+            (ins 1 [0, 1, EdgeDir::Left]),
+            (ins 1 [0, "one", EdgeDir::Left]),
+        ]"#, &[]).unwrap();
+        let err = validate_document(&doc).unwrap_err();
+        assert!(err.starts_with("[3].data.to: "), "{}", err);
+
+        let doc = parsing::parse_str(r#"[
+            enum EdgeDir {Left, Right, Both, None},
+            struct Edge {from: usize, to: usize, dir: (ty EdgeDir)},
+            (ins 1 [0, 1, EdgeDir::Left]),
+            (ins 1 [from: 1, to: 2, dir: "Both"]),
+        ]"#, &[]).unwrap();
+        validate_document(&doc).unwrap();
+    }
+
+    #[test]
+    fn test_defaults() {
+        let format = parsing::parse_str(
+            include_str!("../source/test/person/person-defaults.txt"), &[]).unwrap();
+        assert_eq!(parsing::parse_str(&format!("{}", format), &[]).unwrap(), format);
+        let schema = Schema::from_root(&format).unwrap();
+        assert_eq!(schema.to_root(), format);
+        if let Def::Struct(s) = &schema.defs[0] {
+            assert_eq!(s.fields[2].default, Some(Root::F64(0.0)));
+            assert!(s.fields[2].is_optional() && s.fields[3].is_optional());
+            assert!(!s.fields[0].is_optional());
+        }
+
+        let person = TypeRef::parse("(ty Person)").unwrap();
+        let read = |src: &str| validate(&schema, &person, &parsing::parse_str(src, &[]).unwrap());
+        assert!(read(r#"["Donald", "Duck"]"#).is_ok());
+        assert!(read(r#"[last_name: "Duck", first_name: "Donald"]"#).is_ok());
+        assert!(read(r#"["Donald"]"#).is_err());
+        assert!(read(r#"["Donald", "Duck", 1, "Don", 2]"#).is_err());
+        assert!(read(r#"[first_name: "Donald", last_name: "Duck", weight: 1]"#).is_err());
+
+        let doc = parsing::parse_str(r#"[
+            struct Person {name: Arc<String>, age: f64 = "old"},
+        ]"#, &[]).unwrap();
+        assert_eq!(validate_document(&doc).unwrap_err(),
+            r#".Person.age: Expected `f64`, found `"old"`"#);
+    }
+}
@@ -0,0 +1,656 @@
+//! Generic values of data described by a format.
+//!
+//! Data is read by interpreting it against a struct or enum declaration.
+//! Since `root_self()` is such a declaration,
+//! Iknow can read `Root` values using only its own self-description.
+
+use crate::*;
+use crate::path::Path;
+use crate::schema::*;
+
+/// A generic value, read from data described by a format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// An `f64` value.
+    F64(f64),
+    /// A `bool` value.
+    Bool(bool),
+    /// A `usize` value.
+    Usize(usize),
+    /// A `String` value.
+    Str(Arc<String>),
+    /// An `Option` value.
+    Option(Option<Box<Value>>),
+    /// Items of a `Vec`.
+    List(Vec<Value>),
+    /// Items of a tuple.
+    Tuple(Vec<Value>),
+    /// A struct value with named fields.
+    Struct(Record),
+    /// An enum value.
+    Variant(Variant),
+    /// A reference to an instance, e.g. `(ref 3)`.
+    Ref(Reference),
+}
+
+/// A struct value, or the fields of a struct-like variant.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Name of struct or variant.
+    pub ty: Arc<String>,
+    /// Field definitions with values, in declared order.
+    pub fields: Vec<(FieldDef, Value)>,
+}
+
+/// An enum variant value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    /// Name of enum.
+    pub ty: Arc<String>,
+    /// Name of variant.
+    pub name: Arc<String>,
+    /// Data of variant, if any.
+    pub data: Option<Box<Value>>,
+}
+
+/// A reference to an instance in a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    /// Name of the type of referenced instance.
+    pub ty: Arc<String>,
+    /// Index of instance among the items of document.
+    pub index: usize,
+}
+
+/// An instance read from a document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+    /// Index of instance among the items of document.
+    pub index: usize,
+    /// Name of the type of instance.
+    pub class: Arc<String>,
+    /// Value of instance.
+    pub value: Value,
+}
+
+/// Reads data described by a format.
+///
+/// The format is a struct or enum declaration, or a tuple of these,
+/// where the first declaration is the type of data.
+pub fn read(format: &Root, data: &Root) -> Result<Value, String> {
+    let schema = Schema::from_root(format)?;
+    let def = schema.defs.first().ok_or_else(|| "Format has no declarations".to_string())?;
+    read_type(&schema, &TypeRef::Named(def.name().clone()), data)
+}
+
+/// Reads data of a type, using the definitions in a schema.
+///
+/// Generic types are instantiated before their data is read,
+/// e.g. `Foo<Arc<String>>` reads `inner` as `Arc<String>`.
+pub fn read_type(schema: &Schema, ty: &TypeRef, data: &Root) -> Result<Value, String> {
+    read_type_at(schema, ty, data, &Path::root())
+}
+
+/// Reads data of a type located at a path, which is used in error messages.
+pub fn read_type_at(schema: &Schema, ty: &TypeRef, data: &Root, path: &Path) -> Result<Value, String> {
+    Reader {schema, self_def: None}.read(ty, data, path)
+}
+
+/// Reads instances of a document against declarations in the same document.
+///
+/// An instance `(ins N ..)` refers to the declaration at index `N`,
+/// while an instance with explicit type, e.g. `(ins Foo<f64> ..)`, is looked up by name.
+/// Instances without data are skipped.
+pub fn read_instances(doc: &Root) -> Result<Vec<Instance>, String> {
+    let schema = Schema::from_root(doc)?;
+    let items = if let Root::Tup(items) = doc {items} else {return Ok(vec![])};
+    let mut res = vec![];
+    for i in 0..items.len() {
+        res.extend(read_instance(&schema, items, i)?);
+    }
+    Ok(res)
+}
+
+/// Reads the item at an index of a document, if it is an instance with data.
+///
+/// The schema contains the declarations of the document, see `read_instances`.
+pub fn read_instance(schema: &Schema, items: &[Root], i: usize) -> Result<Option<Instance>, String> {
+    let (ty, data) = match &items[i] {
+        Root::Instance {class, data} => match items.get(*class) {
+            Some(decl @ Root::Struct {..}) | Some(decl @ Root::Enum {..}) =>
+                (TypeRef::Named(Def::from_root(decl)?.name().clone()), data),
+            _ => return Err(format!("[{}]: Instance refers to `{}`, which is not a declaration",
+                i, class)),
+        },
+        Root::InstanceTy {ty, data} => (TypeRef::from_root(ty, &[])?, data),
+        _ => return Ok(None),
+    };
+    let class = match &ty {
+        TypeRef::Named(name) => name.clone(),
+        TypeRef::Applied(base, _) => match &**base {
+            TypeRef::Named(name) => name.clone(),
+            _ => return Err(format!("[{}]: Expected named type, found `{}`", i, ty)),
+        },
+        _ => return Err(format!("[{}]: Expected named type, found `{}`", i, ty)),
+    };
+    match data {
+        Some(data) => {
+            let path = Path::root().index(i).name(&Arc::new("data".into()));
+            let value = read_type_at(schema, &ty, data, &path)?;
+            Ok(Some(Instance {index: i, class, value}))
+        }
+        None => Ok(None),
+    }
+}
+
+impl Value {
+    /// Returns the value of a field by name.
+    ///
+    /// Works for struct values and variants with named fields.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.record()?.fields.iter().find(|(f, _)| f.name.as_str() == name).map(|(_, v)| v)
+    }
+
+    /// Iterates over fields by name, in declared order.
+    pub fn fields(&self) -> impl Iterator<Item = (&Arc<String>, &Value)> {
+        self.record().map(|r| &r.fields[..]).unwrap_or(&[]).iter().map(|(f, v)| (&f.name, v))
+    }
+
+    /// Returns the struct value or the fields of a struct-like variant.
+    pub fn record(&self) -> Option<&Record> {
+        match self {
+            Value::Struct(rec) => Some(rec),
+            Value::Variant(Variant {data: Some(data), ..}) => match &**data {
+                Value::Struct(rec) => Some(rec),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn record_mut(&mut self) -> Option<&mut Record> {
+        match self {
+            Value::Struct(rec) => Some(rec),
+            Value::Variant(Variant {data: Some(data), ..}) => match &mut **data {
+                Value::Struct(rec) => Some(rec),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the variant name of an enum value.
+    pub fn variant(&self) -> Option<&str> {
+        if let Value::Variant(var) = self {Some(&var.name)} else {None}
+    }
+
+    /// Returns the data of an enum variant.
+    pub fn variant_data(&self) -> Option<&Value> {
+        if let Value::Variant(var) = self {var.data.as_deref()} else {None}
+    }
+
+    /// Returns the name of struct or enum type.
+    pub fn type_name(&self) -> Option<&str> {
+        match self {
+            Value::Struct(rec) => Some(&rec.ty),
+            Value::Variant(var) => Some(&var.ty),
+            _ => None,
+        }
+    }
+
+    /// Returns an `f64` value.
+    ///
+    /// A `usize` value is converted.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::F64(x) => Some(*x),
+            Value::Usize(x) => Some(*x as f64),
+            _ => None,
+        }
+    }
+
+    /// Returns a `usize` value.
+    pub fn as_usize(&self) -> Option<usize> {
+        if let Value::Usize(x) = self {Some(*x)} else {None}
+    }
+
+    /// Returns a `bool` value.
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Value::Bool(x) = self {Some(*x)} else {None}
+    }
+
+    /// Returns a string value.
+    pub fn as_str(&self) -> Option<&str> {
+        if let Value::Str(x) = self {Some(x)} else {None}
+    }
+
+    /// Returns the items of a list or tuple.
+    pub fn as_slice(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(items) | Value::Tuple(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Returns the inner value of an `Option`, or the value itself otherwise.
+    pub fn flatten(&self) -> Option<&Value> {
+        match self {
+            Value::Option(x) => x.as_deref().and_then(|x| x.flatten()),
+            x => Some(x),
+        }
+    }
+
+    /// Replaces the value of a field,
+    /// if the new value conforms to the declared type of the field.
+    pub fn set(&mut self, name: &str, value: Value) -> Result<(), String> {
+        let self_ty = self.type_name().map(|n| Arc::new(n.to_string()));
+        let rec = self.record_mut().ok_or_else(|| "Expected struct value".to_string())?;
+        let (f, v) = rec.fields.iter_mut().find(|(f, _)| f.name.as_str() == name)
+            .ok_or_else(|| format!("`{}` has no field `{}`", rec.ty, name))?;
+        if !value.conforms(&f.ty, self_ty.as_ref()) {
+            return Err(format!("Field `{}` expects `{}`, found `{}`", name, f.ty, value.to_data()));
+        }
+        *v = value;
+        Ok(())
+    }
+
+    /// Changes the variant of an enum value,
+    /// if the variant is declared in the schema and the data conforms to it.
+    pub fn set_variant(
+        &mut self,
+        schema: &Schema,
+        name: &str,
+        data: Option<Value>,
+    ) -> Result<(), String> {
+        let var = if let Value::Variant(var) = self {var} else {
+            return Err("Expected enum value".into())
+        };
+        let def = match schema.get(&var.ty) {
+            Some(Def::Enum(e)) => e,
+            _ => return Err(format!("Unknown enum `{}`", var.ty)),
+        };
+        let v = def.variants.iter().find(|v| v.name.as_str() == name)
+            .ok_or_else(|| format!("`{}` has no variant `{}`", def.name, name))?;
+        let ok = match (&v.data, &data) {
+            (VariantData::Unit, None) => true,
+            (VariantData::Type(ty), Some(data)) => data.conforms(ty, Some(&def.name)),
+            (VariantData::Fields(fields), Some(Value::Struct(rec))) =>
+                rec.ty == v.name && rec.fields.len() == fields.len() &&
+                fields.iter().zip(&rec.fields).all(|(f, (g, val))|
+                    f.name == g.name && val.conforms(&f.ty, Some(&def.name))),
+            _ => false,
+        };
+        if !ok {
+            return Err(format!("Wrong data for variant `{}::{}`", def.name, name));
+        }
+        var.name = v.name.clone();
+        var.data = data.map(Box::new);
+        Ok(())
+    }
+
+    /// Returns `true` if the value conforms to a type.
+    ///
+    /// `Self` refers to `self_ty`, and generic parameters accept any value.
+    pub fn conforms(&self, ty: &TypeRef, self_ty: Option<&Arc<String>>) -> bool {
+        let named = |name: &Arc<String>| self.type_name() == Some(name.as_str());
+        match ty {
+            TypeRef::Primitive(name) => matches!((name.as_str(), self),
+                ("f64", Value::F64(_)) | ("bool", Value::Bool(_)) |
+                ("usize", Value::Usize(_)) | ("String", Value::Str(_))),
+            TypeRef::Applied(base, args) => match (&**base, &args[..], self) {
+                (TypeRef::Primitive(name), [arg], _) => match (name.as_str(), self) {
+                    ("Arc", _) | ("box", _) => self.conforms(arg, self_ty),
+                    ("Option", Value::Option(None)) => true,
+                    ("Option", Value::Option(Some(x))) => x.conforms(arg, self_ty),
+                    ("Vec", Value::List(items)) => items.iter().all(|it| it.conforms(arg, self_ty)),
+                    ("Ref", Value::Ref(r)) => match arg {
+                        TypeRef::Generic(_) => true,
+                        TypeRef::SelfRef => self_ty == Some(&r.ty),
+                        arg => ref_target(arg) == Some(&r.ty),
+                    },
+                    _ => false,
+                },
+                (TypeRef::Named(name), _, _) => named(name),
+                (TypeRef::SelfRef, _, _) => self_ty.map(named).unwrap_or(false),
+                _ => false,
+            },
+            TypeRef::Named(name) => named(name),
+            TypeRef::SelfRef => self_ty.map(named).unwrap_or(false),
+            TypeRef::Generic(_) => true,
+            TypeRef::Tuple(tys) => match self {
+                Value::Tuple(items) => items.len() == tys.len() &&
+                    items.iter().zip(tys).all(|(it, ty)| it.conforms(ty, self_ty)),
+                _ => false,
+            },
+        }
+    }
+
+    /// Converts value back into data.
+    ///
+    /// Struct values become tuples with fields in declared order,
+    /// e.g. `["Donald", "Duck"]`, and variants use the form they were declared with,
+    /// e.g. `Left`, `F64(1)` or `Instance {class: 0, data: Option::None}`.
+    pub fn to_data(&self) -> Root {
+        match self {
+            Value::F64(v) => Root::F64(*v),
+            Value::Bool(v) => Root::Bool(*v),
+            Value::Usize(v) => Root::F64(*v as f64),
+            Value::Str(v) => Root::Str(v.clone()),
+            Value::Ref(r) => Root::Ref(r.index),
+            Value::Option(None) => Root::InstanceTy {
+                ty: Box::new(Root::ty_option()),
+                data: Some(Box::new(Root::Str(Arc::new("None".into())))),
+            },
+            Value::Option(Some(v)) => v.to_data(),
+            Value::List(items) | Value::Tuple(items) =>
+                Root::Tup(items.iter().map(|it| it.to_data()).collect()),
+            Value::Struct(rec) => Root::Tup(rec.fields.iter().map(|(_, v)| v.to_data()).collect()),
+            Value::Variant(var) => match var.data.as_deref() {
+                None => Root::Str(var.name.clone()),
+                Some(Value::Struct(rec)) if rec.ty == var.name => Root::Struct {
+                    name: Box::new(Root::Str(var.name.clone())),
+                    fields: rec.fields.iter()
+                        .map(|(f, v)| Root::Field(Box::new((Root::Str(f.name.clone()), v.to_data()))))
+                        .collect(),
+                },
+                Some(v) => Root::Avatar(Box::new((Root::Str(var.name.clone()), v.to_data()))),
+            },
+        }
+    }
+}
+
+/// Returns the name of the type referred to by `Ref<T>`, e.g. `Foo` for `Foo<f64>`.
+fn ref_target(ty: &TypeRef) -> Option<&Arc<String>> {
+    match ty {
+        TypeRef::Named(name) => Some(name),
+        TypeRef::Applied(base, _) => ref_target(base),
+        _ => None,
+    }
+}
+
+struct Reader<'a> {
+    schema: &'a Schema,
+    self_def: Option<Def>,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&self, ty: &TypeRef, data: &Root, path: &Path) -> Result<Value, String> {
+        match ty {
+            TypeRef::Primitive(name) => match (name.as_str(), data) {
+                ("f64", Root::F64(x)) => Ok(Value::F64(*x)),
+                ("bool", Root::Bool(x)) => Ok(Value::Bool(*x)),
+                ("String", Root::Str(x)) => Ok(Value::Str(x.clone())),
+                ("usize", Root::F64(x)) if *x >= 0.0 && x.fract() == 0.0 =>
+                    Ok(Value::Usize(*x as usize)),
+                _ => Err(mismatch(path, ty, data)),
+            },
+            TypeRef::Applied(base, args) => match (&**base, &args[..]) {
+                (TypeRef::Primitive(name), [arg]) => match name.as_str() {
+                    "Arc" | "box" => self.read(arg, data, path),
+                    "Option" => match option(data) {
+                        Some(None) => Ok(Value::Option(None)),
+                        Some(Some(inner)) => Ok(Value::Option(Some(Box::new(self.read(arg, inner, path)?)))),
+                        None => Ok(Value::Option(Some(Box::new(self.read(arg, data, path)?)))),
+                    },
+                    "Vec" => if let Root::Tup(items) = data {
+                        Ok(Value::List(items.iter().enumerate()
+                            .map(|(i, it)| self.read(arg, it, &path.index(i)))
+                            .collect::<Result<_, _>>()?))
+                    } else {
+                        Err(mismatch(path, ty, data))
+                    },
+                    "Ref" => {
+                        let target = match arg {
+                            TypeRef::SelfRef => self.self_def.as_ref().map(|d| d.name()),
+                            arg => ref_target(arg),
+                        }.ok_or_else(|| format!("{}: Expected named type in `{}`", path, ty))?;
+                        match data {
+                            Root::Ref(index) => Ok(Value::Ref(Reference {ty: target.clone(), index: *index})),
+                            _ => Err(mismatch(path, ty, data)),
+                        }
+                    }
+                    _ => Err(format!("{}: Unknown type `{}`", path, ty)),
+                },
+                (TypeRef::Named(_), _) | (TypeRef::SelfRef, _) => self.read_def(ty, data, path),
+                _ => Err(format!("{}: Unknown type `{}`", path, ty)),
+            },
+            TypeRef::Named(_) | TypeRef::SelfRef => self.read_def(ty, data, path),
+            TypeRef::Generic(name) =>
+                Err(format!("{}: Uninstantiated generic parameter `.{}`", path, name)),
+            TypeRef::Tuple(tys) => match data {
+                Root::Tup(items) if items.len() == tys.len() => Ok(Value::Tuple(tys.iter()
+                    .zip(items).enumerate()
+                    .map(|(i, (ty, it))| self.read(ty, it, &path.index(i)))
+                    .collect::<Result<_, _>>()?)),
+                _ => Err(mismatch(path, ty, data)),
+            },
+        }
+    }
+
+    fn read_def(&self, ty: &TypeRef, data: &Root, path: &Path) -> Result<Value, String> {
+        let def = if is_self(ty) {
+            self.self_def.clone()
+                .ok_or_else(|| format!("{}: `Self` used outside of a definition", path))?
+        } else {
+            self.schema.instantiate(ty).map_err(|err| format!("{}: {}", path, err))?
+        };
+        // Explicitly typed data, e.g. `EdgeDir::Left`.
+        let data = match data {
+            Root::InstanceTy {ty: data_ty, data: Some(inner)} => {
+                let name = match TypeRef::from_root(data_ty, &[])? {
+                    TypeRef::Named(name) => name,
+                    TypeRef::Applied(base, _) => match *base {
+                        TypeRef::Named(name) => name,
+                        x => return Err(mismatch(path, ty, &x.to_root())),
+                    },
+                    x => return Err(mismatch(path, ty, &x.to_root())),
+                };
+                if name != *def.name() {return Err(mismatch(path, ty, data))}
+                &**inner
+            }
+            _ => data,
+        };
+        let sub = Reader {schema: self.schema, self_def: Some(def.clone())};
+        match &def {
+            Def::Struct(s) => match data {
+                Root::Tup(items) => Ok(Value::Struct(sub.read_fields(&s.name, &s.fields, items, path)?)),
+                Root::Struct {name, fields} if **name == Root::Str(s.name.clone()) =>
+                    Ok(Value::Struct(sub.read_fields(&s.name, &s.fields, fields, path)?)),
+                _ => Err(mismatch(path, ty, data)),
+            },
+            Def::Enum(e) => {
+                let (name, payload) = match data {
+                    Root::Str(name) => (name, None),
+                    Root::Avatar(ab) => match &ab.0 {
+                        Root::Str(name) => (name, Some(&ab.1)),
+                        _ => return Err(mismatch(path, ty, data)),
+                    },
+                    Root::Struct {name, ..} => match &**name {
+                        Root::Str(name) => (name, Some(data)),
+                        _ => return Err(mismatch(path, ty, data)),
+                    },
+                    _ => return Err(mismatch(path, ty, data)),
+                };
+                let variant = e.variants.iter().find(|v| &v.name == name)
+                    .ok_or_else(|| format!("{}: `{}` has no variant `{}`", path, e.name, name))?;
+                let data = match (&variant.data, payload) {
+                    (VariantData::Unit, None) => None,
+                    (VariantData::Type(ty), Some(payload)) =>
+                        Some(sub.read(ty, payload, &path.name(name))?),
+                    (VariantData::Fields(fields), Some(Root::Struct {fields: items, ..})) =>
+                        Some(Value::Struct(sub.read_fields(name, fields, items, path)?)),
+                    _ => return Err(format!("{}: Wrong data for variant `{}::{}`, found `{}`",
+                        path, e.name, name, data)),
+                };
+                Ok(Value::Variant(Variant {
+                    ty: e.name.clone(),
+                    name: name.clone(),
+                    data: data.map(Box::new),
+                }))
+            }
+        }
+    }
+
+    fn read_fields(
+        &self,
+        ty: &Arc<String>,
+        fields: &[FieldDef],
+        items: &[Root],
+        path: &Path,
+    ) -> Result<Record, String> {
+        // Named fields may be given in any order.
+        let named = !items.is_empty() && items.iter().all(|it| it.as_field().is_some());
+        if named {
+            for (name, _) in items.iter().filter_map(|it| it.as_field()) {
+                if fields.iter().all(|f| f.name != *name) {
+                    return Err(format!("{}: `{}` has no field `{}`", path, ty, name));
+                }
+            }
+        } else if items.len() > fields.len() {
+            return Err(format!("{}: Expected {} field(s), found {}",
+                path, fields.len(), items.len()));
+        }
+        let mut res = vec![];
+        for (i, f) in fields.iter().enumerate() {
+            let it = if named {
+                items.iter().filter_map(|it| it.as_field())
+                    .find(|(name, _)| **name == f.name)
+                    .map(|(_, value)| value)
+            } else {
+                match items.get(i).map(|it| (it, it.as_field())) {
+                    Some((_, Some((name, value)))) if *name == f.name => Some(value),
+                    Some((_, Some((name, _)))) => return Err(format!(
+                        "{}: Expected field `{}`, found `{}`", path, f.name, name)),
+                    Some((it, None)) => Some(it),
+                    None => None,
+                }
+            };
+            let value = match it {
+                Some(it) => self.read(&f.ty, it, &path.name(&f.name))?,
+                None => match &f.default {
+                    Some(data) => self.read(&f.ty, data, &path.name(&f.name))?,
+                    None if f.ty.is_option() => Value::Option(None),
+                    None => return Err(format!("{}: Missing field `{}`", path, f.name)),
+                },
+            };
+            res.push((f.clone(), value));
+        }
+        Ok(Record {ty: ty.clone(), fields: res})
+    }
+}
+
+pub(crate) fn is_self(ty: &TypeRef) -> bool {
+    match ty {
+        TypeRef::SelfRef => true,
+        TypeRef::Applied(base, _) => **base == TypeRef::SelfRef,
+        _ => false,
+    }
+}
+
+/// Recognizes `Option::None` and `Option::Some(..)`.
+fn option(data: &Root) -> Option<Option<&Root>> {
+    if let Root::InstanceTy {ty, data: Some(inner)} = data {
+        if **ty == Root::ty_option() {
+            match &**inner {
+                Root::Str(name) if name.as_str() == "None" => return Some(None),
+                Root::Avatar(ab) if ab.0 == Root::Str(Arc::new("Some".into())) =>
+                    return Some(Some(&ab.1)),
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+fn mismatch(path: &Path, ty: &TypeRef, data: &Root) -> String {
+    format!("{}: Expected `{}`, found `{}`", path, ty, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_self() {
+        let data = match parsing::parse_str(
+            include_str!("../source/test/self/root-data.txt"), &[]) {
+            Ok(x) => x,
+            Err(err) => panic!("ERROR:\n{}", err),
+        };
+        let schema = Schema::from_root(&root_self()).unwrap();
+        let value = read_type(&schema, &TypeRef::parse("Vec<(ty Root)>").unwrap(), &data).unwrap();
+        let items = if let Value::List(items) = &value {items} else {panic!("Expected list")};
+        assert_eq!(items.len(), 9);
+        assert_eq!(items[0], Value::Variant(Variant {
+            ty: Arc::new("Root".into()),
+            name: Arc::new("Ty".into()),
+            data: Some(Box::new(Value::Str(Arc::new("f64".into())))),
+        }));
+        if let Value::Variant(Variant {name, data: Some(data), ..}) = &items[7] {
+            assert_eq!(name.as_str(), "Struct");
+            assert!(matches!(&**data, Value::Struct(Record {fields, ..}) if fields.len() == 2));
+        } else {
+            panic!("Expected struct variant");
+        }
+        assert_eq!(value.to_data(), data);
+
+        // Each item can also be read directly with the self-description as format.
+        if let Root::Tup(data_items) = &data {
+            for it in data_items {read(&root_self(), it).unwrap();}
+        }
+        assert!(read(&root_self(), &Root::F64(1.0)).is_err());
+    }
+
+    #[test]
+    fn test_read_person() {
+        let person = parsing::parse_str(
+            include_str!("../source/test/person/person.txt"), &[]).unwrap();
+        let value = read(&person, &parsing::parse_str(r#"["Donald", "Duck"]"#, &[]).unwrap()).unwrap();
+        if let Value::Struct(rec) = &value {
+            assert_eq!(rec.fields[1].0.name.as_str(), "last_name");
+            assert_eq!(rec.fields[1].1, Value::Str(Arc::new("Duck".into())));
+        } else {
+            panic!("Expected struct");
+        }
+    }
+
+    #[test]
+    fn test_api() {
+        let doc = parsing::parse_str(r#"[
+            enum EdgeDir {Left, Right, Both, None},
+            struct Edge {from: usize, to: usize, dir: (ty EdgeDir)},
+        ]"#, &[]).unwrap();
+        let schema = Schema::from_root(&doc).unwrap();
+        let mut edge = read_type(&schema, &TypeRef::parse("(ty Edge)").unwrap(),
+            &parsing::parse_str("[0, 1, EdgeDir::Left]", &[]).unwrap()).unwrap();
+        assert_eq!(edge.type_name(), Some("Edge"));
+        assert_eq!(edge.get("to").and_then(|v| v.as_usize()), Some(1));
+        assert_eq!(edge.get("to").and_then(|v| v.as_f64()), Some(1.0));
+        assert_eq!(edge.get("dir").and_then(|v| v.variant()), Some("Left"));
+        assert!(edge.get("weight").is_none());
+        let names: Vec<&str> = edge.fields().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["from", "to", "dir"]);
+
+        edge.set("to", Value::Usize(2)).unwrap();
+        assert!(edge.set("to", Value::F64(2.5)).is_err());
+        assert!(edge.set("weight", Value::F64(1.0)).is_err());
+        let mut dir = edge.get("dir").unwrap().clone();
+        dir.set_variant(&schema, "Both", None).unwrap();
+        assert!(dir.set_variant(&schema, "Up", None).is_err());
+        assert!(dir.set_variant(&schema, "Left", Some(Value::Usize(0))).is_err());
+        edge.set("dir", dir).unwrap();
+        assert_eq!(edge.to_data(), parsing::parse_str(r#"[0, 2, "Both"]"#, &[]).unwrap());
+        assert!(value::read_type(&schema, &TypeRef::parse("(ty Edge)").unwrap(),
+            &edge.to_data()).is_ok());
+
+        let mut node = read(&root_self(), &parsing::parse_str(
+            "Instance {class: 0, data: Option::None}", &[]).unwrap()).unwrap();
+        assert_eq!(node.variant(), Some("Instance"));
+        assert_eq!(node.get("class").and_then(|v| v.as_usize()), Some(0));
+        let data = read(&root_self(), &parsing::parse_str("Bool(true)", &[]).unwrap()).unwrap();
+        assert_eq!(data.variant_data().and_then(|v| v.as_bool()), Some(true));
+        node.set("data", Value::Option(Some(Box::new(data)))).unwrap();
+        assert!(node.set("data", Value::Option(Some(Box::new(Value::F64(1.0))))).is_err());
+        assert!(node.get("data").unwrap().flatten().is_some());
+    }
+}
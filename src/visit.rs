@@ -0,0 +1,319 @@
+//! Traversal and rewriting of `Root` values.
+//!
+//! `Visitor` and `VisitorMut` traverse values by reference,
+//! while `Fold` consumes a value and builds a new one.
+//! Every variant has a method with a default that walks its children,
+//! e.g. `visit_struct` visits the name and the fields,
+//! such that a tool only overrides the cases it cares about:
+//!
+//! ```text
+//! struct Floats(usize);
+//!
+//! impl Visitor for Floats {
+//!     fn visit_f64(&mut self, _: f64) {self.0 += 1}
+//! }
+//! ```
+//!
+//! Call `visit` (or `fold`) to start a traversal.
+//! An override that needs to continue into children calls `walk` (or `walk_mut`, `fold_root`)
+//! or the methods for the children.
+
+use crate::*;
+
+/// Traverses a value by reference.
+pub trait Visitor {
+    /// Visits a value, dispatching to the method of its variant.
+    fn visit(&mut self, root: &Root) {walk(self, root)}
+    /// Visits a type.
+    fn visit_ty(&mut self, _ty: &Arc<String>) {}
+    /// Visits a string.
+    fn visit_str(&mut self, _s: &Arc<String>) {}
+    /// Visits a number.
+    fn visit_f64(&mut self, _x: f64) {}
+    /// Visits a boolean.
+    fn visit_bool(&mut self, _x: bool) {}
+    /// Visits an avatar, e.g. `Option<f64>`.
+    fn visit_avatar(&mut self, a: &Root, b: &Root) {self.visit(a); self.visit(b)}
+    /// Visits a tuple.
+    fn visit_tup(&mut self, items: &[Root]) {for it in items {self.visit(it)}}
+    /// Visits a field, e.g. `name: Arc<String>`.
+    fn visit_field(&mut self, key: &Root, value: &Root) {self.visit(key); self.visit(value)}
+    /// Visits generic parameters or arguments.
+    fn visit_params(&mut self, items: &[Root]) {for it in items {self.visit(it)}}
+    /// Visits a generic parameter with bounds, e.g. `.T: Ord`.
+    fn visit_bound(&mut self, param: &Root, bounds: &Root) {self.visit(param); self.visit(bounds)}
+    /// Visits a type with a default value.
+    fn visit_default(&mut self, ty: &Root, value: &Root) {self.visit(ty); self.visit(value)}
+    /// Visits an attribute with the value it applies to.
+    fn visit_attr(&mut self, attr: &Root, value: &Root) {self.visit(attr); self.visit(value)}
+    /// Visits a struct declaration.
+    fn visit_struct(&mut self, name: &Root, fields: &[Root]) {
+        self.visit(name);
+        for f in fields {self.visit(f)}
+    }
+    /// Visits an enum declaration.
+    fn visit_enum(&mut self, name: &Root, variants: &[Root]) {
+        self.visit(name);
+        for v in variants {self.visit(v)}
+    }
+    /// Visits an instance referring to a declaration by index.
+    fn visit_instance(&mut self, _class: usize, data: Option<&Root>) {
+        if let Some(data) = data {self.visit(data)}
+    }
+    /// Visits an instance with explicit type.
+    fn visit_instance_ty(&mut self, ty: &Root, data: Option<&Root>) {
+        self.visit(ty);
+        if let Some(data) = data {self.visit(data)}
+    }
+    /// Visits a reference to an instance.
+    fn visit_ref(&mut self, _ind: usize) {}
+}
+
+/// Calls the method of a visitor for the variant of a value.
+pub fn walk<V: Visitor + ?Sized>(v: &mut V, root: &Root) {
+    use Root::*;
+
+    match root {
+        Ty(ty) => v.visit_ty(ty),
+        Str(s) => v.visit_str(s),
+        F64(x) => v.visit_f64(*x),
+        Bool(x) => v.visit_bool(*x),
+        Avatar(ab) => v.visit_avatar(&ab.0, &ab.1),
+        Tup(items) => v.visit_tup(items),
+        Field(kv) => v.visit_field(&kv.0, &kv.1),
+        Params(items) => v.visit_params(items),
+        Bound(pb) => v.visit_bound(&pb.0, &pb.1),
+        Default(tv) => v.visit_default(&tv.0, &tv.1),
+        Attr(av) => v.visit_attr(&av.0, &av.1),
+        Struct {name, fields} => v.visit_struct(name, fields),
+        Enum {name, variants} => v.visit_enum(name, variants),
+        Instance {class, data} => v.visit_instance(*class, data.as_deref()),
+        InstanceTy {ty, data} => v.visit_instance_ty(ty, data.as_deref()),
+        Ref(ind) => v.visit_ref(*ind),
+    }
+}
+
+/// Traverses a value by mutable reference, e.g. to rename types in place.
+pub trait VisitorMut {
+    /// Visits a value, dispatching to the method of its variant.
+    fn visit_mut(&mut self, root: &mut Root) {walk_mut(self, root)}
+    /// Visits a type.
+    fn visit_ty_mut(&mut self, _ty: &mut Arc<String>) {}
+    /// Visits a string.
+    fn visit_str_mut(&mut self, _s: &mut Arc<String>) {}
+    /// Visits a number.
+    fn visit_f64_mut(&mut self, _x: &mut f64) {}
+    /// Visits a boolean.
+    fn visit_bool_mut(&mut self, _x: &mut bool) {}
+    /// Visits an avatar, e.g. `Option<f64>`.
+    fn visit_avatar_mut(&mut self, a: &mut Root, b: &mut Root) {self.visit_mut(a); self.visit_mut(b)}
+    /// Visits a tuple.
+    fn visit_tup_mut(&mut self, items: &mut Vec<Root>) {for it in items {self.visit_mut(it)}}
+    /// Visits a field, e.g. `name: Arc<String>`.
+    fn visit_field_mut(&mut self, key: &mut Root, value: &mut Root) {self.visit_mut(key); self.visit_mut(value)}
+    /// Visits generic parameters or arguments.
+    fn visit_params_mut(&mut self, items: &mut Vec<Root>) {for it in items {self.visit_mut(it)}}
+    /// Visits a generic parameter with bounds, e.g. `.T: Ord`.
+    fn visit_bound_mut(&mut self, param: &mut Root, bounds: &mut Root) {
+        self.visit_mut(param);
+        self.visit_mut(bounds)
+    }
+    /// Visits a type with a default value.
+    fn visit_default_mut(&mut self, ty: &mut Root, value: &mut Root) {self.visit_mut(ty); self.visit_mut(value)}
+    /// Visits an attribute with the value it applies to.
+    fn visit_attr_mut(&mut self, attr: &mut Root, value: &mut Root) {self.visit_mut(attr); self.visit_mut(value)}
+    /// Visits a struct declaration.
+    fn visit_struct_mut(&mut self, name: &mut Root, fields: &mut Vec<Root>) {
+        self.visit_mut(name);
+        for f in fields {self.visit_mut(f)}
+    }
+    /// Visits an enum declaration.
+    fn visit_enum_mut(&mut self, name: &mut Root, variants: &mut Vec<Root>) {
+        self.visit_mut(name);
+        for v in variants {self.visit_mut(v)}
+    }
+    /// Visits an instance referring to a declaration by index.
+    fn visit_instance_mut(&mut self, _class: &mut usize, data: Option<&mut Root>) {
+        if let Some(data) = data {self.visit_mut(data)}
+    }
+    /// Visits an instance with explicit type.
+    fn visit_instance_ty_mut(&mut self, ty: &mut Root, data: Option<&mut Root>) {
+        self.visit_mut(ty);
+        if let Some(data) = data {self.visit_mut(data)}
+    }
+    /// Visits a reference to an instance.
+    fn visit_ref_mut(&mut self, _ind: &mut usize) {}
+}
+
+/// Calls the method of a mutable visitor for the variant of a value.
+pub fn walk_mut<V: VisitorMut + ?Sized>(v: &mut V, root: &mut Root) {
+    use Root::*;
+
+    match root {
+        Ty(ty) => v.visit_ty_mut(ty),
+        Str(s) => v.visit_str_mut(s),
+        F64(x) => v.visit_f64_mut(x),
+        Bool(x) => v.visit_bool_mut(x),
+        Avatar(ab) => v.visit_avatar_mut(&mut ab.0, &mut ab.1),
+        Tup(items) => v.visit_tup_mut(items),
+        Field(kv) => v.visit_field_mut(&mut kv.0, &mut kv.1),
+        Params(items) => v.visit_params_mut(items),
+        Bound(pb) => v.visit_bound_mut(&mut pb.0, &mut pb.1),
+        Default(tv) => v.visit_default_mut(&mut tv.0, &mut tv.1),
+        Attr(av) => v.visit_attr_mut(&mut av.0, &mut av.1),
+        Struct {name, fields} => v.visit_struct_mut(name, fields),
+        Enum {name, variants} => v.visit_enum_mut(name, variants),
+        Instance {class, data} => v.visit_instance_mut(class, data.as_deref_mut()),
+        InstanceTy {ty, data} => v.visit_instance_ty_mut(ty, data.as_deref_mut()),
+        Ref(ind) => v.visit_ref_mut(ind),
+    }
+}
+
+/// Transforms a value into a new value.
+///
+/// The defaults rebuild the same variant from folded children,
+/// such that a fold without overrides returns an equal value.
+pub trait Fold {
+    /// Folds a value, dispatching to the method of its variant.
+    fn fold(&mut self, root: Root) -> Root {fold_root(self, root)}
+    /// Folds a type.
+    fn fold_ty(&mut self, ty: Arc<String>) -> Root {Root::Ty(ty)}
+    /// Folds a string.
+    fn fold_str(&mut self, s: Arc<String>) -> Root {Root::Str(s)}
+    /// Folds a number.
+    fn fold_f64(&mut self, x: f64) -> Root {Root::F64(x)}
+    /// Folds a boolean.
+    fn fold_bool(&mut self, x: bool) -> Root {Root::Bool(x)}
+    /// Folds an avatar, e.g. `Option<f64>`.
+    fn fold_avatar(&mut self, a: Root, b: Root) -> Root {
+        Root::Avatar(Box::new((self.fold(a), self.fold(b))))
+    }
+    /// Folds a tuple.
+    fn fold_tup(&mut self, items: Vec<Root>) -> Root {
+        Root::Tup(items.into_iter().map(|it| self.fold(it)).collect())
+    }
+    /// Folds a field, e.g. `name: Arc<String>`.
+    fn fold_field(&mut self, key: Root, value: Root) -> Root {
+        Root::Field(Box::new((self.fold(key), self.fold(value))))
+    }
+    /// Folds generic parameters or arguments.
+    fn fold_params(&mut self, items: Vec<Root>) -> Root {
+        Root::Params(items.into_iter().map(|it| self.fold(it)).collect())
+    }
+    /// Folds a generic parameter with bounds, e.g. `.T: Ord`.
+    fn fold_bound(&mut self, param: Root, bounds: Root) -> Root {
+        Root::Bound(Box::new((self.fold(param), self.fold(bounds))))
+    }
+    /// Folds a type with a default value.
+    fn fold_default(&mut self, ty: Root, value: Root) -> Root {
+        Root::Default(Box::new((self.fold(ty), self.fold(value))))
+    }
+    /// Folds an attribute with the value it applies to.
+    fn fold_attr(&mut self, attr: Root, value: Root) -> Root {
+        Root::Attr(Box::new((self.fold(attr), self.fold(value))))
+    }
+    /// Folds a struct declaration.
+    fn fold_struct(&mut self, name: Root, fields: Vec<Root>) -> Root {
+        Root::Struct {
+            name: Box::new(self.fold(name)),
+            fields: fields.into_iter().map(|f| self.fold(f)).collect(),
+        }
+    }
+    /// Folds an enum declaration.
+    fn fold_enum(&mut self, name: Root, variants: Vec<Root>) -> Root {
+        Root::Enum {
+            name: Box::new(self.fold(name)),
+            variants: variants.into_iter().map(|v| self.fold(v)).collect(),
+        }
+    }
+    /// Folds an instance referring to a declaration by index.
+    fn fold_instance(&mut self, class: usize, data: Option<Root>) -> Root {
+        Root::Instance {class, data: data.map(|d| Box::new(self.fold(d)))}
+    }
+    /// Folds an instance with explicit type.
+    fn fold_instance_ty(&mut self, ty: Root, data: Option<Root>) -> Root {
+        Root::InstanceTy {ty: Box::new(self.fold(ty)), data: data.map(|d| Box::new(self.fold(d)))}
+    }
+    /// Folds a reference to an instance.
+    fn fold_ref(&mut self, ind: usize) -> Root {Root::Ref(ind)}
+}
+
+/// Calls the method of a fold for the variant of a value.
+pub fn fold_root<F: Fold + ?Sized>(f: &mut F, root: Root) -> Root {
+    use Root::*;
+
+    match root {
+        Ty(ty) => f.fold_ty(ty),
+        Str(s) => f.fold_str(s),
+        F64(x) => f.fold_f64(x),
+        Bool(x) => f.fold_bool(x),
+        Avatar(ab) => {let (a, b) = *ab; f.fold_avatar(a, b)}
+        Tup(items) => f.fold_tup(items),
+        Field(kv) => {let (k, v) = *kv; f.fold_field(k, v)}
+        Params(items) => f.fold_params(items),
+        Bound(pb) => {let (p, b) = *pb; f.fold_bound(p, b)}
+        Default(tv) => {let (t, v) = *tv; f.fold_default(t, v)}
+        Attr(av) => {let (a, v) = *av; f.fold_attr(a, v)}
+        Struct {name, fields} => f.fold_struct(*name, fields),
+        Enum {name, variants} => f.fold_enum(*name, variants),
+        Instance {class, data} => f.fold_instance(class, data.map(|d| *d)),
+        InstanceTy {ty, data} => f.fold_instance_ty(*ty, data.map(|d| *d)),
+        Ref(ind) => f.fold_ref(ind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visitor() {
+        struct Names(Vec<String>);
+
+        impl Visitor for Names {
+            fn visit_struct(&mut self, name: &Root, fields: &[Root]) {
+                self.0.push(format!("{}", name));
+                for f in fields {self.visit(f)}
+            }
+            fn visit_field(&mut self, key: &Root, _: &Root) {self.0.push(format!("{}", key))}
+        }
+
+        let doc = parsing::parse_str(include_str!("../source/test/person/person-with-instance.txt"), &[]).unwrap();
+        let mut names = Names(vec![]);
+        names.visit(&doc);
+        assert_eq!(names.0, vec!["\"Person\"", "\"first_name\"", "\"last_name\""]);
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        struct Rename;
+
+        impl VisitorMut for Rename {
+            fn visit_ty_mut(&mut self, ty: &mut Arc<String>) {
+                if **ty == "Node" {*ty = Arc::new("Vertex".into())}
+            }
+            fn visit_ref_mut(&mut self, ind: &mut usize) {*ind += 1}
+        }
+
+        let mut doc = parsing::parse_str("[struct Edge {from: Ref<(ty Node)>}, (ins 0 [(ref 3)])]", &[]).unwrap();
+        Rename.visit_mut(&mut doc);
+        assert_eq!(doc, parsing::parse_str("[struct Edge {from: Ref<(ty Vertex)>}, (ins 0 [(ref 4)])]", &[]).unwrap());
+    }
+
+    #[test]
+    fn test_fold() {
+        struct Identity;
+        impl Fold for Identity {}
+
+        assert_eq!(Identity.fold(root_self()), root_self());
+
+        struct Round;
+        impl Fold for Round {
+            fn fold_f64(&mut self, x: f64) -> Root {Root::F64(x.round())}
+            fn fold_instance(&mut self, class: usize, _: Option<Root>) -> Root {Root::Instance {class, data: None}}
+        }
+
+        let doc = parsing::parse_str("[1.4, (2.6, \"a\"), (ins 0 [3.3])]", &[]).unwrap();
+        assert_eq!(Round.fold(doc), parsing::parse_str("[1, (3, \"a\"), (ins 0)]", &[]).unwrap());
+    }
+}